@@ -1,3 +1,120 @@
+/// Every command the app registers. Each gets `allow-` and `deny-` permissions, so a window
+/// can only call the commands its capability grants; see `capabilities/`
+const COMMANDS: &[&str] = &[
+    "parse_moon_data",
+    "parse_moon_data_lenient",
+    "add_moon",
+    "delete_moon",
+    "get_moons",
+    "set_moon_notes",
+    "export_moonpack",
+    "import_moonpack",
+    "get_sync_config",
+    "set_sync_config",
+    "sync_moons",
+    "get_unique_materials",
+    "search_everything",
+    "search_reactions",
+    "analyze_reactions",
+    "cancel_analysis",
+    "get_analysis_page",
+    "get_last_analysis",
+    "export_analysis",
+    "get_reaction_detail",
+    "build_tree",
+    "export_tree_dot",
+    "export_tree_text",
+    "recompute_tree",
+    "simulate_prices",
+    "list_analyses",
+    "get_analysis",
+    "list_price_snapshots",
+    "load_price_snapshot",
+    "compare_analyses",
+    "rank_moons",
+    "score_moons",
+    "explain_calculation",
+    "get_input_sensitivity",
+    "get_margin_alerts",
+    "set_margin_alert",
+    "remove_margin_alert",
+    "set_margin_alert_schedule",
+    "compare_bonus_scenarios",
+    "compare_structures",
+    "get_moon_structures",
+    "set_moon_structure",
+    "remove_moon_structure",
+    "get_max_buy_prices",
+    "get_shopping_list",
+    "get_bill_of_materials",
+    "get_run_summary",
+    "schedule_reaction_jobs",
+    "open_detail_window",
+    "get_detail_view",
+    "set_detail_target",
+    "export_multibuy",
+    "create_appraisal_link",
+    "plan_runs",
+    "plan_steady_state",
+    "appraise_paste",
+    "appraise_items",
+    "import_mining_ledger",
+    "get_mined_amounts",
+    "clear_mining_ledger",
+    "apply_ledger_extractions",
+    "set_extracted_units",
+    "calculate_extraction",
+    "compare_moon_sales",
+    "list_profiles",
+    "create_profile",
+    "delete_profile",
+    "switch_profile",
+    "purge_local_data",
+    "backup_data",
+    "restore_data",
+    "schedule_extraction",
+    "remove_extraction",
+    "get_upcoming_extractions",
+    "get_extractions_by_structure",
+    "export_extraction_calendar",
+    "get_inventory",
+    "set_stock",
+    "import_inventory",
+    "get_stockpile_valuation",
+    "value_inventory",
+    "get_staleness_thresholds",
+    "set_staleness_thresholds",
+    "get_memory_mode",
+    "set_memory_mode",
+    "get_settings",
+    "update_settings",
+    "get_telemetry_consent",
+    "set_telemetry_consent",
+    "get_telemetry_payload",
+    "get_esi_auth",
+    "begin_esi_login",
+    "complete_esi_login",
+    "esi_logout",
+    "get_dataset_info",
+    "update_datasets",
+    "reload_databases",
+    "get_recent_logs",
+    "check_for_updates",
+    "get_announcements",
+    "mark_announcement_read",
+    "pin_price",
+    "unpin_price",
+    "get_price_pins",
+    "get_weekly_report_config",
+    "set_weekly_report_config",
+    "run_weekly_report",
+    "generate_report",
+];
+
 fn main() {
-    tauri_build::build()
+    tauri_build::try_build(
+        tauri_build::Attributes::new()
+            .app_manifest(tauri_build::AppManifest::new().commands(COMMANDS)),
+    )
+    .expect("failed to run tauri-build");
 }
@@ -0,0 +1,176 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::storage;
+
+const ALERTS_FILE: &str = "margin_alerts.json";
+
+/// Event emitted with the alerts that fired
+pub const MARGIN_ALERT_EVENT: &str = "prices://margin-alert";
+
+/// Shortest time between re-pricing the watched reactions
+const MIN_CHECK_INTERVAL_MINS: u64 = 5;
+/// Longest time between re-pricing the watched reactions, a week
+const MAX_CHECK_INTERVAL_MINS: u64 = 7 * 24 * 60;
+
+/// Held while the alerts file is read, changed and written back, so a background check
+/// can't overwrite an alert the user set in the meantime
+static ALERTS_LOCK: Mutex<()> = Mutex::new(());
+
+/// Which side of the threshold raises the alert
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Crossing {
+    #[default]
+    Above,
+    Below,
+}
+
+/// A reaction whose margin is watched
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarginAlert {
+    pub formula_id: u32,
+    pub formula_name: String,
+    /// Margin in percent that raises the alert
+    pub threshold: f64,
+    pub crossing: Crossing,
+    /// Margin at the last check
+    #[serde(default)]
+    pub last_margin: Option<f64>,
+    /// Whether the margin was past the threshold at the last check, so each crossing
+    /// alerts once
+    #[serde(default)]
+    pub triggered: bool,
+}
+
+impl MarginAlert {
+    fn is_past(&self, margin: f64) -> bool {
+        match self.crossing {
+            Crossing::Above => margin >= self.threshold,
+            Crossing::Below => margin <= self.threshold,
+        }
+    }
+}
+
+/// An alert that fired, with the margin that crossed the threshold
+#[derive(Debug, Clone, Serialize)]
+pub struct FiredAlert {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub threshold: f64,
+    pub crossing: Crossing,
+    pub margin: f64,
+}
+
+/// The watched reactions and how often they are re-priced
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarginAlerts {
+    pub enabled: bool,
+    pub interval_mins: u64,
+    pub alerts: Vec<MarginAlert>,
+    /// Unix timestamp (seconds) of the last check
+    pub last_checked_at: Option<u64>,
+}
+
+impl Default for MarginAlerts {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval_mins: 30,
+            alerts: Vec::new(),
+            last_checked_at: None,
+        }
+    }
+}
+
+impl MarginAlerts {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(ALERTS_FILE)).unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(ALERTS_FILE), self)
+    }
+
+    /// Hold off alert changes until the guard is dropped, e.g. while the active profile
+    /// changes under them
+    pub fn lock() -> MutexGuard<'static, ()> {
+        ALERTS_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Load the alerts, change them and save them, with no other change in between
+    pub fn update<T>(change: impl FnOnce(&mut Self) -> Result<T, String>) -> Result<T, String> {
+        let _guard = Self::lock();
+        let mut alerts = Self::load();
+        let result = change(&mut alerts)?;
+        alerts.save()?;
+        Ok(result)
+    }
+
+    /// Watch a reaction, replacing its previous alert
+    pub fn set(&mut self, alert: MarginAlert) -> Result<(), String> {
+        if !alert.threshold.is_finite() {
+            return Err("Margin threshold must be a number".to_string());
+        }
+        self.remove(alert.formula_id);
+        self.alerts.push(MarginAlert {
+            last_margin: None,
+            triggered: false,
+            ..alert
+        });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, formula_id: u32) {
+        self.alerts.retain(|a| a.formula_id != formula_id);
+    }
+
+    pub fn set_interval(&mut self, interval_mins: u64) -> Result<(), String> {
+        if interval_mins < MIN_CHECK_INTERVAL_MINS {
+            return Err(format!(
+                "Check at most every {} minutes",
+                MIN_CHECK_INTERVAL_MINS
+            ));
+        }
+        if interval_mins > MAX_CHECK_INTERVAL_MINS {
+            return Err("Check at least once a week".to_string());
+        }
+        self.interval_mins = interval_mins;
+        Ok(())
+    }
+
+    /// Whether the watched reactions should be re-priced
+    pub fn is_due(&self, now: u64) -> bool {
+        self.enabled
+            && !self.alerts.is_empty()
+            && self.last_checked_at.is_none_or(|last| {
+                now.saturating_sub(last) >= self.interval_mins.saturating_mul(60)
+            })
+    }
+
+    /// Record fresh margins, by formula ID, and return the alerts whose margin crossed
+    /// the threshold since the last check. Reactions without a margin are skipped
+    pub fn record(&mut self, now: u64, margin_of: impl Fn(u32) -> Option<f64>) -> Vec<FiredAlert> {
+        self.last_checked_at = Some(now);
+        let mut fired = Vec::new();
+        for alert in &mut self.alerts {
+            let Some(margin) = margin_of(alert.formula_id) else {
+                continue;
+            };
+            let past = alert.is_past(margin);
+            if past && !alert.triggered {
+                fired.push(FiredAlert {
+                    formula_id: alert.formula_id,
+                    formula_name: alert.formula_name.clone(),
+                    threshold: alert.threshold,
+                    crossing: alert.crossing,
+                    margin,
+                });
+            }
+            alert.triggered = past;
+            alert.last_margin = Some(margin);
+        }
+        fired
+    }
+}
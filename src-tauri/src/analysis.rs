@@ -0,0 +1,421 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::fees::SaleOptions;
+use crate::prices::{self, ReactionProfit};
+use crate::reaction_tree::{self, TreeContext};
+use crate::reactions::{Reaction, ReactionCategory, ReactionDatabase};
+
+/// Event emitted while an analysis runs
+pub const PROGRESS_EVENT: &str = "analysis://progress";
+
+/// Stages of an analysis, in the order they run
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisStage {
+    CollectingGoo,
+    FetchingPrices,
+    ComputingProfits,
+    Done,
+}
+
+impl AnalysisStage {
+    /// Overall percentage at which this stage starts and ends
+    fn span(self) -> (f64, f64) {
+        match self {
+            AnalysisStage::CollectingGoo => (0.0, 5.0),
+            AnalysisStage::FetchingPrices => (5.0, 50.0),
+            AnalysisStage::ComputingProfits => (50.0, 100.0),
+            AnalysisStage::Done => (100.0, 100.0),
+        }
+    }
+}
+
+/// Payload of a progress event
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisProgress {
+    pub stage: AnalysisStage,
+    /// Overall completion, 0 to 100
+    pub percent: f64,
+}
+
+impl AnalysisProgress {
+    /// Progress `done` of `total` of the way through a stage
+    pub fn new(stage: AnalysisStage, done: usize, total: usize) -> Self {
+        let (start, end) = stage.span();
+        let fraction = if total == 0 {
+            1.0
+        } else {
+            done as f64 / total as f64
+        };
+        Self {
+            stage,
+            percent: start + (end - start) * fraction.min(1.0),
+        }
+    }
+}
+
+/// Error returned when an analysis is cancelled part way
+pub const CANCELLED: &str = "Analysis cancelled";
+
+/// Flag for stopping a running analysis between stages
+#[derive(Debug, Default)]
+pub struct CancelToken(AtomicBool);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+
+    /// Clear a previous cancellation before starting a new analysis
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
+
+    /// Err if cancellation was requested
+    pub fn check(&self) -> Result<(), String> {
+        if self.0.load(Ordering::SeqCst) {
+            Err(CANCELLED.to_string())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Optional narrowing of an analysis, applied before trees are built
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalysisFilters {
+    pub min_profit: Option<f64>,
+    /// Minimum margin, in percent
+    pub min_margin: Option<f64>,
+    /// Categories to include; empty means all
+    pub categories: Vec<ReactionCategory>,
+    /// Only reactions that take at least one input straight from the user's moons
+    pub only_direct_moon_inputs: bool,
+    /// Formula IDs to leave out
+    pub exclude_reactions: HashSet<u32>,
+    /// Skip reactions consuming any of these items
+    pub exclude_inputs: HashSet<String>,
+    /// Only reactions the extracted goo is enough for, rather than any that use the moons'
+    /// goo at all
+    pub limit_to_extracted_goo: bool,
+}
+
+impl AnalysisFilters {
+    /// Whether a reaction passes the filters that don't depend on prices
+    fn allows_reaction(&self, reaction: &Reaction, category: Option<ReactionCategory>) -> bool {
+        !self.exclude_reactions.contains(&reaction.formula_id)
+            && !reaction
+                .inputs
+                .iter()
+                .any(|input| self.exclude_inputs.contains(&input.name))
+            && (self.categories.is_empty()
+                || category.is_some_and(|c| self.categories.contains(&c)))
+    }
+
+    /// Whether a priced reaction passes the profit filters
+    fn allows_profit(&self, profit: &ReactionProfit) -> bool {
+        self.min_profit.is_none_or(|min| profit.profit >= min)
+            && self.min_margin.is_none_or(|min| profit.margin >= min)
+            && (!self.only_direct_moon_inputs || profit.uses_user_materials)
+    }
+}
+
+/// What analysis results can be ordered by
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SortKey {
+    #[default]
+    Margin,
+    Profit,
+    IskPerHour,
+    IskPerM3,
+    IskPerDay,
+    OutputValue,
+    InputCost,
+    CashProfit,
+    CashMargin,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+/// Order for analysis results; highest margin first by default
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(default)]
+pub struct ResultSort {
+    pub key: SortKey,
+    pub direction: SortDirection,
+}
+
+/// Sort results in place
+pub fn sort_results(results: &mut [ReactionProfit], sort: ResultSort, db: &ReactionDatabase) {
+    let value = |result: &ReactionProfit| match sort.key {
+        SortKey::Margin => result.margin,
+        SortKey::Profit => result.profit,
+        SortKey::IskPerHour => {
+            let run_hours = db
+                .by_output
+                .get(&result.output_id)
+                .map(|r| r.run_seconds as f64 / 3600.0)
+                .unwrap_or(0.0);
+            if run_hours > 0.0 {
+                result.profit / run_hours
+            } else {
+                0.0
+            }
+        }
+        SortKey::IskPerM3 => result.isk_per_m3,
+        SortKey::IskPerDay => result.isk_per_day,
+        SortKey::OutputValue => result.output_value,
+        SortKey::InputCost => result.input_cost,
+        SortKey::CashProfit => result.cash_profit,
+        SortKey::CashMargin => result.cash_margin,
+    };
+
+    results.sort_by(|a, b| {
+        let order = value(a)
+            .partial_cmp(&value(b))
+            .unwrap_or(std::cmp::Ordering::Equal);
+        match sort.direction {
+            SortDirection::Asc => order,
+            SortDirection::Desc => order.reverse(),
+        }
+    });
+}
+
+/// One page of results, with the total available
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub offset: usize,
+    pub total: usize,
+    /// Every result's group, when grouping was asked for
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<ResultGroup>,
+}
+
+/// Take the page of `limit` results (all when None) starting at `offset`
+pub fn paginate(
+    results: &[ReactionProfit],
+    offset: usize,
+    limit: Option<usize>,
+) -> Page<ReactionProfit> {
+    let items = results
+        .iter()
+        .skip(offset)
+        .take(limit.unwrap_or(usize::MAX))
+        .cloned()
+        .collect();
+
+    Page {
+        items,
+        offset,
+        total: results.len(),
+        groups: Vec::new(),
+    }
+}
+
+/// How analysis results are grouped into product families
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultGrouping {
+    /// By reaction category, e.g. all advanced composites
+    Category,
+    /// By output family, e.g. all metamaterials or all carbides; see `output_family`
+    Output,
+}
+
+/// Family of a reaction output: the last word of its name, singular, so Fermionic and
+/// Fluxed Condensates or Carbon and Reinforced Carbon Fiber share a family. Unrefined
+/// outputs fall in with the product they stand in for
+fn output_family(output_name: &str) -> String {
+    let last = output_name.split_whitespace().last().unwrap_or(output_name);
+    match last.strip_suffix('s') {
+        Some(singular) if !singular.is_empty() && !singular.ends_with('s') => singular,
+        _ => last,
+    }
+    .to_string()
+}
+
+/// Results sharing a category or output family, with their combined figures
+#[derive(Debug, Clone, Serialize)]
+pub struct ResultGroup {
+    pub name: String,
+    /// Members in result order
+    pub formula_ids: Vec<u32>,
+    pub total_profit: f64,
+    pub best_profit: f64,
+    pub average_margin: f64,
+}
+
+/// Group results, keeping their order within each group; groups come in the order of
+/// their first result
+pub fn group_results(
+    results: &[ReactionProfit],
+    grouping: ResultGrouping,
+    db: &ReactionDatabase,
+) -> Vec<ResultGroup> {
+    let mut groups: Vec<ResultGroup> = Vec::new();
+    let mut margins: Vec<f64> = Vec::new();
+    for result in results {
+        let name = match grouping {
+            ResultGrouping::Category => db
+                .category_of(result.formula_id)
+                .map_or("Uncategorized", ReactionCategory::label)
+                .to_string(),
+            ResultGrouping::Output => output_family(&result.output_name),
+        };
+        let index = match groups.iter().position(|g| g.name == name) {
+            Some(index) => index,
+            None => {
+                groups.push(ResultGroup {
+                    name,
+                    formula_ids: Vec::new(),
+                    total_profit: 0.0,
+                    best_profit: f64::MIN,
+                    average_margin: 0.0,
+                });
+                margins.push(0.0);
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[index];
+        group.formula_ids.push(result.formula_id);
+        group.total_profit += result.profit;
+        group.best_profit = group.best_profit.max(result.profit);
+        margins[index] += result.margin;
+    }
+
+    for (group, margin_sum) in groups.iter_mut().zip(margins) {
+        group.average_margin = margin_sum / group.formula_ids.len() as f64;
+    }
+    groups
+}
+
+/// Calculate profit for every reaction that uses the user's moon materials and keep the
+/// profitable ones. Trees are left out; build them per reaction when they're needed
+pub fn profitable_reactions(
+    ctx: &TreeContext,
+    sale_options: &SaleOptions,
+    filters: &AnalysisFilters,
+    cancel: &CancelToken,
+    mut on_progress: impl FnMut(AnalysisProgress),
+) -> Result<Vec<ReactionProfit>, String> {
+    cancel.check()?;
+    on_progress(AnalysisProgress::new(AnalysisStage::ComputingProfits, 0, 1));
+
+    // Calculate profit for each reaction (inputs priced at sell value = opportunity cost)
+    let profits: Vec<ReactionProfit> = ctx
+        .reactions_db
+        .reactions
+        .iter()
+        .filter(|r| filters.allows_reaction(r, ctx.reactions_db.category_of(r.formula_id)))
+        .filter(|r| {
+            reaction_tree::reaction_uses_user_materials(r, ctx.reactions_db, ctx.user_moon_goo_ids)
+        })
+        .filter_map(|r| {
+            prices::calculate_reaction_profit(r, ctx.prices, ctx.user_moon_goo_ids, sale_options)
+        })
+        .filter(|p| p.profit > 0.0 && filters.allows_profit(p))
+        .collect();
+
+    on_progress(AnalysisProgress::new(AnalysisStage::Done, 1, 1));
+
+    Ok(profits)
+}
+
+/// Moon goo one run consumes through the whole chain, by item ID. Intermediates that can
+/// be made from the user's goo count as made, taking the share of a run they need; others
+/// are bought
+pub fn moon_goo_per_run(
+    reaction: &Reaction,
+    db: &ReactionDatabase,
+    user_moon_goo_ids: &HashSet<u32>,
+) -> HashMap<u32, f64> {
+    let mut needed = HashMap::new();
+    add_moon_goo(reaction, 1.0, db, user_moon_goo_ids, &mut needed, 0);
+    needed
+}
+
+fn add_moon_goo(
+    reaction: &Reaction,
+    runs: f64,
+    db: &ReactionDatabase,
+    user_moon_goo_ids: &HashSet<u32>,
+    needed: &mut HashMap<u32, f64>,
+    depth: usize,
+) {
+    // Reaction data with a cycle would recurse forever
+    if depth > db.reactions.len() {
+        return;
+    }
+    for input in &reaction.inputs {
+        let quantity = input.quantity as f64 * runs;
+        if user_moon_goo_ids.contains(&input.id) {
+            *needed.entry(input.id).or_insert(0.0) += quantity;
+        } else if let Some(intermediate) = db.by_output.get(&input.id).filter(|r| {
+            reaction_tree::traces_to_moon_materials(
+                r.output.id,
+                db,
+                user_moon_goo_ids,
+                &mut HashSet::new(),
+            )
+        }) {
+            let runs = quantity / intermediate.output.quantity.max(1) as f64;
+            add_moon_goo(intermediate, runs, db, user_moon_goo_ids, needed, depth + 1);
+        }
+    }
+}
+
+/// Work out how many runs the available goo (units by item ID) is enough for and drop the
+/// reactions it can't cover once
+pub fn limit_to_available_goo(
+    results: &mut Vec<ReactionProfit>,
+    db: &ReactionDatabase,
+    user_moon_goo_ids: &HashSet<u32>,
+    available: &HashMap<u32, f64>,
+) {
+    for result in results.iter_mut() {
+        let Some(reaction) = db.by_output.get(&result.output_id) else {
+            continue;
+        };
+        let runs = moon_goo_per_run(reaction, db, user_moon_goo_ids)
+            .iter()
+            .filter(|(_, per_run)| **per_run > 0.0)
+            .map(|(id, per_run)| available.get(id).copied().unwrap_or(0.0) / per_run)
+            .fold(f64::INFINITY, f64::min);
+        result.available_runs = runs.is_finite().then(|| runs.floor() as u32);
+    }
+    results.retain(|result| result.available_runs.is_none_or(|runs| runs > 0));
+}
+
+/// Calculate profit and the production tree for a single reaction, profitable or not
+pub fn reaction_detail(
+    reaction: &Reaction,
+    ctx: &TreeContext,
+    sale_options: &SaleOptions,
+) -> Result<ReactionProfit, String> {
+    let mut profit = prices::calculate_reaction_profit(
+        reaction,
+        ctx.prices,
+        ctx.user_moon_goo_ids,
+        sale_options,
+    )
+    .ok_or_else(|| format!("No prices available for {}", reaction.formula_name))?;
+    profit.reaction_tree = Some(reaction_tree::build_full_reaction_tree(
+        &profit.output_name,
+        profit.output_id,
+        profit.output_quantity,
+        1,
+        ctx,
+    )?);
+    Ok(profit)
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::prices::now_unix;
+use crate::storage;
+
+const FEED_URL: &str = "https://moon.illuminatedcorp.com/announcements.json";
+const CACHE_FILE: &str = "announcements.json";
+
+/// Held while the cache file is read, changed and written back, so a feed refresh can't
+/// overwrite an entry the user marked read in the meantime
+static CACHE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AnnouncementKind {
+    Release,
+    DataUpdate,
+    MarketEvent,
+    #[serde(other)]
+    Other,
+}
+
+/// One entry of the announcement feed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Announcement {
+    pub id: String,
+    pub kind: AnnouncementKind,
+    pub title: String,
+    pub body: String,
+    /// Unix timestamp (seconds)
+    pub published_at: u64,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+/// An announcement with whether the user has seen it
+#[derive(Debug, Clone, Serialize)]
+pub struct AnnouncementEntry {
+    #[serde(flatten)]
+    pub announcement: Announcement,
+    pub read: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Feed {
+    announcements: Vec<Announcement>,
+}
+
+/// Last fetched feed and which entries have been read
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AnnouncementCache {
+    fetched_at: u64,
+    announcements: Vec<Announcement>,
+    read: HashSet<String>,
+}
+
+impl AnnouncementCache {
+    pub fn load() -> Self {
+        storage::load_json(CACHE_FILE).unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        storage::save_json(CACHE_FILE, self)
+    }
+
+    /// Load the cache, change it and save it, with no other change in between
+    fn update<T>(change: impl FnOnce(&mut Self) -> T) -> Result<T, String> {
+        let _guard = CACHE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut cache = Self::load();
+        let result = change(&mut cache);
+        cache.save()?;
+        Ok(result)
+    }
+
+    /// Announcements newest first, refreshing the feed if the cached copy is older than
+    /// `ttl_secs`; keeps the cached copy if the fetch fails. The fetched feed is merged
+    /// into the cache as it is then, so entries marked read during the fetch stay read
+    pub async fn refreshed_entries(
+        force: bool,
+        ttl_secs: u64,
+    ) -> Result<Vec<AnnouncementEntry>, String> {
+        let cache = Self::load();
+        if !force && now_unix().saturating_sub(cache.fetched_at) < ttl_secs {
+            return Ok(cache.entries());
+        }
+        match fetch_feed().await {
+            Ok(announcements) => Self::update(|cache| {
+                cache.replace_feed(announcements);
+                cache.entries()
+            }),
+            Err(_) => Ok(cache.entries()),
+        }
+    }
+
+    fn replace_feed(&mut self, announcements: Vec<Announcement>) {
+        // Forget read state for entries that dropped off the feed
+        let ids: HashSet<&String> = announcements.iter().map(|a| &a.id).collect();
+        self.read.retain(|id| ids.contains(id));
+        self.announcements = announcements;
+        self.fetched_at = now_unix();
+    }
+
+    pub fn mark_read(id: &str) -> Result<(), String> {
+        Self::update(|cache| {
+            cache.read.insert(id.to_string());
+        })
+    }
+
+    /// Announcements newest first, with read state
+    pub fn entries(&self) -> Vec<AnnouncementEntry> {
+        let mut entries: Vec<AnnouncementEntry> = self
+            .announcements
+            .iter()
+            .map(|announcement| AnnouncementEntry {
+                read: self.read.contains(&announcement.id),
+                announcement: announcement.clone(),
+            })
+            .collect();
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.announcement.published_at));
+        entries
+    }
+}
+
+async fn fetch_feed() -> Result<Vec<Announcement>, String> {
+    let response = reqwest::Client::new()
+        .get(FEED_URL)
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch announcements: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Announcement feed returned status: {}",
+            response.status()
+        ));
+    }
+
+    let feed: Feed = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse announcements: {}", e))?;
+    Ok(feed.announcements)
+}
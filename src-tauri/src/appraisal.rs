@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::prices::{MarketHub, PriceInfo};
+
+const GOONPRAISAL_URL: &str = "https://appraise.gnf.lt";
+const JANICE_URL: &str = "https://janice.e-351.com";
+/// Janice's market ID for Jita 4-4
+const JANICE_JITA_MARKET: &str = "2";
+
+/// Site that hosts shareable appraisals
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AppraisalService {
+    #[default]
+    Goonpraisal,
+    Janice,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoonpraisalResponse {
+    appraisal: GoonpraisalAppraisal,
+}
+
+#[derive(Debug, Deserialize)]
+struct GoonpraisalAppraisal {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct JaniceResponse {
+    code: String,
+}
+
+/// One priced line of an appraisal
+#[derive(Debug, Clone, Serialize)]
+pub struct AppraisedItem {
+    pub name: String,
+    pub quantity: u64,
+    pub buy_unit: f64,
+    pub sell_unit: f64,
+    pub buy_total: f64,
+    pub sell_total: f64,
+    /// Whether the item is moon goo from the user's moons or a reaction product that can
+    /// be made from it
+    pub producible: bool,
+}
+
+/// Buy/sell totals for a pasted item list
+#[derive(Debug, Clone, Serialize)]
+pub struct Appraisal {
+    pub items: Vec<AppraisedItem>,
+    /// Names that could not be priced
+    pub unknown: Vec<String>,
+    pub buy_total: f64,
+    pub sell_total: f64,
+    /// Sell value of the items the user can produce themselves
+    pub producible_sell_total: f64,
+}
+
+/// Save an item list ("Name quantity" per line) as a persistent appraisal and return its
+/// shareable URL. Janice needs an API key and prices at Jita
+pub async fn share_link(
+    items: &str,
+    service: AppraisalService,
+    market: MarketHub,
+    janice_api_key: Option<&str>,
+) -> Result<String, String> {
+    let client = reqwest::Client::new();
+    let request = match service {
+        AppraisalService::Goonpraisal => client
+            .post(format!("{}/appraisal.json", GOONPRAISAL_URL))
+            .form(&[
+                ("market", market.market_param()),
+                ("raw_textarea", items),
+                ("persist", "yes"),
+            ]),
+        AppraisalService::Janice => {
+            let key = janice_api_key
+                .filter(|key| !key.trim().is_empty())
+                .ok_or("Add a Janice API key in settings first")?;
+            client
+                .post(format!("{}/api/rest/v2/appraisal", JANICE_URL))
+                .query(&[
+                    ("market", JANICE_JITA_MARKET),
+                    ("designation", "appraisal"),
+                    ("pricing", "split"),
+                    ("persist", "true"),
+                ])
+                .header("X-ApiKey", key.trim())
+                .header("Content-Type", "text/plain")
+                .body(items.to_string())
+        }
+    };
+
+    let response = request
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to create appraisal: {}", e))?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Appraisal site returned status: {}",
+            response.status()
+        ));
+    }
+
+    match service {
+        AppraisalService::Goonpraisal => {
+            let body: GoonpraisalResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse appraisal: {}", e))?;
+            Ok(format!("{}/a/{}", GOONPRAISAL_URL, body.appraisal.id))
+        }
+        AppraisalService::Janice => {
+            let body: JaniceResponse = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse appraisal: {}", e))?;
+            Ok(format!("{}/a/{}", JANICE_URL, body.code))
+        }
+    }
+}
+
+/// Parse an item list paste into (name, quantity) pairs, merging duplicates.
+///
+/// Accepts the common EVE formats: "Name<TAB>qty<TAB>...", "Name qty", "qty Name",
+/// "qty x Name", "Name x qty" and bare names (quantity 1).
+pub fn parse_item_list(input: &str) -> Vec<(String, u64)> {
+    let mut order: Vec<String> = Vec::new();
+    let mut quantities: HashMap<String, u64> = HashMap::new();
+
+    for line in input.lines() {
+        let Some((name, quantity)) = parse_item_line(line) else {
+            continue;
+        };
+        if !quantities.contains_key(&name) {
+            order.push(name.clone());
+        }
+        *quantities.entry(name).or_insert(0) += quantity;
+    }
+
+    order
+        .into_iter()
+        .map(|name| {
+            let quantity = quantities[&name];
+            (name, quantity)
+        })
+        .collect()
+}
+
+fn parse_item_line(line: &str) -> Option<(String, u64)> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+
+    // Tab separated: name first, quantity second
+    if line.contains('\t') {
+        let mut fields = line.split('\t').map(str::trim);
+        let name = fields.next()?.to_string();
+        let quantity = fields.next().and_then(parse_quantity).unwrap_or(1);
+        return (!name.is_empty()).then_some((name, quantity));
+    }
+
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let first = words.first()?;
+    let last = words.last()?;
+
+    // "qty x Name" / "qty Name"
+    if let Some(quantity) = parse_quantity(first.trim_end_matches('x')) {
+        let rest = if words.get(1) == Some(&"x") {
+            &words[2..]
+        } else {
+            &words[1..]
+        };
+        if !rest.is_empty() {
+            return Some((rest.join(" "), quantity));
+        }
+    }
+
+    // "Name x qty" / "Name qty"
+    if words.len() > 1 {
+        if let Some(quantity) = parse_quantity(last.trim_start_matches('x')) {
+            let end = if words[words.len() - 2] == "x" {
+                words.len() - 2
+            } else {
+                words.len() - 1
+            };
+            if end > 0 {
+                return Some((words[..end].join(" "), quantity));
+            }
+        }
+    }
+
+    Some((words.join(" "), 1))
+}
+
+/// Parse a quantity, allowing thousands separators
+pub fn parse_quantity(text: &str) -> Option<u64> {
+    let cleaned: String = text
+        .chars()
+        .filter(|c| !matches!(c, ',' | '.' | '\''))
+        .collect();
+    if cleaned.is_empty() || !cleaned.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    cleaned.parse().ok()
+}
+
+/// Price parsed items, matching names case-insensitively
+pub fn appraise(items: &[(String, u64)], prices: &HashMap<String, PriceInfo>) -> Appraisal {
+    let by_lowercase: HashMap<String, (&String, &PriceInfo)> = prices
+        .iter()
+        .map(|(name, price)| (name.to_lowercase(), (name, price)))
+        .collect();
+
+    let mut appraised = Vec::new();
+    let mut unknown = Vec::new();
+
+    for (name, quantity) in items {
+        match by_lowercase.get(&name.to_lowercase()) {
+            Some((canonical, price)) => appraised.push(AppraisedItem {
+                name: canonical.to_string(),
+                quantity: *quantity,
+                buy_unit: price.buy,
+                sell_unit: price.sell,
+                buy_total: price.buy * *quantity as f64,
+                sell_total: price.sell * *quantity as f64,
+                producible: false,
+            }),
+            None => unknown.push(name.clone()),
+        }
+    }
+
+    Appraisal {
+        buy_total: appraised.iter().map(|i| i.buy_total).sum(),
+        sell_total: appraised.iter().map(|i| i.sell_total).sum(),
+        producible_sell_total: 0.0,
+        items: appraised,
+        unknown,
+    }
+}
+
+impl Appraisal {
+    /// Flag the items the user can produce from their moons, e.g. to value a buyback
+    /// contract against their own production
+    pub fn mark_producible(&mut self, is_producible: impl Fn(&str) -> bool) {
+        for item in &mut self.items {
+            item.producible = is_producible(&item.name);
+        }
+        self.producible_sell_total = self
+            .items
+            .iter()
+            .filter(|i| i.producible)
+            .map(|i| i.sell_total)
+            .sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_item_list_formats() {
+        let input = "Dysprosium\t1,500\tMoon Materials\n\
+                     200 x Hafnium\n\
+                     Platinum Technite 300\n\
+                     Fullerides x 40\n\
+                     Nitrogen Fuel Block\n\
+                     Hafnium 50\n";
+
+        let items = parse_item_list(input);
+
+        assert_eq!(
+            items,
+            vec![
+                ("Dysprosium".to_string(), 1500),
+                ("Hafnium".to_string(), 250),
+                ("Platinum Technite".to_string(), 300),
+                ("Fullerides".to_string(), 40),
+                ("Nitrogen Fuel Block".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_appraise_reports_unknown_items() {
+        let mut prices = HashMap::new();
+        prices.insert(
+            "Hafnium".to_string(),
+            PriceInfo {
+                buy: 100.0,
+                sell: 120.0,
+            },
+        );
+
+        let result = appraise(
+            &[("hafnium".to_string(), 10), ("Nonsense".to_string(), 1)],
+            &prices,
+        );
+
+        assert_eq!(result.items.len(), 1);
+        assert_eq!(result.items[0].name, "Hafnium");
+        assert_eq!(result.unknown, vec!["Nonsense".to_string()]);
+        assert!((result.sell_total - 1200.0).abs() < 0.001);
+    }
+}
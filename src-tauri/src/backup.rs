@@ -0,0 +1,179 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::prices::now_unix;
+use crate::{esi_auth, logging, migrations, storage, sync};
+
+/// Version of the archive layout written by this build
+const FORMAT_VERSION: u32 = 1;
+/// Largest archive restored, uncompressed
+const MAX_BACKUP_BYTES: u64 = 512 * 1_000_000;
+
+/// Left out of backups: logs, earlier copies of the data directory, and the telemetry
+/// device ID, which belongs to the machine rather than the user
+const EXCLUDED: &[&str] = &[logging::LOG_DIR, migrations::BACKUP_DIR, "telemetry.json"];
+
+/// Files in any profile holding credentials: the ESI refresh token and the sync secret.
+/// Only backed up when asked for, since anyone with the archive could use them
+const CREDENTIAL_FILES: &[&str] = &[esi_auth::AUTH_FILE, sync::CONFIG_FILE];
+
+/// Everything the app keeps on disk (moons, settings, inventory, plans, price snapshots and
+/// the rest, for every profile) in one gzip compressed JSON file
+#[derive(Debug, Serialize, Deserialize)]
+struct Backup {
+    format_version: u32,
+    /// Data directory layout the files are in; older layouts are migrated on restore
+    layout_version: u32,
+    /// Unix timestamp (seconds)
+    created_at: u64,
+    /// File contents by path relative to the data directory, with `/` separators
+    files: BTreeMap<String, String>,
+}
+
+/// What a backup holds
+#[derive(Debug, Clone, Serialize)]
+pub struct BackupSummary {
+    pub created_at: u64,
+    pub layout_version: u32,
+    pub files: usize,
+}
+
+impl Backup {
+    fn summary(&self) -> BackupSummary {
+        BackupSummary {
+            created_at: self.created_at,
+            layout_version: self.layout_version,
+            files: self.files.len(),
+        }
+    }
+}
+
+/// Write all app data to one archive. Credentials are left out unless
+/// `include_credentials` is set
+pub fn backup_data(path: &Path, include_credentials: bool) -> Result<BackupSummary, String> {
+    let data_dir = storage::data_dir().ok_or("Could not determine app data directory")?;
+    let mut files = BTreeMap::new();
+    collect_files(&data_dir, "", &mut files)?;
+    if include_credentials {
+        tracing::warn!("Backing up ESI login and sync secret; keep the archive private");
+    } else {
+        files.retain(|name, _| !is_credential_file(name));
+    }
+    if files.is_empty() {
+        return Err("There is no data to back up yet".to_string());
+    }
+
+    let backup = Backup {
+        format_version: FORMAT_VERSION,
+        layout_version: migrations::current_version(),
+        created_at: now_unix(),
+        files,
+    };
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let file =
+        File::create(path).map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+    serde_json::to_writer(&mut encoder, &backup)
+        .and_then(|_| encoder.finish().map_err(serde_json::Error::io))
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(backup.summary())
+}
+
+/// Replace all app data with an archive's, after copying the current data into the backups
+/// folder. Archives from an older layout are migrated; newer ones are refused. Credentials
+/// the archive doesn't carry are kept from the current data
+pub fn restore_data(path: &Path) -> Result<BackupSummary, String> {
+    let contents = storage::read_gzip(path, MAX_BACKUP_BYTES)?;
+    let backup: Backup = serde_json::from_slice(&contents)
+        .map_err(|e| format!("{} isn't a valid backup: {}", path.display(), e))?;
+    if backup.format_version > FORMAT_VERSION
+        || backup.layout_version > migrations::current_version()
+    {
+        return Err(format!(
+            "{} was made by a newer version of the app",
+            path.display()
+        ));
+    }
+    if let Some(bad) = backup.files.keys().find(|name| !is_safe_path(name)) {
+        return Err(format!(
+            "{} contains an invalid path '{}'",
+            path.display(),
+            bad
+        ));
+    }
+
+    let saved = migrations::back_up("before-restore")?;
+    tracing::info!(path = %saved.display(), "Saved current data before restoring");
+
+    let data_dir = storage::data_dir().ok_or("Could not determine app data directory")?;
+    let mut credentials = BTreeMap::new();
+    collect_files(&data_dir, "", &mut credentials)?;
+    credentials.retain(|name, _| is_credential_file(name) && !backup.files.contains_key(name));
+
+    storage::remove_all_except(EXCLUDED)?;
+    for (name, contents) in backup.files.iter().chain(&credentials) {
+        storage::save_text(name, contents)?;
+    }
+    // Archives always carry their layout, so older ones are brought up to date here
+    migrations::stamp(backup.layout_version)?;
+    migrations::run()?;
+
+    Ok(backup.summary())
+}
+
+/// Read every text file under `dir` into `files`, keyed by path relative to the data
+/// directory
+fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut BTreeMap<String, String>,
+) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    for entry in entries.flatten() {
+        let Some(name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+        if prefix.is_empty() && EXCLUDED.contains(&name.as_str()) {
+            continue;
+        }
+        let relative = format!("{}{}", prefix, name);
+        let path = entry.path();
+        if path.is_dir() {
+            collect_files(&path, &format!("{}/", relative), files)?;
+        } else if !name.ends_with(".tmp") {
+            match fs::read_to_string(&path) {
+                Ok(contents) => {
+                    files.insert(relative, contents);
+                }
+                Err(e) => tracing::warn!(path = %path.display(), error = %e, "Not backing up file"),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_credential_file(name: &str) -> bool {
+    let file_name = name.rsplit('/').next().unwrap_or(name);
+    CREDENTIAL_FILES.contains(&file_name)
+}
+
+/// A relative path that stays inside the data directory
+fn is_safe_path(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && !name.contains('\\')
+        && !name.contains(':')
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..")
+}
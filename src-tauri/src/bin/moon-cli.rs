@@ -0,0 +1,196 @@
+//! Command line companion to the app: runs the same moon analysis without the GUI, using
+//! the active profile's settings and pins.
+//!
+//! ```text
+//! moon-cli analyze scan.txt --min-margin 10
+//! moon-cli parse scan.txt
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::process::ExitCode;
+
+use moon_lib::analysis::{self, AnalysisFilters, CancelToken, ResultSort, SortKey};
+use moon_lib::ore_mappings::OreMappings;
+use moon_lib::parser::{self, MoonComposition};
+use moon_lib::pins::PricePins;
+use moon_lib::prices::{self, ReactionProfit};
+use moon_lib::profiles;
+use moon_lib::reaction_tree::TreeContext;
+use moon_lib::reactions::ReactionDatabase;
+use moon_lib::settings::Settings;
+
+const USAGE: &str = "\
+Usage:
+  moon-cli analyze <scan.txt> [--min-margin PERCENT] [--min-profit ISK] [--sort KEY]
+                              [--top N] [--json]
+  moon-cli parse <scan.txt>
+
+Sort keys: margin, profit, isk_per_hour, isk_per_day, isk_per_m3, cash_profit, cash_margin";
+
+/// Options for the analyze command
+struct AnalyzeArgs {
+    scan: String,
+    filters: AnalysisFilters,
+    sort: ResultSort,
+    top: Option<usize>,
+    json: bool,
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("analyze") => match parse_analyze_args(&args[1..]) {
+            Ok(options) => analyze(options).await,
+            Err(e) => Err(e),
+        },
+        Some("parse") if args.len() == 2 => read_moons(&args[1]).and_then(|moons| {
+            serde_json::to_string_pretty(&moons)
+                .map(|json| println!("{}", json))
+                .map_err(|e| format!("Failed to write moons: {}", e))
+        }),
+        Some("-h" | "--help") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn parse_analyze_args(args: &[String]) -> Result<AnalyzeArgs, String> {
+    let mut scan = None;
+    let mut filters = AnalysisFilters::default();
+    let mut sort = ResultSort::default();
+    let mut top = None;
+    let mut json = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .cloned()
+                .ok_or_else(|| format!("{} needs a value", name))
+        };
+        match arg.as_str() {
+            "--min-margin" => filters.min_margin = Some(number(&value(arg)?)?),
+            "--min-profit" => filters.min_profit = Some(number(&value(arg)?)?),
+            "--top" => {
+                let n = value(arg)?;
+                top = Some(n.parse().map_err(|_| format!("Invalid count '{}'", n))?);
+            }
+            "--sort" => {
+                let key = value(arg)?;
+                sort.key =
+                    serde_json::from_value::<SortKey>(serde_json::Value::String(key.clone()))
+                        .map_err(|_| format!("Unknown sort key '{}'", key))?;
+            }
+            "--json" => json = true,
+            other if other.starts_with("--") => return Err(format!("Unknown option {}", other)),
+            other if scan.is_none() => scan = Some(other.to_string()),
+            other => return Err(format!("Unexpected argument '{}'", other)),
+        }
+    }
+
+    Ok(AnalyzeArgs {
+        scan: scan.ok_or_else(|| USAGE.to_string())?,
+        filters,
+        sort,
+        top,
+        json,
+    })
+}
+
+fn number(text: &str) -> Result<f64, String> {
+    text.parse()
+        .map_err(|_| format!("Invalid number '{}'", text))
+}
+
+fn read_moons(path: &str) -> Result<Vec<MoonComposition>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    parser::parse_moon_data(&text).map_err(|e| format!("{}: {}", path, e))
+}
+
+async fn analyze(args: AnalyzeArgs) -> Result<(), String> {
+    let moons = read_moons(&args.scan)?;
+    profiles::init();
+    let settings = Settings::load();
+    let ore_mappings = OreMappings::load()?;
+    let reactions_db = ReactionDatabase::load()?;
+
+    let moon_goo: Vec<String> = ore_mappings
+        .ores_to_moon_goo(moons.iter().flat_map(|m| &m.materials))
+        .into_iter()
+        .collect();
+    if moon_goo.is_empty() {
+        return Err("No moon ores found in the scan".to_string());
+    }
+    let user_material_ids: HashSet<u32> = reactions_db.get_user_material_ids(&moon_goo);
+
+    let snapshot = prices::fetch_snapshot(
+        &reactions_db.get_all_item_names(),
+        settings.price_source(),
+        settings.price_retry,
+        &prices::StructureMarket::default(),
+    )
+    .await?;
+    if snapshot.stale {
+        eprintln!("Price source unreachable, using prices saved offline");
+    }
+    let prices = PricePins::load().apply(&snapshot.prices);
+
+    let stock = HashMap::new();
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &prices,
+        stock: &stock,
+        max_depth: None,
+        subtrees: None,
+        overrides: None,
+    };
+    let mut profits = analysis::profitable_reactions(
+        &ctx,
+        &settings.sale_options,
+        &args.filters,
+        &CancelToken::default(),
+        |_| {},
+    )?;
+    analysis::sort_results(&mut profits, args.sort, &reactions_db);
+    profits.truncate(args.top.unwrap_or(usize::MAX));
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&profits)
+            .map_err(|e| format!("Failed to write results: {}", e))?;
+        println!("{}", json);
+    } else {
+        print_table(&profits, &snapshot.source);
+    }
+    Ok(())
+}
+
+fn print_table(profits: &[ReactionProfit], source: &str) {
+    println!(
+        "{:<40} {:>16} {:>9} {:>16}",
+        "Reaction", "Profit/run", "Margin", "ISK/day"
+    );
+    for profit in profits {
+        println!(
+            "{:<40} {:>16.0} {:>8.1}% {:>16.0}",
+            profit.formula_name, profit.profit, profit.margin, profit.isk_per_day
+        );
+    }
+    println!(
+        "\n{} profitable reactions, prices from {}",
+        profits.len(),
+        source
+    );
+}
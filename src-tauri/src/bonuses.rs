@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::fees::SaleOptions;
+use crate::prices::{self, PriceInfo};
+use crate::reactions::{Reaction, ReactionItem};
+
+const SECONDS_PER_MONTH: f64 = 30.0 * 86_400.0;
+/// Reactions skill: run time reduction per level
+const REACTIONS_SKILL_TIME_PER_LEVEL: f64 = 0.04;
+/// Tatara role bonus to reaction time
+const TATARA_TIME_BONUS: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum RigTier {
+    #[default]
+    None,
+    T1,
+    T2,
+}
+
+impl RigTier {
+    /// (material reduction, time reduction) before the security multiplier
+    fn bonuses(self) -> (f64, f64) {
+        match self {
+            RigTier::None => (0.0, 0.0),
+            RigTier::T1 => (0.02, 0.20),
+            RigTier::T2 => (0.024, 0.24),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SecurityBand {
+    #[default]
+    Lowsec,
+    Nullsec,
+    Wormhole,
+}
+
+impl SecurityBand {
+    /// Multiplier applied to rig bonuses
+    fn rig_multiplier(self) -> f64 {
+        match self {
+            SecurityBand::Lowsec => 1.0,
+            SecurityBand::Nullsec | SecurityBand::Wormhole => 1.1,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Refinery {
+    #[default]
+    Athanor,
+    Tatara,
+}
+
+/// One combination of skills, rigs and structure to price a reaction under
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BonusScenario {
+    pub name: String,
+    /// Reactions skill level, 0 to 5
+    pub reactions_skill: u8,
+    pub rig: RigTier,
+    pub security: SecurityBand,
+    pub refinery: Refinery,
+}
+
+impl Default for BonusScenario {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            reactions_skill: 5,
+            rig: RigTier::None,
+            security: SecurityBand::Lowsec,
+            refinery: Refinery::Athanor,
+        }
+    }
+}
+
+impl BonusScenario {
+    /// Fraction of the base input quantities consumed
+    pub fn material_multiplier(&self) -> f64 {
+        let (material, _) = self.rig.bonuses();
+        1.0 - material * self.security.rig_multiplier()
+    }
+
+    /// Fraction of the base run time taken
+    pub fn time_multiplier(&self) -> f64 {
+        let (_, time) = self.rig.bonuses();
+        let skill = 1.0 - REACTIONS_SKILL_TIME_PER_LEVEL * self.reactions_skill.min(5) as f64;
+        let rig = 1.0 - time * self.security.rig_multiplier();
+        let refinery = match self.refinery {
+            Refinery::Athanor => 1.0,
+            Refinery::Tatara => 1.0 - TATARA_TIME_BONUS,
+        };
+        skill * rig * refinery
+    }
+}
+
+/// Profit of a reaction under one scenario, running one slot continuously
+#[derive(Debug, Clone, Serialize)]
+pub struct ScenarioResult {
+    pub name: String,
+    pub material_multiplier: f64,
+    pub time_multiplier: f64,
+    pub run_seconds: f64,
+    pub input_cost_per_run: f64,
+    pub profit_per_run: f64,
+    pub runs_per_month: f64,
+    pub profit_per_month: f64,
+    /// Monthly profit compared to the first scenario
+    pub gain_per_month: f64,
+}
+
+/// Price a reaction under each scenario; the first scenario is the baseline for gains
+pub fn compare_scenarios(
+    reaction: &Reaction,
+    scenarios: &[BonusScenario],
+    prices: &HashMap<String, PriceInfo>,
+    user_material_ids: &HashSet<u32>,
+    sale_options: &SaleOptions,
+) -> Result<Vec<ScenarioResult>, String> {
+    if scenarios.is_empty() {
+        return Err("Add at least one scenario to compare".to_string());
+    }
+
+    // Price a full job so material savings round the way they do in game
+    let runs = reaction.max_runs_per_job.max(1);
+    let too_many_runs = || {
+        format!(
+            "{} runs per job of {} is too many to price",
+            runs, reaction.formula_name
+        )
+    };
+
+    let mut results = Vec::new();
+    for (index, scenario) in scenarios.iter().enumerate() {
+        let material_multiplier = scenario.material_multiplier();
+        let inputs = reaction
+            .inputs
+            .iter()
+            .map(|input| {
+                let quantity = (input.quantity as f64 * runs as f64 * material_multiplier)
+                    .ceil()
+                    .max(runs as f64);
+                if quantity > u32::MAX as f64 {
+                    return Err(too_many_runs());
+                }
+                Ok(ReactionItem {
+                    quantity: quantity as u32,
+                    ..input.clone()
+                })
+            })
+            .collect::<Result<_, String>>()?;
+        let job = Reaction {
+            output: ReactionItem {
+                quantity: reaction
+                    .output
+                    .quantity
+                    .checked_mul(runs)
+                    .ok_or_else(too_many_runs)?,
+                ..reaction.output.clone()
+            },
+            inputs,
+            ..reaction.clone()
+        };
+
+        let profit =
+            prices::calculate_reaction_profit(&job, prices, user_material_ids, sale_options)
+                .ok_or_else(|| format!("Missing prices for '{}'", reaction.formula_name))?;
+
+        let time_multiplier = scenario.time_multiplier();
+        let run_seconds = reaction.run_seconds as f64 * time_multiplier;
+        let runs_per_month = SECONDS_PER_MONTH / run_seconds.max(1.0);
+        let profit_per_run = profit.profit / runs as f64;
+
+        results.push(ScenarioResult {
+            name: if scenario.name.is_empty() {
+                format!("Scenario {}", index + 1)
+            } else {
+                scenario.name.clone()
+            },
+            material_multiplier,
+            time_multiplier,
+            run_seconds,
+            input_cost_per_run: profit.input_cost / runs as f64,
+            profit_per_run,
+            runs_per_month,
+            profit_per_month: profit_per_run * runs_per_month,
+            gain_per_month: 0.0,
+        });
+    }
+
+    let baseline = results[0].profit_per_month;
+    for result in &mut results {
+        result.gain_per_month = result.profit_per_month - baseline;
+    }
+    Ok(results)
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use std::backtrace::Backtrace;
+use std::fs;
+use std::panic::PanicHookInfo;
+
+use crate::prices::now_unix;
+use crate::storage;
+
+const CRASH_DIR: &str = "crashes";
+
+/// What was known about a panic when it happened
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrashReport {
+    /// Tells apart reports from panics in the same second. Empty on older reports
+    #[serde(default)]
+    pub id: String,
+    /// Unix timestamp (seconds)
+    pub occurred_at: u64,
+    pub version: String,
+    pub os: String,
+    pub thread: String,
+    pub message: String,
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// Whether the report has been sent to the telemetry endpoint
+    #[serde(default)]
+    pub submitted: bool,
+}
+
+impl CrashReport {
+    fn from_panic(info: &PanicHookInfo) -> Self {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic payload".to_string());
+
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            occurred_at: now_unix(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            os: std::env::consts::OS.to_string(),
+            thread: std::thread::current()
+                .name()
+                .unwrap_or("unnamed")
+                .to_string(),
+            message,
+            location: info
+                .location()
+                .map(|l| format!("{}:{}", l.file(), l.line())),
+            backtrace: Backtrace::force_capture().to_string(),
+            submitted: false,
+        }
+    }
+
+    fn file_name(&self) -> String {
+        if self.id.is_empty() {
+            format!("{}/crash-{}.json", CRASH_DIR, self.occurred_at)
+        } else {
+            format!("{}/crash-{}-{}.json", CRASH_DIR, self.occurred_at, self.id)
+        }
+    }
+}
+
+/// Write a crash report for every panic, on commands and background threads alike, then
+/// carry on with the default panic handling
+pub fn install_panic_hook() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let report = CrashReport::from_panic(info);
+        tracing::error!(
+            message = %report.message,
+            location = report.location.as_deref().unwrap_or("unknown"),
+            thread = %report.thread,
+            "Panic"
+        );
+        let _ = storage::save_json(&report.file_name(), &report);
+        default_hook(info);
+    }));
+}
+
+/// Crash reports that haven't been sent yet
+pub fn unsubmitted() -> Vec<CrashReport> {
+    let Some(dir) = storage::data_file(CRASH_DIR) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            storage::load_json::<CrashReport>(&format!("{}/{}", CRASH_DIR, name.to_str()?))
+        })
+        .filter(|report| !report.submitted)
+        .collect()
+}
+
+/// Record that a report has been sent
+pub fn mark_submitted(report: &CrashReport) -> Result<(), String> {
+    storage::save_json(
+        &report.file_name(),
+        &CrashReport {
+            submitted: true,
+            ..report.clone()
+        },
+    )
+}
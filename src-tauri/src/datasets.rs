@@ -0,0 +1,163 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::Path;
+
+use crate::ore_mappings::OreMappings;
+use crate::prices::now_unix;
+use crate::reactions::ReactionDatabase;
+use crate::storage;
+
+/// Manifest describing the latest reaction and ore data generated from the EVE SDE
+const MANIFEST_URL: &str =
+    "https://github.com/isomerc/moon/releases/latest/download/data-manifest.json";
+const DATA_DIR: &str = "data";
+/// An update is written here in full, then swapped in for `DATA_DIR`
+const STAGING_DIR: &str = "data.staging";
+/// The previous dataset while an update is swapped in
+const PREVIOUS_DIR: &str = "data.previous";
+const REACTIONS_FILE: &str = "reactions.json";
+const MAPPINGS_FILE: &str = "mappings.json";
+const INFO_FILE: &str = "dataset.json";
+
+/// Data shipped inside the app, used until an update has been downloaded
+pub const BUNDLED_REACTIONS: &str = include_str!("../reactions.json");
+pub const BUNDLED_MAPPINGS: &str = include_str!("../mappings.json");
+pub const BUNDLED_VERSION: &str = concat!("bundled-", env!("CARGO_PKG_VERSION"));
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+    version: String,
+    reactions: ManifestFile,
+    mappings: ManifestFile,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestFile {
+    url: String,
+    /// Hex encoded SHA-256 of the file
+    sha256: String,
+}
+
+/// Version of the reaction and ore data in use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetInfo {
+    pub version: String,
+    /// Unix timestamp (seconds) the data was downloaded; None for the bundled data
+    pub installed_at: Option<u64>,
+}
+
+/// The downloaded dataset, or the bundled one if none has been installed
+pub fn installed() -> DatasetInfo {
+    storage::load_json(&data_file(INFO_FILE)).unwrap_or_else(|| DatasetInfo {
+        version: BUNDLED_VERSION.to_string(),
+        installed_at: None,
+    })
+}
+
+/// Downloaded reactions data, if an update has been installed
+pub fn installed_reactions() -> Option<String> {
+    storage::load_json::<DatasetInfo>(&data_file(INFO_FILE))?;
+    storage::load_text(&data_file(REACTIONS_FILE))
+}
+
+/// Downloaded ore mappings, if an update has been installed
+pub fn installed_mappings() -> Option<String> {
+    storage::load_json::<DatasetInfo>(&data_file(INFO_FILE))?;
+    storage::load_text(&data_file(MAPPINGS_FILE))
+}
+
+fn data_file(file_name: &str) -> String {
+    format!("{}/{}", DATA_DIR, file_name)
+}
+
+/// Download the latest dataset if it differs from the installed one. Files are checked
+/// against the manifest checksums and parsed, then written to a staging folder that
+/// replaces the installed dataset in one rename, so an update applies fully or not at all
+pub async fn update() -> Result<DatasetInfo, String> {
+    let client = reqwest::Client::new();
+    let manifest: Manifest = fetch(&client, MANIFEST_URL).await.and_then(|body| {
+        serde_json::from_str(&body).map_err(|e| format!("Failed to parse manifest: {}", e))
+    })?;
+
+    let current = installed();
+    if manifest.version == current.version {
+        return Ok(current);
+    }
+
+    let reactions = fetch_verified(&client, &manifest.reactions).await?;
+    let mappings = fetch_verified(&client, &manifest.mappings).await?;
+    ReactionDatabase::from_json(&reactions)?;
+    OreMappings::from_json(&mappings)?;
+
+    let info = DatasetInfo {
+        version: manifest.version,
+        installed_at: Some(now_unix()),
+    };
+    let staging =
+        storage::data_file(STAGING_DIR).ok_or("Could not determine app data directory")?;
+    // Left over from an update that was interrupted
+    let _ = fs::remove_dir_all(&staging);
+    let staged = |file_name: &str| format!("{}/{}", STAGING_DIR, file_name);
+    storage::save_text(&staged(REACTIONS_FILE), &reactions)?;
+    storage::save_text(&staged(MAPPINGS_FILE), &mappings)?;
+    storage::save_json(&staged(INFO_FILE), &info)?;
+    install_staged(&staging)?;
+    Ok(info)
+}
+
+/// Swap the staged dataset in for the installed one, putting the installed one back if the
+/// swap fails
+fn install_staged(staging: &Path) -> Result<(), String> {
+    let current = storage::data_file(DATA_DIR).ok_or("Could not determine app data directory")?;
+    let previous =
+        storage::data_file(PREVIOUS_DIR).ok_or("Could not determine app data directory")?;
+    let _ = fs::remove_dir_all(&previous);
+
+    let had_dataset = current.exists();
+    if had_dataset {
+        fs::rename(&current, &previous)
+            .map_err(|e| format!("Failed to move the installed dataset aside: {}", e))?;
+    }
+    if let Err(e) = fs::rename(staging, &current) {
+        if had_dataset {
+            let _ = fs::rename(&previous, &current);
+        }
+        return Err(format!("Failed to install the dataset: {}", e));
+    }
+    if had_dataset {
+        let _ = fs::remove_dir_all(&previous);
+    }
+    Ok(())
+}
+
+async fn fetch_verified(client: &reqwest::Client, file: &ManifestFile) -> Result<String, String> {
+    let body = fetch(client, &file.url).await?;
+    let digest: String = Sha256::digest(body.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    if !digest.eq_ignore_ascii_case(file.sha256.trim()) {
+        return Err(format!("Checksum mismatch for {}", file.url));
+    }
+    Ok(body)
+}
+
+async fn fetch(client: &reqwest::Client, url: &str) -> Result<String, String> {
+    let response = client
+        .get(url)
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to download {}: {}", url, e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("{} returned status: {}", url, response.status()));
+    }
+
+    response
+        .text()
+        .await
+        .map_err(|e| format!("Failed to read {}: {}", url, e))
+}
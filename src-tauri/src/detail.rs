@@ -0,0 +1,76 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use crate::reaction_tree::ReactionTreeNode;
+use crate::shopping::ShoppingList;
+use crate::slots::JobSchedule;
+
+/// Event sent to a single detail window with its refreshed view
+pub const DETAIL_UPDATED_EVENT: &str = "detail://updated";
+
+/// Labels of detail windows start with this; the `detail` capability covers `detail-*`
+const LABEL_PREFIX: &str = "detail-";
+
+/// The reaction a detail window follows, and how much of it to plan for
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct DetailTarget {
+    pub output_id: u32,
+    pub quantity: u32,
+    pub characters: u32,
+    pub slots_per_character: u32,
+}
+
+/// Everything a detail window shows for its reaction
+#[derive(Debug, Clone, Serialize)]
+pub struct DetailView {
+    pub label: String,
+    pub output_name: String,
+    pub target: DetailTarget,
+    pub tree: ReactionTreeNode,
+    pub schedule: JobSchedule,
+    pub shopping_list: ShoppingList,
+}
+
+/// Open detail windows and what each one follows. Every window has its own entry, so
+/// changing one leaves the main window and the other detail windows alone
+#[derive(Debug, Default)]
+pub struct DetailWindows {
+    targets: Mutex<HashMap<String, DetailTarget>>,
+}
+
+impl DetailWindows {
+    /// Label of the detail window for a reaction's output; one window per reaction
+    pub fn label_for(output_id: u32) -> String {
+        format!("{}{}", LABEL_PREFIX, output_id)
+    }
+
+    pub fn get(&self, label: &str) -> Option<DetailTarget> {
+        self.targets.lock().ok()?.get(label).copied()
+    }
+
+    pub fn set(&self, label: &str, target: DetailTarget) {
+        if let Ok(mut targets) = self.targets.lock() {
+            targets.insert(label.to_string(), target);
+        }
+    }
+
+    pub fn remove(&self, label: &str) {
+        if let Ok(mut targets) = self.targets.lock() {
+            targets.remove(label);
+        }
+    }
+
+    /// Every open window's label and target
+    pub fn all(&self) -> Vec<(String, DetailTarget)> {
+        self.targets
+            .lock()
+            .map(|targets| {
+                targets
+                    .iter()
+                    .map(|(label, target)| (label.clone(), *target))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
@@ -0,0 +1,217 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::storage;
+
+/// Holds the refresh token, so it is left out of backups unless asked for
+pub const AUTH_FILE: &str = "esi_auth.json";
+const SSO_URL: &str = "https://login.eveonline.com/v2/oauth";
+/// Scope needed to read a player structure's market
+const STRUCTURE_MARKET_SCOPE: &str = "esi-markets.structure_markets.v1";
+/// Renew the access token this long before it expires
+const EXPIRY_MARGIN_SECS: u64 = 60;
+
+/// EVE SSO login for ESI endpoints that need a character, kept per profile. Uses the PKCE
+/// flow for native apps, so only the client ID of the user's own application is needed
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EsiAuth {
+    /// Client ID of the application registered on the EVE developers site
+    pub client_id: String,
+    /// Callback URL registered with the application
+    pub callback_url: String,
+    refresh_token: Option<String>,
+    access_token: Option<String>,
+    /// Unix timestamp (seconds) the access token expires at
+    expires_at: u64,
+    /// Login started but not yet completed
+    pending: Option<PendingLogin>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PendingLogin {
+    state: String,
+    code_verifier: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: u64,
+    refresh_token: String,
+}
+
+/// Whether ESI is logged in, for the settings screen
+#[derive(Debug, Clone, Serialize)]
+pub struct EsiAuthStatus {
+    pub client_id: String,
+    pub callback_url: String,
+    pub logged_in: bool,
+}
+
+impl EsiAuth {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(AUTH_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(AUTH_FILE), self)
+    }
+
+    pub fn status(&self) -> EsiAuthStatus {
+        EsiAuthStatus {
+            client_id: self.client_id.clone(),
+            callback_url: self.callback_url.clone(),
+            logged_in: self.refresh_token.is_some(),
+        }
+    }
+
+    /// Start a login for the given application and return the URL to open in the browser
+    pub fn begin_login(&mut self, client_id: &str, callback_url: &str) -> Result<String, String> {
+        let client_id = client_id.trim();
+        let callback_url = callback_url.trim();
+        if client_id.is_empty() || callback_url.is_empty() {
+            return Err("Enter the client ID and callback URL of your ESI application".to_string());
+        }
+
+        let state = uuid::Uuid::new_v4().simple().to_string();
+        let code_verifier = format!(
+            "{}{}",
+            uuid::Uuid::new_v4().simple(),
+            uuid::Uuid::new_v4().simple()
+        );
+        let challenge = base64_url(&Sha256::digest(code_verifier.as_bytes()));
+
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/authorize", SSO_URL),
+            &[
+                ("response_type", "code"),
+                ("redirect_uri", callback_url),
+                ("client_id", client_id),
+                ("scope", STRUCTURE_MARKET_SCOPE),
+                ("code_challenge", &challenge),
+                ("code_challenge_method", "S256"),
+                ("state", &state),
+            ],
+        )
+        .map_err(|e| format!("Invalid login URL: {}", e))?;
+
+        self.client_id = client_id.to_string();
+        self.callback_url = callback_url.to_string();
+        self.pending = Some(PendingLogin {
+            state,
+            code_verifier,
+        });
+        Ok(url.to_string())
+    }
+
+    /// Finish a login with the URL the browser was sent back to
+    pub async fn complete_login(&mut self, redirected_url: &str) -> Result<(), String> {
+        let pending = self.pending.clone().ok_or("Start the ESI login first")?;
+        let url = reqwest::Url::parse(redirected_url.trim())
+            .map_err(|_| "Paste the whole address the browser was sent to".to_string())?;
+        let param = |key: &str| {
+            url.query_pairs()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value.into_owned())
+        };
+        if param("state").as_deref() != Some(pending.state.as_str()) {
+            return Err("This login link doesn't belong to the login that was started".to_string());
+        }
+        let code = param("code").ok_or("The address has no login code")?;
+
+        self.request_token(&[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("client_id", &self.client_id.clone()),
+            ("code_verifier", &pending.code_verifier),
+        ])
+        .await?;
+        self.pending = None;
+        Ok(())
+    }
+
+    pub fn logout(&mut self) {
+        self.refresh_token = None;
+        self.access_token = None;
+        self.expires_at = 0;
+        self.pending = None;
+    }
+
+    /// A valid access token, renewed with the refresh token when it has expired
+    pub async fn access_token(&mut self) -> Result<String, String> {
+        if let Some(token) = &self.access_token {
+            if crate::prices::now_unix() + EXPIRY_MARGIN_SECS < self.expires_at {
+                return Ok(token.clone());
+            }
+        }
+        let refresh_token = self
+            .refresh_token
+            .clone()
+            .ok_or("Log in to ESI to use structure markets")?;
+        self.request_token(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &refresh_token),
+            ("client_id", &self.client_id.clone()),
+        ])
+        .await?;
+        self.access_token
+            .clone()
+            .ok_or_else(|| "ESI login returned no access token".to_string())
+    }
+
+    async fn request_token(&mut self, form: &[(&str, &str)]) -> Result<(), String> {
+        let response = reqwest::Client::new()
+            .post(format!("{}/token", SSO_URL))
+            .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+            .form(form)
+            .timeout(std::time::Duration::from_secs(30))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach EVE login: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("EVE login returned status: {}", response.status()));
+        }
+        let token: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse login response: {}", e))?;
+
+        // The refresh token may be replaced on every use
+        self.access_token = Some(token.access_token);
+        self.refresh_token = Some(token.refresh_token);
+        self.expires_at = crate::prices::now_unix() + token.expires_in;
+        Ok(())
+    }
+}
+
+/// Base64url without padding, as PKCE code challenges are sent
+fn base64_url(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..=chunk.len() {
+            out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base64_url_has_no_padding() {
+        assert_eq!(base64_url(b"foo"), "Zm9v");
+        assert_eq!(base64_url(b"foob"), "Zm9vYg");
+        assert_eq!(base64_url(b"fooba"), "Zm9vYmE");
+        assert_eq!(base64_url(&[0xfb, 0xff]), "-_8");
+    }
+}
@@ -0,0 +1,283 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::fees::{self, OutputDestination, SaleOptions};
+use crate::prices::{self, PriceInfo, PriceSnapshot};
+use crate::reactions::Reaction;
+use crate::transport::HaulingRate;
+
+/// Where an item's price came from
+#[derive(Debug, Clone, Serialize)]
+pub struct PricedItem {
+    pub name: String,
+    pub quantity: u32,
+    pub unit_price: f64,
+    pub total_price: f64,
+    /// Which side of the market was used (e.g. "sell")
+    pub price_basis: String,
+    pub source: String,
+    pub fetched_at: u64,
+    pub from_moon: bool,
+}
+
+/// One line of the calculation, in the order it was applied
+#[derive(Debug, Clone, Serialize)]
+pub struct CalculationStep {
+    pub description: String,
+    pub amount: f64,
+}
+
+/// Step-by-step breakdown of how a reaction's profit was computed
+#[derive(Debug, Clone, Serialize)]
+pub struct CalculationExplanation {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub output: PricedItem,
+    pub inputs: Vec<PricedItem>,
+    pub steps: Vec<CalculationStep>,
+    pub profit: f64,
+    pub margin: f64,
+}
+
+/// Explain a single run of a reaction using the given price snapshot
+pub fn explain_reaction(
+    reaction: &Reaction,
+    snapshot: &PriceSnapshot,
+    user_material_ids: &HashSet<u32>,
+    sale_options: &SaleOptions,
+) -> Result<CalculationExplanation, String> {
+    let profit = prices::calculate_reaction_profit(
+        reaction,
+        &snapshot.prices,
+        user_material_ids,
+        sale_options,
+    )
+    .ok_or_else(|| format!("Missing prices for '{}'", reaction.formula_name))?;
+
+    let priced =
+        |name: &str, quantity: u32, unit_price: f64, total_price: f64, basis: &str, from_moon| {
+            PricedItem {
+                name: name.to_string(),
+                quantity,
+                unit_price,
+                total_price,
+                price_basis: basis.to_string(),
+                source: snapshot.source.clone(),
+                fetched_at: snapshot.fetched_at,
+                from_moon,
+            }
+        };
+
+    let output_basis = match sale_options.destination {
+        OutputDestination::SellOrder => "sell",
+        OutputDestination::ImmediateSell => "buy",
+    };
+    let output = priced(
+        &profit.output_name,
+        profit.output_quantity,
+        profit.output_unit_price,
+        profit.output_value,
+        output_basis,
+        false,
+    );
+    let inputs: Vec<PricedItem> = profit
+        .inputs
+        .iter()
+        .map(|i| {
+            priced(
+                &i.name,
+                i.quantity,
+                i.unit_price,
+                i.total_price,
+                "sell",
+                i.from_moon,
+            )
+        })
+        .collect();
+
+    let mut steps = Vec::new();
+    steps.push(CalculationStep {
+        description: format!(
+            "Output: {} x {} at {:.2} ISK ({})",
+            profit.output_quantity, profit.output_name, profit.output_unit_price, output_basis
+        ),
+        amount: profit.output_value,
+    });
+    for input in &inputs {
+        let note = if input.from_moon {
+            " - from your moons, priced at opportunity cost"
+        } else {
+            ""
+        };
+        steps.push(CalculationStep {
+            description: format!(
+                "Input: {} x {} at {:.2} ISK (sell){}",
+                input.quantity, input.name, input.unit_price, note
+            ),
+            amount: -input.total_price,
+        });
+    }
+    steps.push(CalculationStep {
+        description: "Bonuses: none applied (material and time multipliers x1.00)".to_string(),
+        amount: 0.0,
+    });
+    let sale = fees::value_output(
+        &snapshot.prices[&profit.output_name],
+        profit.output_quantity,
+        sale_options,
+    );
+    steps.push(CalculationStep {
+        description: match sale_options.destination {
+            OutputDestination::SellOrder => format!(
+                "Broker fee: {:.2}% for listing a sell order",
+                sale_options.broker_fee_percent
+            ),
+            OutputDestination::ImmediateSell => {
+                "Broker fee: none, output is sold straight into buy orders".to_string()
+            }
+        },
+        amount: -sale.broker_fee,
+    });
+    if sale_options.destination == OutputDestination::SellOrder {
+        steps.push(CalculationStep {
+            description: format!(
+                "Relist fees: {:.1} relists/day over {:.1} days, half the order unsold on average",
+                sale_options.relists_per_day, sale_options.sale_duration_days
+            ),
+            amount: -sale.relist_fees,
+        });
+    }
+    steps.push(CalculationStep {
+        description: format!("Sales tax: {:.2}%", sale_options.sales_tax_percent),
+        amount: -sale.sales_tax,
+    });
+    if sale_options.hauling != HaulingRate::None {
+        steps.push(CalculationStep {
+            description: format!(
+                "Import hauling: bought inputs at {:.2} ISK/m3, moon materials are local",
+                sale_options.hauling.isk_per_m3()
+            ),
+            amount: -profit.import_hauling_cost,
+        });
+        steps.push(CalculationStep {
+            description: format!(
+                "Export hauling: output to market at {:.2} ISK/m3",
+                sale_options.hauling.isk_per_m3()
+            ),
+            amount: -profit.export_hauling_cost,
+        });
+    }
+    steps.push(CalculationStep {
+        description: "Rounding: single run, quantities are exact".to_string(),
+        amount: 0.0,
+    });
+    steps.push(CalculationStep {
+        description: format!(
+            "Profit: output value minus fees, hauling and input cost, margin {:.2}% of input cost",
+            profit.margin
+        ),
+        amount: profit.profit,
+    });
+
+    Ok(CalculationExplanation {
+        formula_id: reaction.formula_id,
+        formula_name: reaction.formula_name.clone(),
+        output,
+        inputs,
+        steps,
+        profit: profit.profit,
+        margin: profit.margin,
+    })
+}
+
+/// How one input's price moves a reaction's margin
+#[derive(Debug, Clone, Serialize)]
+pub struct InputSensitivity {
+    pub name: String,
+    /// Share of the total input cost
+    pub cost_share: f64,
+    /// Margin with the input's price raised by the change
+    pub margin_if_up: f64,
+    /// Margin with the input's price lowered by the change
+    pub margin_if_down: f64,
+    /// Percentage points the margin moves per the change, in either direction
+    pub margin_swing: f64,
+}
+
+/// Effect of a price change in each input on a reaction's margin
+#[derive(Debug, Clone, Serialize)]
+pub struct SensitivityReport {
+    pub formula_id: u32,
+    pub formula_name: String,
+    /// Price change applied to each input in turn, in percent
+    pub change_percent: f64,
+    pub margin: f64,
+    /// Inputs, the one that moves the margin most first
+    pub inputs: Vec<InputSensitivity>,
+    /// Input with the largest share of the cost
+    pub dominant_input: Option<String>,
+}
+
+/// Re-price a reaction with each input's price moved up and down by `change_percent`
+pub fn input_sensitivity(
+    reaction: &Reaction,
+    prices: &HashMap<String, PriceInfo>,
+    user_material_ids: &HashSet<u32>,
+    sale_options: &SaleOptions,
+    change_percent: f64,
+) -> Result<SensitivityReport, String> {
+    let missing = || format!("Missing prices for '{}'", reaction.formula_name);
+    let base = prices::calculate_reaction_profit(reaction, prices, user_material_ids, sale_options)
+        .ok_or_else(missing)?;
+
+    let margin_with = |name: &str, factor: f64| -> Result<f64, String> {
+        let mut moved = prices.clone();
+        if let Some(price) = moved.get_mut(name) {
+            price.buy *= factor;
+            price.sell *= factor;
+        }
+        prices::calculate_reaction_profit(reaction, &moved, user_material_ids, sale_options)
+            .map(|p| p.margin)
+            .ok_or_else(missing)
+    };
+
+    let change = change_percent / 100.0;
+    let mut inputs = Vec::new();
+    for input in &base.inputs {
+        let margin_if_up = margin_with(&input.name, 1.0 + change)?;
+        let margin_if_down = margin_with(&input.name, 1.0 - change)?;
+        inputs.push(InputSensitivity {
+            name: input.name.clone(),
+            cost_share: if base.input_cost > 0.0 {
+                input.total_price / base.input_cost
+            } else {
+                0.0
+            },
+            margin_if_up,
+            margin_if_down,
+            margin_swing: (margin_if_down - margin_if_up).abs() / 2.0,
+        });
+    }
+    inputs.sort_by(|a, b| {
+        b.margin_swing
+            .partial_cmp(&a.margin_swing)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    let dominant_input = inputs
+        .iter()
+        .max_by(|a, b| {
+            a.cost_share
+                .partial_cmp(&b.cost_share)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|i| i.name.clone());
+
+    Ok(SensitivityReport {
+        formula_id: reaction.formula_id,
+        formula_name: reaction.formula_name.clone(),
+        change_percent,
+        margin: base.margin,
+        inputs,
+        dominant_input,
+    })
+}
@@ -0,0 +1,243 @@
+use serde::Deserialize;
+use std::io::Write;
+use std::path::Path;
+
+use crate::prices::ReactionProfit;
+use crate::reaction_tree::{ReactionTreeNode, SourceType};
+
+/// File format analysis results are exported in
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// One row per reaction, inputs summarized in one column
+    #[default]
+    Csv,
+    /// The results as the app returns them, without trees
+    Json,
+}
+
+const CSV_HEADER: &[&str] = &[
+    "formula_id",
+    "formula_name",
+    "output_name",
+    "output_quantity",
+    "output_unit_price",
+    "output_value",
+    "output_fees",
+    "input_cost",
+    "import_hauling_cost",
+    "export_hauling_cost",
+    "profit",
+    "margin",
+    "cash_profit",
+    "cash_margin",
+    "isk_per_day",
+    "isk_per_m3",
+    "breakeven_price",
+    "price_headroom",
+    "uses_user_materials",
+    "inputs",
+];
+
+/// Write analysis results to a file, creating its folder if needed
+pub fn export_results(
+    path: &Path,
+    results: &[ReactionProfit],
+    format: ExportFormat,
+) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let file = std::fs::File::create(path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut out = std::io::BufWriter::new(file);
+    write_results(&mut out, results, format)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+pub fn write_results(
+    out: &mut impl Write,
+    results: &[ReactionProfit],
+    format: ExportFormat,
+) -> std::io::Result<()> {
+    match format {
+        ExportFormat::Csv => write_csv(out, results),
+        ExportFormat::Json => {
+            let results: Vec<ReactionProfit> = results
+                .iter()
+                .map(|result| ReactionProfit {
+                    reaction_tree: None,
+                    ..result.clone()
+                })
+                .collect();
+            serde_json::to_writer_pretty(&mut *out, &results)?;
+            writeln!(out)
+        }
+    }
+}
+
+fn write_csv(out: &mut impl Write, results: &[ReactionProfit]) -> std::io::Result<()> {
+    writeln!(out, "{}", CSV_HEADER.join(","))?;
+    for result in results {
+        let inputs = result
+            .inputs
+            .iter()
+            .map(|input| format!("{} x{}", input.name, input.quantity))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let row = [
+            result.formula_id.to_string(),
+            csv_field(&result.formula_name),
+            csv_field(&result.output_name),
+            result.output_quantity.to_string(),
+            result.output_unit_price.to_string(),
+            result.output_value.to_string(),
+            result.output_fees.to_string(),
+            result.input_cost.to_string(),
+            result.import_hauling_cost.to_string(),
+            result.export_hauling_cost.to_string(),
+            result.profit.to_string(),
+            result.margin.to_string(),
+            result.cash_profit.to_string(),
+            result.cash_margin.to_string(),
+            result.isk_per_day.to_string(),
+            result.isk_per_m3.to_string(),
+            result.breakeven_price.to_string(),
+            result.price_headroom.to_string(),
+            result.uses_user_materials.to_string(),
+            csv_field(&inputs),
+        ];
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quote a field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Render a reaction tree as a Graphviz digraph, each material pointing at what it's used
+/// for and filled with the color of its source
+pub fn tree_to_dot(tree: &ReactionTreeNode) -> String {
+    let mut lines = vec![
+        "digraph reaction_tree {".to_string(),
+        "    rankdir=LR;".to_string(),
+        "    node [shape=box, style=\"rounded,filled\", fontname=\"Helvetica\"];".to_string(),
+    ];
+    let mut next_id = 0;
+    dot_node(tree, &mut next_id, &mut lines);
+    lines.push("}".to_string());
+    lines.join("\n") + "\n"
+}
+
+/// Add a node and its inputs, returning the node's ID
+fn dot_node(node: &ReactionTreeNode, next_id: &mut usize, lines: &mut Vec<String>) -> String {
+    let id = format!("n{}", next_id);
+    *next_id += 1;
+
+    let mut label = format!("{}\\nx{}", node.name, node.quantity);
+    if let Some(reaction) = &node.reaction_name {
+        label.push_str(&format!("\\n{}", reaction));
+    }
+    if node.from_stock > 0 {
+        label.push_str(&format!("\\n{} from stock", node.from_stock));
+    }
+    label.push_str(&format!("\\n{:.0} ISK", node.total_price));
+    lines.push(format!(
+        "    {} [label=\"{}\", fillcolor=\"{}\"];",
+        id,
+        label.replace('"', "\\\""),
+        source_color(&node.source)
+    ));
+
+    for child in &node.children {
+        let child_id = dot_node(child, next_id, lines);
+        lines.push(format!("    {} -> {};", child_id, id));
+    }
+    id
+}
+
+fn source_color(source: &SourceType) -> &'static str {
+    match source {
+        SourceType::Moon => "#a5d6a7",
+        SourceType::Buy => "#ffcc80",
+        SourceType::React => "#90caf9",
+        SourceType::Output => "#fff59d",
+        SourceType::Stock => "#e0e0e0",
+    }
+}
+
+/// Render a reaction tree as indented plain text for mails and bulletins, one line per
+/// material with its quantity, source and value, ISK written the way the game does
+pub fn tree_to_text(tree: &ReactionTreeNode) -> String {
+    let mut lines = Vec::new();
+    text_node(tree, 0, &mut lines);
+    lines.join("\n")
+}
+
+fn text_node(node: &ReactionTreeNode, depth: usize, lines: &mut Vec<String>) {
+    let source = match node.source {
+        SourceType::Moon => "Moon",
+        SourceType::Buy => "Buy",
+        SourceType::React => "React",
+        SourceType::Output => "Output",
+        SourceType::Stock => "Stock",
+    };
+    let mut line = format!(
+        "{}{} x {} [{}] {} ISK",
+        "  ".repeat(depth),
+        node.name,
+        group_digits(node.quantity as u64),
+        source,
+        format_isk_full(node.total_price)
+    );
+    if node.from_stock > 0 && node.source != SourceType::Stock {
+        line.push_str(&format!(
+            " ({} from stock)",
+            group_digits(node.from_stock as u64)
+        ));
+    }
+    lines.push(line);
+
+    for child in &node.children {
+        text_node(child, depth + 1, lines);
+    }
+}
+
+/// ISK with thousands separators and two decimals, e.g. 1,234,567.89
+fn format_isk_full(value: f64) -> String {
+    let cents = (value.abs() * 100.0).round() as u64;
+    let sign = if value < 0.0 && cents > 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, group_digits(cents / 100), cents % 100)
+}
+
+fn group_digits(value: u64) -> String {
+    let digits = value.to_string();
+    let mut out = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(digit);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_isk_is_written_with_thousands_separators() {
+        assert_eq!(format_isk_full(1_234_567.891), "1,234,567.89");
+        assert_eq!(format_isk_full(999.0), "999.00");
+        assert_eq!(format_isk_full(-1000.5), "-1,000.50");
+    }
+}
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+
+use crate::prices::PriceInfo;
+use crate::transport::HaulingRate;
+
+/// How reaction outputs are sold
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputDestination {
+    /// List sell orders: sell price, minus broker fee and sales tax
+    #[default]
+    SellOrder,
+    /// Sell into buy orders right away: buy price, minus sales tax only
+    ImmediateSell,
+}
+
+/// Per-analysis options for valuing outputs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SaleOptions {
+    pub destination: OutputDestination,
+    /// Broker fee charged when placing a sell order, in percent
+    pub broker_fee_percent: f64,
+    /// Sales tax charged on every sale, in percent
+    pub sales_tax_percent: f64,
+    /// How often a sell order gets undercut and has to be relisted
+    pub relists_per_day: f64,
+    /// How long a sell order takes to fill completely
+    pub sale_duration_days: f64,
+    /// Freight for bringing bought inputs to the refinery and taking the output to market
+    pub hauling: HaulingRate,
+}
+
+impl Default for SaleOptions {
+    fn default() -> Self {
+        Self {
+            destination: OutputDestination::SellOrder,
+            broker_fee_percent: 3.0,
+            sales_tax_percent: 3.6,
+            relists_per_day: 1.0,
+            sale_duration_days: 3.0,
+            hauling: HaulingRate::default(),
+        }
+    }
+}
+
+/// Value of selling a quantity of output, broken down by fee
+#[derive(Debug, Clone, Serialize)]
+pub struct OutputSale {
+    pub unit_price: f64,
+    pub gross_value: f64,
+    pub broker_fee: f64,
+    /// Expected broker fees paid for relisting after being undercut
+    pub relist_fees: f64,
+    pub sales_tax: f64,
+    pub net_value: f64,
+}
+
+/// Value a quantity of output according to where it will be sold
+pub fn value_output(price: &PriceInfo, quantity: u32, options: &SaleOptions) -> OutputSale {
+    let (unit_price, broker_fee_percent) = match options.destination {
+        OutputDestination::SellOrder => (price.sell, options.broker_fee_percent),
+        OutputDestination::ImmediateSell => (price.buy, 0.0),
+    };
+
+    let gross_value = unit_price * quantity as f64;
+    let broker_fee = gross_value * broker_fee_percent / 100.0;
+    let sales_tax = gross_value * options.sales_tax_percent / 100.0;
+
+    // Each relist pays the broker fee again on what is still unsold; on average
+    // half the order remains when it gets undercut
+    let relists = (options.relists_per_day * options.sale_duration_days).max(0.0);
+    let relist_fees = broker_fee * 0.5 * relists;
+
+    OutputSale {
+        unit_price,
+        gross_value,
+        broker_fee,
+        relist_fees,
+        sales_tax,
+        net_value: gross_value - broker_fee - relist_fees - sales_tax,
+    }
+}
@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+
+use crate::datasets;
+use crate::prices::now_unix;
+use crate::storage;
+
+const THRESHOLDS_FILE: &str = "staleness.json";
+
+/// How old data may get before responses flag it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StalenessThresholds {
+    /// Price age in seconds after which a warning is raised
+    pub price_warning_secs: u64,
+    /// Price age in seconds after which the numbers shouldn't be trusted
+    pub price_critical_secs: u64,
+}
+
+impl Default for StalenessThresholds {
+    fn default() -> Self {
+        Self {
+            price_warning_secs: 6 * 60 * 60,
+            price_critical_secs: 48 * 60 * 60,
+        }
+    }
+}
+
+impl StalenessThresholds {
+    pub fn load() -> Self {
+        storage::load_json(THRESHOLDS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(THRESHOLDS_FILE, self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Fresh,
+    Warning,
+    Critical,
+}
+
+/// Where the data behind a response came from and how old it is
+#[derive(Debug, Clone, Serialize)]
+pub struct DataFreshness {
+    pub price_source: String,
+    pub prices_fetched_at: Option<u64>,
+    pub price_age_secs: Option<u64>,
+    /// Version of the reaction and ore data in use
+    pub data_version: String,
+    /// When the reaction and ore data was last synced; None for the bundled data
+    pub data_synced_at: Option<u64>,
+    /// Prices came from the last kept snapshot because the price source was unreachable
+    pub stale: bool,
+    pub severity: Severity,
+    pub warnings: Vec<String>,
+}
+
+impl DataFreshness {
+    /// Assess prices fetched at `prices_fetched_at` from `price_source` against the thresholds
+    pub fn assess(
+        prices_fetched_at: Option<u64>,
+        price_source: String,
+        thresholds: &StalenessThresholds,
+    ) -> Self {
+        let price_age_secs = prices_fetched_at.map(|at| now_unix().saturating_sub(at));
+        let mut warnings = Vec::new();
+
+        let severity = match price_age_secs {
+            None => {
+                warnings.push("No prices have been fetched yet".to_string());
+                Severity::Critical
+            }
+            Some(age) if age >= thresholds.price_critical_secs => {
+                warnings.push(format!("Prices are {} old", format_age(age)));
+                Severity::Critical
+            }
+            Some(age) if age >= thresholds.price_warning_secs => {
+                warnings.push(format!("Prices are {} old", format_age(age)));
+                Severity::Warning
+            }
+            Some(_) => Severity::Fresh,
+        };
+
+        let dataset = datasets::installed();
+        Self {
+            price_source,
+            prices_fetched_at,
+            price_age_secs,
+            data_version: dataset.version,
+            data_synced_at: dataset.installed_at,
+            stale: false,
+            severity,
+            warnings,
+        }
+    }
+}
+
+impl DataFreshness {
+    /// Flag prices taken from the last kept snapshot while offline
+    pub fn offline(mut self) -> Self {
+        self.stale = true;
+        self.severity = self.severity.max(Severity::Warning);
+        self.warnings.insert(
+            0,
+            "Price source unreachable, showing the last kept prices".to_string(),
+        );
+        self
+    }
+}
+
+/// A response together with the freshness of the data it was computed from
+#[derive(Debug, Clone, Serialize)]
+pub struct WithFreshness<T> {
+    pub data: T,
+    pub freshness: DataFreshness,
+}
+
+/// Format an age in seconds as e.g. "3h 20m" or "2d 4h"
+fn format_age(secs: u64) -> String {
+    let minutes = secs / 60;
+    let hours = minutes / 60;
+    let days = hours / 24;
+    if days > 0 {
+        format!("{}d {}h", days, hours % 24)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
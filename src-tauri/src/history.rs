@@ -0,0 +1,236 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+use crate::analysis::{AnalysisFilters, Page};
+use crate::fees::SaleOptions;
+use crate::prices::{now_unix, PriceSnapshot, ReactionProfit};
+use crate::storage;
+
+const HISTORY_DIR: &str = "analyses";
+/// Results kept per stored analysis, best first
+const HISTORY_TOP_RESULTS: usize = 50;
+/// Stored analyses kept; older ones are deleted as new ones are saved
+const MAX_STORED_ANALYSES: usize = 200;
+const LAST_ANALYSIS_FILE: &str = "last_analysis.json";
+
+/// The most recent analysis in full, with the prices it ran against, kept so it can be
+/// shown again after a restart without refetching and recomputing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LastAnalysis {
+    pub timestamp: u64,
+    pub results: Vec<ReactionProfit>,
+    pub snapshot: PriceSnapshot,
+}
+
+impl LastAnalysis {
+    pub fn load() -> Option<Self> {
+        storage::load_json(&storage::profile_file(LAST_ANALYSIS_FILE))
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(LAST_ANALYSIS_FILE), self)
+    }
+}
+
+/// A page of the most recent analysis and when it ran
+#[derive(Debug, Clone, Serialize)]
+pub struct LastAnalysisPage {
+    pub analyzed_at: u64,
+    #[serde(flatten)]
+    pub page: Page<ReactionProfit>,
+}
+
+/// Profit figures for one reaction in a stored analysis
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReactionSummary {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub output_name: String,
+    pub profit: f64,
+    pub margin: f64,
+}
+
+/// What an analysis was run with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisSettings {
+    pub price_source: String,
+    pub sale_options: SaleOptions,
+    pub filters: AnalysisFilters,
+}
+
+/// A persisted analysis run: its settings and top results, stored without trees to keep
+/// files small
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisRecord {
+    pub id: String,
+    pub timestamp: u64,
+    /// Missing for analyses stored before settings were recorded
+    #[serde(default)]
+    pub settings: Option<AnalysisSettings>,
+    pub results: Vec<ReactionSummary>,
+}
+
+/// Identifying details of a stored analysis
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisInfo {
+    pub id: String,
+    pub timestamp: u64,
+    pub reaction_count: usize,
+    pub price_source: Option<String>,
+}
+
+impl AnalysisRecord {
+    pub fn new(profits: &[ReactionProfit], settings: AnalysisSettings) -> Self {
+        let timestamp = now_unix();
+        Self {
+            id: format!(
+                "{}-{}",
+                timestamp,
+                &uuid::Uuid::new_v4().simple().to_string()[..8]
+            ),
+            timestamp,
+            settings: Some(settings),
+            results: profits
+                .iter()
+                .take(HISTORY_TOP_RESULTS)
+                .map(|p| ReactionSummary {
+                    formula_id: p.formula_id,
+                    formula_name: p.formula_name.clone(),
+                    output_name: p.output_name.clone(),
+                    profit: p.profit,
+                    margin: p.margin,
+                })
+                .collect(),
+        }
+    }
+
+    /// Store the record, deleting the oldest ones beyond the history limit
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&record_file(&self.id), self)?;
+        for old in list().iter().skip(MAX_STORED_ANALYSES) {
+            if let Some(path) = storage::data_file(&record_file(&old.id)) {
+                let _ = fs::remove_file(path);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn load(id: &str) -> Result<Self, String> {
+        if id.contains(['/', '\\', '.']) {
+            return Err(format!("Invalid analysis ID '{}'", id));
+        }
+        storage::load_json(&record_file(id)).ok_or_else(|| format!("Analysis '{}' not found", id))
+    }
+}
+
+fn record_file(id: &str) -> String {
+    format!("{}/{}.json", HISTORY_DIR, id)
+}
+
+/// All stored analyses, newest first
+pub fn list() -> Vec<AnalysisInfo> {
+    let Some(dir) = storage::data_file(HISTORY_DIR) else {
+        return Vec::new();
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut infos: Vec<AnalysisInfo> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let id = path.file_stem()?.to_str()?;
+            let record = AnalysisRecord::load(id).ok()?;
+            Some(AnalysisInfo {
+                id: record.id,
+                timestamp: record.timestamp,
+                reaction_count: record.results.len(),
+                price_source: record.settings.map(|settings| settings.price_source),
+            })
+        })
+        .collect();
+    infos.sort_by_key(|info| std::cmp::Reverse(info.timestamp));
+    infos
+}
+
+/// Change in one reaction's figures between two analyses
+#[derive(Debug, Clone, Serialize)]
+pub struct ReactionDelta {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub output_name: String,
+    /// None when the reaction wasn't profitable in that analysis
+    pub profit_a: Option<f64>,
+    pub profit_b: Option<f64>,
+    pub profit_delta: f64,
+    pub margin_a: Option<f64>,
+    pub margin_b: Option<f64>,
+    pub margin_delta: f64,
+}
+
+/// Per-reaction differences from analysis A to analysis B
+#[derive(Debug, Clone, Serialize)]
+pub struct AnalysisComparison {
+    pub a_id: String,
+    pub a_timestamp: u64,
+    pub b_id: String,
+    pub b_timestamp: u64,
+    pub deltas: Vec<ReactionDelta>,
+}
+
+/// Compare two analyses reaction by reaction, largest profit swings first
+pub fn compare(a: &AnalysisRecord, b: &AnalysisRecord) -> AnalysisComparison {
+    let a_by_id: HashMap<u32, &ReactionSummary> =
+        a.results.iter().map(|r| (r.formula_id, r)).collect();
+    let b_by_id: HashMap<u32, &ReactionSummary> =
+        b.results.iter().map(|r| (r.formula_id, r)).collect();
+
+    let mut formula_ids: Vec<u32> = a_by_id.keys().chain(b_by_id.keys()).copied().collect();
+    formula_ids.sort_unstable();
+    formula_ids.dedup();
+
+    let mut deltas: Vec<ReactionDelta> = formula_ids
+        .into_iter()
+        .map(|formula_id| {
+            let ra = a_by_id.get(&formula_id);
+            let rb = b_by_id.get(&formula_id);
+            let summary = ra
+                .or(rb)
+                .expect("formula ID comes from one of the analyses");
+            let profit_a = ra.map(|r| r.profit);
+            let profit_b = rb.map(|r| r.profit);
+            let margin_a = ra.map(|r| r.margin);
+            let margin_b = rb.map(|r| r.margin);
+            ReactionDelta {
+                formula_id,
+                formula_name: summary.formula_name.clone(),
+                output_name: summary.output_name.clone(),
+                profit_delta: profit_b.unwrap_or(0.0) - profit_a.unwrap_or(0.0),
+                margin_delta: margin_b.unwrap_or(0.0) - margin_a.unwrap_or(0.0),
+                profit_a,
+                profit_b,
+                margin_a,
+                margin_b,
+            }
+        })
+        .collect();
+
+    deltas.sort_by(|x, y| {
+        y.profit_delta
+            .abs()
+            .partial_cmp(&x.profit_delta.abs())
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    AnalysisComparison {
+        a_id: a.id.clone(),
+        a_timestamp: a.timestamp,
+        b_id: b.id.clone(),
+        b_timestamp: b.timestamp,
+        deltas,
+    }
+}
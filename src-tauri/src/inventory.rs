@@ -0,0 +1,131 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::appraisal::parse_quantity;
+use crate::storage;
+
+const INVENTORY_FILE: &str = "inventory.json";
+
+/// Parse a copy of an inventory or item window ("Name<TAB>Quantity<TAB>Group<TAB>..."),
+/// adding up stacks of the same item. Single items are copied without a quantity and count
+/// as one; the column header and rows that aren't tab separated are skipped
+pub fn parse_item_window(input: &str) -> Vec<(String, u64)> {
+    let mut items: Vec<(String, u64)> = Vec::new();
+
+    for line in input.lines() {
+        let mut fields = line.split('\t').map(str::trim);
+        let (Some(name), Some(quantity)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        let quantity = match quantity {
+            "" => 1,
+            text => match parse_quantity(text) {
+                Some(quantity) => quantity,
+                // The header row, or something that isn't an item row
+                None => continue,
+            },
+        };
+        // Blueprint copies are marked with a trailing asterisk
+        let name = name.trim_end_matches('*').trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        match items.iter_mut().find(|(existing, _)| existing == name) {
+            Some((_, total)) => *total += quantity,
+            None => items.push((name.to_string(), quantity)),
+        }
+    }
+
+    items
+}
+
+/// Quantity of one item in stock
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockEntry {
+    pub name: String,
+    pub quantity: u64,
+}
+
+/// Materials the user already has on hand, keyed by item name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    stock: HashMap<String, u64>,
+}
+
+impl Inventory {
+    /// Load the persisted inventory, or start empty
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(INVENTORY_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(INVENTORY_FILE), self)
+    }
+
+    /// Set the quantity of an item; zero removes it
+    pub fn set(&mut self, name: &str, quantity: u64) {
+        if quantity == 0 {
+            self.stock.remove(name);
+        } else {
+            self.stock.insert(name.to_string(), quantity);
+        }
+    }
+
+    /// Add to the quantity of an item
+    pub fn add(&mut self, name: &str, quantity: u64) {
+        *self.stock.entry(name.to_string()).or_insert(0) += quantity;
+    }
+
+    pub fn clear(&mut self) {
+        self.stock.clear();
+    }
+
+    /// All stock entries, sorted by name
+    pub fn entries(&self) -> Vec<StockEntry> {
+        let mut entries: Vec<StockEntry> = self
+            .stock
+            .iter()
+            .map(|(name, quantity)| StockEntry {
+                name: name.clone(),
+                quantity: *quantity,
+            })
+            .collect();
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        entries
+    }
+
+    /// Stock keyed by item ID, for items the name lookup knows about
+    pub fn by_id(&self, name_to_id: &HashMap<String, u32>) -> HashMap<u32, u32> {
+        self.stock
+            .iter()
+            .filter_map(|(name, quantity)| {
+                let id = name_to_id.get(name)?;
+                Some((*id, (*quantity).min(u32::MAX as u64) as u32))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_item_window() {
+        let input = "Name\tQuantity\tGroup\tCategory\tSize\tSlot\tVolume\tEst. Price\n\
+                     Hafnium\t12,000\tMoon Materials\tMaterial\t\t\t1,200 m3\t21,000,000 ISK\n\
+                     Fulleride Reaction Formula*\t\tReaction Formulas\tBlueprint\t\t\t0.01 m3\t\n\
+                     Hafnium\t500\tMoon Materials\tMaterial\t\t\t50 m3\t875,000 ISK\n";
+
+        let items = parse_item_window(input);
+
+        assert_eq!(
+            items,
+            vec![
+                ("Hafnium".to_string(), 12_500),
+                ("Fulleride Reaction Formula".to_string(), 1),
+            ]
+        );
+    }
+}
@@ -0,0 +1,258 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::appraisal::parse_quantity;
+use crate::ore_mappings::OreMappings;
+use crate::parser::MoonComposition;
+use crate::storage;
+
+const LEDGER_FILE: &str = "mining_ledger.json";
+
+/// One row of the personal mining ledger: ore mined in a system on a day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    /// Day mined, as `YYYY.MM.DD`
+    pub date: String,
+    pub ore: String,
+    pub system: String,
+    pub quantity: u64,
+}
+
+/// Parse a copy of the in-game personal mining ledger. Rows are tab separated and the
+/// columns are found by their contents (a `YYYY.MM.DD` date, the first whole number as the
+/// quantity, the first text as the ore and the last text as the system), so extra columns
+/// such as volume and estimated price are ignored. Rows that don't fit, like the header,
+/// are skipped
+pub fn parse_ledger(input: &str) -> Vec<LedgerEntry> {
+    input.lines().filter_map(parse_ledger_line).collect()
+}
+
+fn parse_ledger_line(line: &str) -> Option<LedgerEntry> {
+    let mut date = None;
+    let mut quantity = None;
+    let mut text = Vec::new();
+
+    for field in line.split('\t').map(str::trim).filter(|f| !f.is_empty()) {
+        if date.is_none() && is_ledger_date(field) {
+            date = Some(field[..10].to_string());
+        } else if let Some(number) = parse_quantity(field) {
+            quantity = quantity.or(Some(number));
+        } else if !is_measurement(field) {
+            text.push(field);
+        }
+    }
+
+    if text.len() < 2 {
+        return None;
+    }
+    Some(LedgerEntry {
+        date: date?,
+        ore: text[0].to_string(),
+        system: text[text.len() - 1].to_string(),
+        quantity: quantity?,
+    })
+}
+
+fn is_ledger_date(field: &str) -> bool {
+    let bytes = field.as_bytes();
+    bytes.len() >= 10
+        && bytes[..10].iter().enumerate().all(|(i, b)| match i {
+            4 | 7 => *b == b'.',
+            _ => b.is_ascii_digit(),
+        })
+}
+
+// Volume and price columns, e.g. "123,450 m3" or "1,234,567 ISK"
+fn is_measurement(field: &str) -> bool {
+    field.starts_with(|c: char| c.is_ascii_digit())
+        && (field.ends_with("m3") || field.ends_with("m³") || field.ends_with("ISK"))
+}
+
+/// Ore mined from one moon
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractedOre {
+    pub ore: String,
+    pub units: f64,
+    pub volume_m3: f64,
+}
+
+/// Everything mined from one moon according to the ledger
+#[derive(Debug, Clone, Serialize)]
+pub struct MoonExtraction {
+    pub moon: String,
+    pub ores: Vec<ExtractedOre>,
+    pub total_units: f64,
+    pub total_m3: f64,
+}
+
+/// Ledger totals by moon, with the entries that couldn't be matched to a loaded moon
+#[derive(Debug, Clone, Serialize)]
+pub struct LedgerSummary {
+    pub moons: Vec<MoonExtraction>,
+    pub unmatched: Vec<LedgerEntry>,
+}
+
+/// Mining ledger rows imported so far
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MiningLedger {
+    entries: Vec<LedgerEntry>,
+}
+
+impl MiningLedger {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(LEDGER_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(LEDGER_FILE), self)
+    }
+
+    /// Add entries, replacing any for the same day, ore and system so overlapping pastes
+    /// aren't counted twice. Returns how many entries were new
+    pub fn import(&mut self, entries: Vec<LedgerEntry>) -> usize {
+        let mut added = 0;
+        for entry in entries {
+            let existing = self
+                .entries
+                .iter_mut()
+                .find(|e| e.date == entry.date && e.ore == entry.ore && e.system == entry.system);
+            match existing {
+                Some(existing) => *existing = entry,
+                None => {
+                    self.entries.push(entry);
+                    added += 1;
+                }
+            }
+        }
+        added
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Fold ledger quantities into per-moon totals. Moons are matched by system (moon names
+    /// start with the system name) and by carrying the mined ore; when several moons in a
+    /// system have the ore, units are split by each moon's share of it
+    pub fn by_moon(&self, moons: &[MoonComposition], ore_mappings: &OreMappings) -> LedgerSummary {
+        let mut mined: Vec<HashMap<String, f64>> = vec![HashMap::new(); moons.len()];
+        let mut unmatched = Vec::new();
+
+        for entry in &self.entries {
+            let base_ore = ore_mappings.base_ore_name(&entry.ore);
+            let system_prefix = format!("{} ", entry.system);
+            let candidates: Vec<(usize, f64)> = moons
+                .iter()
+                .enumerate()
+                .filter(|(_, moon)| moon.name.starts_with(&system_prefix))
+                .filter_map(|(index, moon)| {
+                    let share: f64 = moon
+                        .materials
+                        .iter()
+                        .filter(|m| ore_mappings.base_ore(m) == base_ore)
+                        .map(|m| m.quantity)
+                        .sum();
+                    (share > 0.0).then_some((index, share))
+                })
+                .collect();
+
+            let total_share: f64 = candidates.iter().map(|(_, share)| share).sum();
+            if candidates.is_empty() {
+                unmatched.push(entry.clone());
+                continue;
+            }
+            for (index, share) in candidates {
+                *mined[index].entry(base_ore.clone()).or_insert(0.0) +=
+                    entry.quantity as f64 * share / total_share;
+            }
+        }
+
+        let moons = moons
+            .iter()
+            .zip(mined)
+            .filter(|(_, ores)| !ores.is_empty())
+            .map(|(moon, ores)| {
+                let mut ores: Vec<ExtractedOre> = ores
+                    .into_iter()
+                    .map(|(ore, units)| ExtractedOre {
+                        volume_m3: units * ore_mappings.ore_volume_m3(&ore),
+                        ore,
+                        units,
+                    })
+                    .collect();
+                ores.sort_by(|a, b| a.ore.cmp(&b.ore));
+                MoonExtraction {
+                    moon: moon.name.clone(),
+                    total_units: ores.iter().map(|o| o.units).sum(),
+                    total_m3: ores.iter().map(|o| o.volume_m3).sum(),
+                    ores,
+                }
+            })
+            .collect();
+
+        LedgerSummary { moons, unmatched }
+    }
+
+    /// Set each loaded moon's extracted units from the ledger, splitting an ore between
+    /// its variants on the moon by their share. Returns how many materials were updated
+    pub fn apply_to_moons(
+        &self,
+        moons: &mut [MoonComposition],
+        ore_mappings: &OreMappings,
+    ) -> usize {
+        let summary = self.by_moon(moons, ore_mappings);
+        let mut updated = 0;
+        for extraction in summary.moons {
+            let Some(moon) = moons.iter_mut().find(|m| m.name == extraction.moon) else {
+                continue;
+            };
+            for ore in extraction.ores {
+                let share: f64 = moon
+                    .materials
+                    .iter()
+                    .filter(|m| ore_mappings.base_ore(m) == ore.ore)
+                    .map(|m| m.quantity)
+                    .sum();
+                for material in &mut moon.materials {
+                    if ore_mappings.base_ore(material) == ore.ore && share > 0.0 {
+                        material.extracted_units = Some(ore.units * material.quantity / share);
+                        updated += 1;
+                    }
+                }
+            }
+        }
+        updated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ledger_skips_header_and_extra_columns() {
+        let input = "Date\tOre Type\tQuantity\tVolume\tEst. Price\tSolar System\n\
+                     2025.03.02\tBitumens\t12,500\t125,000 m3\t2,500,000 ISK\tOP9L-F\n\
+                     2025.03.02 11:00\tGlistening Sylvite\t800\t8,000 m3\t\tOP9L-F\n";
+
+        let entries = parse_ledger(input);
+
+        assert_eq!(
+            entries,
+            vec![
+                LedgerEntry {
+                    date: "2025.03.02".to_string(),
+                    ore: "Bitumens".to_string(),
+                    system: "OP9L-F".to_string(),
+                    quantity: 12_500,
+                },
+                LedgerEntry {
+                    date: "2025.03.02".to_string(),
+                    ore: "Glistening Sylvite".to_string(),
+                    system: "OP9L-F".to_string(),
+                    quantity: 800,
+                },
+            ]
+        );
+    }
+}
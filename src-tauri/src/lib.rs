@@ -1,89 +1,463 @@
-use std::collections::HashSet;
-use std::sync::Mutex;
-use tauri::{Manager, State};
-
-mod ore_mappings;
-mod parser;
-mod prices;
-mod reaction_tree;
-mod reactions;
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_notification::NotificationExt;
+use tokio::sync::{RwLock, RwLockWriteGuard};
+
+mod alerts;
+pub mod analysis;
+mod announcements;
+mod appraisal;
+mod backup;
+mod bonuses;
+mod crash;
+mod datasets;
+mod detail;
+mod esi_auth;
+mod explain;
+mod export;
+pub mod fees;
+mod freshness;
+mod history;
+mod inventory;
+mod ledger;
+mod logging;
+mod memory;
+mod migrations;
+mod moonpack;
+pub mod ore_mappings;
+pub mod parser;
+pub mod pins;
+mod planner;
+pub mod prices;
+pub mod profiles;
+pub mod reaction_tree;
+pub mod reactions;
+mod reports;
+mod schedule;
+mod schema;
+mod search;
+pub mod settings;
+mod shopping;
+mod slots;
+mod stockpile;
+mod storage;
+mod structures;
+mod substitution;
+mod sync;
 mod telemetry;
+mod transport;
+mod universe;
+mod updates;
+mod valuation;
 
+use alerts::{Crossing, MarginAlert, MarginAlerts};
+use analysis::{
+    AnalysisFilters, AnalysisProgress, AnalysisStage, CancelToken, Page, ResultGrouping, ResultSort,
+};
+use announcements::{AnnouncementCache, AnnouncementEntry};
+use appraisal::{Appraisal, AppraisalService};
+use backup::BackupSummary;
+use bonuses::{BonusScenario, ScenarioResult};
+use datasets::DatasetInfo;
+use detail::{DetailTarget, DetailView, DetailWindows};
+use esi_auth::{EsiAuth, EsiAuthStatus};
+use export::ExportFormat;
+use fees::SaleOptions;
+use freshness::{DataFreshness, StalenessThresholds, WithFreshness};
+use history::{
+    AnalysisComparison, AnalysisInfo, AnalysisRecord, AnalysisSettings, LastAnalysis,
+    LastAnalysisPage,
+};
+use inventory::{Inventory, StockEntry};
+use ledger::{LedgerSummary, MiningLedger};
+use memory::MemoryMode;
+use moonpack::{MoonPack, MoonPackImport};
 use ore_mappings::OreMappings;
-use prices::ReactionProfit;
-use reactions::ReactionDatabase;
+use pins::{PricePin, PricePins};
+use planner::{RecomputedTree, RunPlan, RunSummary, SteadyStatePlan};
+use prices::{
+    PriceAdjustments, PriceInfo, PriceSnapshot, ReactionProfit, SnapshotInfo, StructureMarket,
+};
+use profiles::ProfileRegistry;
+use reaction_tree::{CacheEpoch, SourceOverride, SubtreeCache, TreeContext};
+use reactions::{ReactionCategory, ReactionDatabase};
+use reports::{HtmlReport, HtmlReportOptions, WeeklyReportConfig};
+use schedule::{ExtractionSchedule, UpcomingExtraction};
+use search::SearchResult;
+use settings::Settings;
+use shopping::{BillOfMaterials, ShoppingList};
+use slots::{JobSchedule, ProductTarget};
+use stockpile::{InventoryValuation, StockpileHistory, StockpileValuation};
+use structures::{MoonStructure, MoonStructures, StructureExtractions};
+use substitution::MaxBuyHint;
+use sync::{SyncConfig, SyncReport};
+use telemetry::{LaunchPayload, TelemetryConsent};
+use updates::UpdateInfo;
+use valuation::{ExtractionYield, MoonSaleComparison, MoonScore, MoonValuation};
 
-// State to hold the loaded moons and reactions
+// State to hold the loaded moons and reactions. Async locks let long reads (like an
+// analysis) run without blocking the runtime, and the databases sit behind swappable
+// Arcs so they can be replaced while the app is running
 pub struct AppState {
-    moons: Mutex<Vec<parser::MoonComposition>>,
-    reactions_db: ReactionDatabase,
-    ore_mappings: OreMappings,
+    moons: RwLock<Vec<parser::MoonComposition>>,
+    price_cache: RwLock<Option<PriceSnapshot>>,
+    /// Full results of the most recent analysis, for paging and per-reaction details
+    analysis_results: RwLock<Vec<ReactionProfit>>,
+    /// Unix timestamp (seconds) of the most recent analysis
+    analyzed_at: RwLock<Option<u64>>,
+    inventory: RwLock<Inventory>,
+    mining_ledger: RwLock<MiningLedger>,
+    extraction_schedule: RwLock<ExtractionSchedule>,
+    staleness: RwLock<StalenessThresholds>,
+    analysis_cancel: CancelToken,
+    memory_mode: RwLock<MemoryMode>,
+    price_pins: RwLock<PricePins>,
+    reactions_db: RwLock<Arc<ReactionDatabase>>,
+    ore_mappings: RwLock<Arc<OreMappings>>,
+    subtree_cache: SubtreeCache,
+    settings: RwLock<Settings>,
+    detail_windows: DetailWindows,
+    structure_market: StructureMarket,
+}
+
+/// Write locks on the in-memory copies of the files kept per profile, taken in field order
+struct ProfileData<'a> {
+    moons: RwLockWriteGuard<'a, Vec<parser::MoonComposition>>,
+    inventory: RwLockWriteGuard<'a, Inventory>,
+    mining_ledger: RwLockWriteGuard<'a, MiningLedger>,
+    extraction_schedule: RwLockWriteGuard<'a, ExtractionSchedule>,
+    settings: RwLockWriteGuard<'a, Settings>,
+}
+
+impl ProfileData<'_> {
+    // Re-read every file from the active profile
+    fn reload(&mut self) {
+        *self.moons = profiles::load_moons();
+        *self.inventory = Inventory::load();
+        *self.mining_ledger = MiningLedger::load();
+        *self.extraction_schedule = ExtractionSchedule::load();
+        *self.settings = Settings::load();
+    }
+}
+
+impl AppState {
+    // Lock everything saved per profile, so no save can land while the active profile
+    // changes: one already under way finishes first and the rest wait for the new data
+    async fn lock_profile_data(&self) -> ProfileData<'_> {
+        ProfileData {
+            moons: self.moons.write().await,
+            inventory: self.inventory.write().await,
+            mining_ledger: self.mining_ledger.write().await,
+            extraction_schedule: self.extraction_schedule.write().await,
+            settings: self.settings.write().await,
+        }
+    }
+
+    // Get the current reactions database; callers keep using it even if it's replaced
+    async fn reactions_db(&self) -> Arc<ReactionDatabase> {
+        Arc::clone(&*self.reactions_db.read().await)
+    }
+
+    // Get the current ore mappings; callers keep using them even if they're replaced
+    async fn ore_mappings(&self) -> Arc<OreMappings> {
+        Arc::clone(&*self.ore_mappings.read().await)
+    }
 }
 
 // Parse moon scan data
 #[tauri::command]
-fn parse_moon_data(input: String) -> Result<Vec<parser::MoonComposition>, String> {
-    parser::parse_moon_data(&input).map_err(|e| e.to_string())
+fn parse_moon_data(input: String) -> Result<Vec<parser::MoonComposition>, parser::ParseError> {
+    parser::parse_moon_data(&input).inspect_err(|e| {
+        tracing::warn!(error = %e, "Failed to parse moon scan");
+    })
+}
+
+// Parse moon scan data, skipping lines that can't be parsed and reporting them as warnings
+// along with moons whose compositions look wrong
+#[tauri::command]
+async fn parse_moon_data_lenient(
+    input: String,
+    state: State<'_, AppState>,
+) -> Result<parser::ParseResult, parser::ParseError> {
+    let mut result =
+        parser::parse_moon_data_with(&input, parser::ParseMode::Lenient).inspect_err(|e| {
+            tracing::warn!(error = %e, "Failed to parse moon scan");
+        })?;
+    let ore_mappings = state.ore_mappings().await;
+    result
+        .warnings
+        .extend(parser::validate_compositions(&result.moons, |material| {
+            ore_mappings.is_known_ore(material)
+        }));
+    Ok(result)
 }
 
 // Add moon(s) to the state
 #[tauri::command]
-fn add_moon(
+async fn add_moon(
     moons_to_add: Vec<parser::MoonComposition>,
-    state: State<AppState>,
+    state: State<'_, AppState>,
 ) -> Result<(), String> {
-    let mut moons = state
-        .moons
-        .lock()
-        .map_err(|_| "Internal error: database lock failed".to_string())?;
+    let mut moons = state.moons.write().await;
 
-    // Check for duplicates
-    for new_moon in &moons_to_add {
-        if moons.iter().any(|m| m.name == new_moon.name) {
-            return Err(format!("Moon '{}' already exists", new_moon.name));
+    // Check for duplicates, by celestial IDs where the scans have them
+    for (i, new_moon) in moons_to_add.iter().enumerate() {
+        if let Some(existing) = moons.iter().find(|m| m.is_same_moon(new_moon)) {
+            return Err(if existing.name == new_moon.name {
+                format!("Moon '{}' already exists", new_moon.name)
+            } else {
+                format!(
+                    "Moon '{}' is already loaded as '{}'",
+                    new_moon.name, existing.name
+                )
+            });
+        }
+        if moons_to_add[..i].iter().any(|m| m.is_same_moon(new_moon)) {
+            return Err(format!("Moon '{}' is pasted twice", new_moon.name));
         }
     }
 
     for moon in moons_to_add {
         moons.push(moon);
     }
+    state.subtree_cache.invalidate();
 
-    Ok(())
+    profiles::save_moons(&moons)
 }
 
 // Delete moon by index
 #[tauri::command]
-fn delete_moon(index: usize, state: State<AppState>) -> Result<(), String> {
-    let mut moons = state
-        .moons
-        .lock()
-        .map_err(|_| "Internal error: database lock failed".to_string())?;
+async fn delete_moon(index: usize, state: State<'_, AppState>) -> Result<(), String> {
+    let mut moons = state.moons.write().await;
 
     if index >= moons.len() {
         return Err("Invalid moon index".to_string());
     }
 
     moons.remove(index);
-    Ok(())
+    state.subtree_cache.invalidate();
+    profiles::save_moons(&moons)
+}
+
+// Get all moons, with their ore tiers, and their system, region, security status and jumps
+// to the market hub where already known; the rest are looked up in the background
+#[tauri::command]
+async fn get_moons(state: State<'_, AppState>) -> Result<Vec<parser::MoonComposition>, String> {
+    let mut moons = state.moons.read().await.clone();
+    let ore_mappings = state.ore_mappings().await;
+    for moon in &mut moons {
+        ore_mappings.classify(moon);
+    }
+    let market = state.settings.read().await.market_hub;
+    locate_moons(&mut moons, market);
+    Ok(moons)
+}
+
+/// Hub prices with each moon region's local goo prices laid over them, by region ID.
+/// Regions nobody trades the goo in are left out, so callers fall back to hub prices
+async fn regional_goo_prices(
+    state: &AppState,
+    moons: &[parser::MoonComposition],
+    goo_names: &[String],
+    hub_prices: &HashMap<String, PriceInfo>,
+    mode: prices::PricingMode,
+) -> HashMap<u32, HashMap<String, PriceInfo>> {
+    let reactions_db = state.reactions_db().await;
+    let goo_items: Vec<(String, u32)> = goo_names
+        .iter()
+        .filter_map(|name| Some((name.clone(), *reactions_db.name_to_id.get(name)?)))
+        .collect();
+    let regions: HashSet<u32> = moons
+        .iter()
+        .filter_map(|moon| moon.location.as_ref())
+        .map(|location| location.region_id)
+        .collect();
+
+    let mut regional_prices = HashMap::new();
+    for region_id in regions {
+        match prices::fetch_regional_prices(region_id, &goo_items, mode).await {
+            Ok(local) if !local.is_empty() => {
+                let mut merged = hub_prices.clone();
+                prices::overlay_prices(&mut merged, local);
+                regional_prices.insert(region_id, merged);
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!(region_id, error = %e, "Using hub prices for region"),
+        }
+    }
+    regional_prices
+}
+
+/// Each goo the user mines priced where it's mined: the average over the regions of the
+/// moons yielding it, for valuing the user's own goo as reaction inputs
+async fn mined_goo_prices(
+    state: &AppState,
+    hub_prices: &HashMap<String, PriceInfo>,
+    mode: prices::PricingMode,
+) -> HashMap<String, PriceInfo> {
+    let mut moons = state.moons.read().await.clone();
+    let market = state.settings.read().await.market_hub;
+    locate_moons(&mut moons, market);
+
+    let ore_mappings = state.ore_mappings().await;
+    // Regions each goo is mined in
+    let mut goo_regions: HashMap<String, HashSet<u32>> = HashMap::new();
+    for moon in &moons {
+        let Some(location) = &moon.location else {
+            continue;
+        };
+        for goo in ore_mappings.ores_to_moon_goo(&moon.materials) {
+            goo_regions
+                .entry(goo)
+                .or_default()
+                .insert(location.region_id);
+        }
+    }
+    let goo_names: Vec<String> = goo_regions.keys().cloned().collect();
+    let regional = regional_goo_prices(state, &moons, &goo_names, hub_prices, mode).await;
+
+    goo_regions
+        .into_iter()
+        .filter_map(|(goo, regions)| {
+            let local: Vec<&PriceInfo> = regions
+                .iter()
+                .filter_map(|region_id| regional.get(region_id)?.get(&goo))
+                .collect();
+            if local.is_empty() {
+                return None;
+            }
+            let count = local.len() as f64;
+            Some((
+                goo,
+                PriceInfo {
+                    buy: local.iter().map(|price| price.buy).sum::<f64>() / count,
+                    sell: local.iter().map(|price| price.sell).sum::<f64>() / count,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Fill in where each moon is and how far it is from the market hub from the cache. What
+/// isn't cached yet is looked up in the background and shows up on a later call
+fn locate_moons(moons: &mut [parser::MoonComposition], market: prices::MarketHub) {
+    let universe = universe::UniverseCache::load();
+    universe::resolve_in_background(&universe, moons, market.system_id());
+    universe.enrich(moons, market.system_id());
+}
+
+// Set a moon's tags and notes
+#[tauri::command]
+async fn set_moon_notes(
+    name: String,
+    tags: Vec<String>,
+    notes: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let mut moons = state.moons.write().await;
+    let moon = moons
+        .iter_mut()
+        .find(|m| m.name == name)
+        .ok_or_else(|| format!("Moon '{}' isn't loaded", name))?;
+
+    moon.tags = tags
+        .iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    let mut seen = HashSet::new();
+    moon.tags.retain(|tag| seen.insert(tag.clone()));
+    moon.notes = notes;
+    profiles::save_moons(&moons)
+}
+
+// Bundle the loaded moons, their tags and notes, and their valuations (when prices can be
+// fetched) into one compressed .moonpack file for sharing
+#[tauri::command]
+async fn export_moonpack(path: String, state: State<'_, AppState>) -> Result<(), String> {
+    let valuations = match moon_valuations(&state).await {
+        Ok((valuations, _)) => valuations,
+        Err(e) => {
+            tracing::warn!(error = %e, "Exporting moon pack without valuations");
+            Vec::new()
+        }
+    };
+    let moons = state.moons.read().await.clone();
+    if moons.is_empty() {
+        return Err("No moons loaded. Add some moons first.".to_string());
+    }
+    MoonPack::new(moons, valuations).write(std::path::Path::new(&path))
+}
+
+// Merge a .moonpack file into the loaded moons
+#[tauri::command]
+async fn import_moonpack(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<MoonPackImport, String> {
+    let pack = MoonPack::read(std::path::Path::new(&path))?;
+    let mut moons = state.moons.write().await;
+    let import = pack.merge_into(&mut moons);
+    if !import.added.is_empty() {
+        state.subtree_cache.invalidate();
+    }
+    profiles::save_moons(&moons)?;
+    Ok(import)
+}
+
+// Get where moons are synced to
+#[tauri::command]
+fn get_sync_config() -> SyncConfig {
+    SyncConfig::load()
+}
+
+// Change where moons are synced to, keeping the time of the last sync
+#[tauri::command]
+fn set_sync_config(config: SyncConfig) -> Result<(), String> {
+    let last_synced_at = SyncConfig::load().last_synced_at;
+    SyncConfig {
+        last_synced_at,
+        ..config
+    }
+    .save()
 }
 
-// Get all moons
+// Merge the loaded moons with the synced copy and push the result
 #[tauri::command]
-fn get_moons(state: State<AppState>) -> Result<Vec<parser::MoonComposition>, String> {
-    let moons = state
-        .moons
-        .lock()
-        .map_err(|_| "Internal error: database lock failed".to_string())?;
-    Ok(moons.clone())
+async fn sync_moons(state: State<'_, AppState>) -> Result<SyncReport, String> {
+    let mut config = SyncConfig::load();
+    // Don't keep the moons locked while waiting on the server
+    let snapshot = state.moons.read().await.clone();
+    let (merged, report) = sync::sync_moons(&config, &snapshot).await?;
+
+    let mut moons = state.moons.write().await;
+    // Moons edited while the sync ran keep those edits; they go up with the next sync
+    let merged = if *moons == snapshot {
+        merged
+    } else {
+        sync::keep_local_edits(&snapshot, &moons, &merged)
+    };
+    if !report.pulled.is_empty() {
+        state.subtree_cache.invalidate();
+    }
+    *moons = merged;
+    profiles::save_moons(&moons)?;
+    config.last_synced_at = Some(report.synced_at);
+    config.save()?;
+    tracing::info!(
+        pulled = report.pulled.len(),
+        conflicts = report.conflicts.len(),
+        "Synced moons"
+    );
+    Ok(report)
 }
 
 // Get unique materials across all moons
 #[tauri::command]
-fn get_unique_materials(state: State<AppState>) -> Result<Vec<String>, String> {
-    let moons = state
-        .moons
-        .lock()
-        .map_err(|_| "Internal error: database lock failed".to_string())?;
+async fn get_unique_materials(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let moons = state.moons.read().await;
     let mut unique_materials: HashSet<String> = HashSet::new();
 
     for moon in moons.iter() {
@@ -97,30 +471,74 @@ fn get_unique_materials(state: State<AppState>) -> Result<Vec<String>, String> {
     Ok(materials_vec)
 }
 
-// Analyze reactions and find profitable ones based on available moon materials
+// Search moons, reactions, items and commands for the command palette
 #[tauri::command]
-async fn analyze_reactions(state: State<'_, AppState>) -> Result<Vec<ReactionProfit>, String> {
-    // Get ore names from loaded moons
-    let ore_names: Vec<String> = {
-        let moons = state
-            .moons
-            .lock()
-            .map_err(|_| "Internal error: database lock failed".to_string())?;
-        let mut ores: HashSet<String> = HashSet::new();
-        for moon in moons.iter() {
-            for material in &moon.materials {
-                ores.insert(material.name.clone());
-            }
-        }
-        ores.into_iter().collect()
+async fn search_everything(
+    query: String,
+    state: State<'_, AppState>,
+) -> Result<Vec<SearchResult>, String> {
+    let moons = state.moons.read().await;
+    Ok(search::search_everything(
+        &query,
+        &moons,
+        &*state.reactions_db().await,
+        &*state.ore_mappings().await,
+    ))
+}
+
+// Search reactions by formula, output or input name, optionally in one category only
+#[tauri::command]
+async fn search_reactions(
+    query: String,
+    category: Option<ReactionCategory>,
+    state: State<'_, AppState>,
+) -> Result<Vec<reactions::Reaction>, String> {
+    let reactions_db = state.reactions_db().await;
+    Ok(reactions_db
+        .search(&query)
+        .into_iter()
+        .filter(|r| category.is_none() || r.category == category)
+        .cloned()
+        .collect())
+}
+
+// Analyze reactions and find profitable ones based on available moon materials. Returns
+// `limit` results (all by default) from `offset` in `sort` order, without their trees,
+// and with every result's product family when `group_by` is given
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+async fn analyze_reactions(
+    sale_options: Option<SaleOptions>,
+    filters: Option<AnalysisFilters>,
+    sort: Option<ResultSort>,
+    offset: Option<usize>,
+    limit: Option<usize>,
+    group_by: Option<ResultGrouping>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Page<ReactionProfit>>, String> {
+    let sale_options = sale_options_or_default(&state, sale_options).await;
+    // Progress is best-effort; a closed window shouldn't fail the analysis
+    let report = |progress: AnalysisProgress| {
+        tracing::info!(stage = ?progress.stage, percent = progress.percent, "Analysis progress");
+        let _ = app.emit(analysis::PROGRESS_EVENT, progress);
     };
+    state.analysis_cancel.reset();
+    report(AnalysisProgress::new(AnalysisStage::CollectingGoo, 0, 1));
 
-    if ore_names.is_empty() {
-        return Err("No moons loaded. Add some moons first.".to_string());
-    }
+    let reactions_db = state.reactions_db().await;
+    let moon_goo: HashSet<String> = {
+        let moons = state.moons.read().await;
+        if moons.iter().all(|moon| moon.materials.is_empty()) {
+            return Err("No moons loaded. Add some moons first.".to_string());
+        }
 
-    // Convert ore names to moon goo materials (this is what reactions actually use)
-    let moon_goo: HashSet<String> = state.ore_mappings.ores_to_moon_goo(&ore_names);
+        // Convert ores to moon goo materials (this is what reactions actually use)
+        state
+            .ore_mappings()
+            .await
+            .ores_to_moon_goo(moons.iter().flat_map(|m| &m.materials))
+    };
 
     if moon_goo.is_empty() {
         return Err(
@@ -130,88 +548,2649 @@ async fn analyze_reactions(state: State<'_, AppState>) -> Result<Vec<ReactionPro
 
     // Get the IDs of user's moon materials (to mark which reactions use their materials)
     let moon_goo_vec: Vec<String> = moon_goo.into_iter().collect();
-    let user_material_ids = state.reactions_db.get_user_material_ids(&moon_goo_vec);
+    let user_material_ids = reactions_db.get_user_material_ids(&moon_goo_vec);
 
     // Get ALL item names for price lookup
-    let all_items = state.reactions_db.get_all_item_names();
+    let all_items = reactions_db.get_all_item_names();
 
     // Fetch prices from Goonpraisal
-    let prices = prices::fetch_prices(&all_items).await?;
+    state.analysis_cancel.check()?;
+    report(AnalysisProgress::new(AnalysisStage::FetchingPrices, 0, 1));
+    let (source, retry, regional) = {
+        let settings = state.settings.read().await;
+        (
+            settings.price_source(),
+            settings.price_retry,
+            settings.regional_moon_pricing,
+        )
+    };
+    let mut snapshot =
+        prices::fetch_snapshot(&all_items, source, retry, &state.structure_market).await?;
+    state.analysis_cancel.check()?;
+
+    // Keep a full fresh set of prices on disk for when the price source is unreachable
+    if !snapshot.stale {
+        if let Err(e) = snapshot.save_last() {
+            tracing::warn!(error = %e, "Failed to keep prices for offline use");
+        }
+        if let Err(e) = snapshot.archive() {
+            tracing::warn!(error = %e, "Failed to archive prices");
+        }
+    }
+
+    // The user's own goo is worth what it sells for where it's mined, not at the hub
+    if regional {
+        snapshot.regional_goo = mined_goo_prices(&state, &snapshot.prices, source.mode).await;
+        state.analysis_cancel.check()?;
+    }
+
+    // Keep the market prices around so individual results can be explained later
+    let priced = snapshot.clone().with_regional_goo();
+    let prices = state.price_pins.read().await.apply(&priced.prices);
+    *state.price_cache.write().await = Some(snapshot.clone());
+    state.subtree_cache.invalidate();
+
+    let stock = stock_by_id(&state, &reactions_db).await;
+    let memory_mode = state.memory_mode.read().await.clone();
+    let tree_ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &prices,
+        stock: &stock,
+        max_depth: memory_mode.tree_depth_cap(),
+        subtrees: None,
+        overrides: None,
+    };
+
+    let filters = filters.unwrap_or_default();
+    let mut profits = analysis::profitable_reactions(
+        &tree_ctx,
+        &sale_options,
+        &filters,
+        &state.analysis_cancel,
+        report,
+    )?;
+    if filters.limit_to_extracted_goo {
+        let available = extracted_goo_by_id(&state, &reactions_db).await?;
+        analysis::limit_to_available_goo(
+            &mut profits,
+            &reactions_db,
+            &user_material_ids,
+            &available,
+        );
+    }
+    analysis::sort_results(&mut profits, sort.unwrap_or_default(), &reactions_db);
+
+    // Keep a record of this run and the stockpile value at these prices for later
+    // comparison; a failed write shouldn't fail the analysis
+    if memory_mode.retain_history() {
+        let settings = AnalysisSettings {
+            price_source: source.describe(),
+            sale_options: sale_options.clone(),
+            filters,
+        };
+        let _ = AnalysisRecord::new(&profits, settings).save();
+        let mut stockpile_history = StockpileHistory::load();
+        stockpile_history.record(&goo_stock(&state).await, &prices);
+        let _ = stockpile_history.save();
+    }
+
+    let mut page = analysis::paginate(&profits, offset.unwrap_or_default(), limit);
+    if let Some(grouping) = group_by {
+        page.groups = analysis::group_results(&profits, grouping, &reactions_db);
+    }
+
+    // Keep the full results for the next launch
+    let last = LastAnalysis {
+        timestamp: prices::now_unix(),
+        results: profits,
+        snapshot,
+    };
+    if let Err(e) = last.save() {
+        tracing::warn!(error = %e, "Failed to keep the analysis for the next launch");
+    }
+    *state.analyzed_at.write().await = Some(last.timestamp);
+    *state.analysis_results.write().await = last.results;
+
+    Ok(WithFreshness {
+        data: page,
+        freshness: price_freshness(&state).await,
+    })
+}
+
+// Get a page of the most recent analysis, kept from the last launch if none has run since,
+// and when it ran. None when no analysis has been run
+#[tauri::command]
+async fn get_last_analysis(
+    offset: Option<usize>,
+    limit: Option<usize>,
+    state: State<'_, AppState>,
+) -> Result<Option<WithFreshness<LastAnalysisPage>>, String> {
+    let Some(analyzed_at) = *state.analyzed_at.read().await else {
+        return Ok(None);
+    };
+    let page = analysis::paginate(
+        &state.analysis_results.read().await,
+        offset.unwrap_or_default(),
+        limit,
+    );
+    Ok(Some(WithFreshness {
+        data: LastAnalysisPage { analyzed_at, page },
+        freshness: price_freshness(&state).await,
+    }))
+}
+
+// Get another page of the most recent analysis without recomputing it, optionally
+// reordering or grouping the results first
+#[tauri::command]
+async fn get_analysis_page(
+    offset: usize,
+    limit: Option<usize>,
+    sort: Option<ResultSort>,
+    group_by: Option<ResultGrouping>,
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Page<ReactionProfit>>, String> {
+    let reactions_db = state.reactions_db().await;
+    let mut results = state.analysis_results.write().await;
+    if let Some(sort) = sort {
+        analysis::sort_results(&mut results, sort, &reactions_db);
+    }
+    let mut page = analysis::paginate(&results, offset, limit);
+    if let Some(grouping) = group_by {
+        page.groups = analysis::group_results(&results, grouping, &reactions_db);
+    }
+    Ok(WithFreshness {
+        data: page,
+        freshness: price_freshness(&state).await,
+    })
+}
+
+// Write the results of the most recent analysis, in their current order, to a CSV or
+// JSON file
+#[tauri::command]
+async fn export_analysis(
+    path: String,
+    format: Option<ExportFormat>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let results = state.analysis_results.read().await;
+    if results.is_empty() {
+        return Err("No analysis results to export. Run an analysis first.".to_string());
+    }
+    export::export_results(
+        std::path::Path::new(&path),
+        &results,
+        format.unwrap_or_default(),
+    )
+}
 
-    // Calculate profit for each reaction (inputs priced at sell value = opportunity cost)
-    let mut profits: Vec<ReactionProfit> = state
-        .reactions_db
+// Calculate a single reaction in full, including its tree, against the cached prices so
+// details can be fetched when a result is expanded
+#[tauri::command]
+async fn get_reaction_detail(
+    formula_id: u32,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<ReactionProfit, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
         .reactions
         .iter()
-        .filter(|r| {
-            reaction_tree::reaction_uses_user_materials(r, &state.reactions_db, &user_material_ids)
-        })
-        .filter_map(|r| prices::calculate_reaction_profit(r, &prices, &user_material_ids))
-        .filter(|p| p.profit > 0.0)
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
+
+    let subtrees = subtree_cache(&state).await;
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    let stock = stock_by_id(&state, &reactions_db).await;
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &stock,
+        max_depth: state.memory_mode.read().await.tree_depth_cap(),
+        subtrees,
+        overrides: None,
+    };
+
+    analysis::reaction_detail(
+        reaction,
+        &ctx,
+        &sale_options_or_default(&state, sale_options).await,
+    )
+}
+
+// Build the production tree for enough runs to make `quantity` of an item, using the
+// cached prices and current inventory
+#[tauri::command]
+async fn build_tree(
+    output_id: u32,
+    quantity: u32,
+    state: State<'_, AppState>,
+) -> Result<reaction_tree::ReactionTreeNode, String> {
+    tree_for(&state, output_id, quantity).await
+}
+
+// Render the production tree for `quantity` of an item as Graphviz DOT, colored by where
+// each material comes from. Written to `path` when given, which is returned; otherwise
+// the DOT text is returned
+#[tauri::command]
+async fn export_tree_dot(
+    output_id: u32,
+    quantity: u32,
+    path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let dot = export::tree_to_dot(&tree_for(&state, output_id, quantity).await?);
+    match path {
+        Some(path) => {
+            reports::write_report(std::path::Path::new(&path), &dot)?;
+            Ok(path)
+        }
+        None => Ok(dot),
+    }
+}
+
+// Render the production tree for `quantity` of an item as indented text for pasting
+// into EVE mails or corp bulletins
+#[tauri::command]
+async fn export_tree_text(
+    output_id: u32,
+    quantity: u32,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    Ok(export::tree_to_text(
+        &tree_for(&state, output_id, quantity).await?,
+    ))
+}
+
+/// Production tree for enough runs to make `quantity` of an item
+async fn tree_for(
+    state: &AppState,
+    output_id: u32,
+    quantity: u32,
+) -> Result<reaction_tree::ReactionTreeNode, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let subtrees = subtree_cache(state).await;
+    let snapshot = cached_prices(state).await?;
+    let user_material_ids = user_material_ids(state, &reactions_db).await;
+    let stock = stock_by_id(state, &reactions_db).await;
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &stock,
+        max_depth: state.memory_mode.read().await.tree_depth_cap(),
+        subtrees,
+        overrides: None,
+    };
+
+    reaction_tree::build_full_reaction_tree(
+        &reaction.output.name,
+        output_id,
+        reaction.output.quantity,
+        reaction.runs_for(quantity.max(1)),
+        &ctx,
+    )
+}
+
+// Rebuild a reaction's tree with some items forced to be bought or reacted, returning the
+// tree and the plan totals it leads to
+#[tauri::command]
+async fn recompute_tree(
+    output_id: u32,
+    overrides: HashMap<u32, SourceOverride>,
+    runs: Option<u32>,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<RecomputedTree, String> {
+    let runs = runs.unwrap_or(1);
+    if runs == 0 {
+        return Err("Runs must be at least 1".to_string());
+    }
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    let stock = stock_by_id(&state, &reactions_db).await;
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &stock,
+        max_depth: None,
+        subtrees: None,
+        overrides: Some(&overrides),
+    };
+
+    let tree = reaction_tree::build_full_reaction_tree(
+        &reaction.output.name,
+        output_id,
+        reaction.output.quantity,
+        runs,
+        &ctx,
+    )?;
+    let plan = planner::plan_runs(
+        &tree,
+        runs,
+        &ctx,
+        &sale_options_or_default(&state, sale_options).await,
+    )?;
+    Ok(RecomputedTree { tree, plan })
+}
+
+// Stop a running analysis at its next checkpoint
+#[tauri::command]
+fn cancel_analysis(state: State<AppState>) {
+    state.analysis_cancel.cancel();
+}
+
+// List stored analyses, newest first
+#[tauri::command]
+fn list_analyses() -> Vec<AnalysisInfo> {
+    history::list()
+}
+
+// Get a stored analysis with its settings and top results
+#[tauri::command]
+fn get_analysis(id: String) -> Result<AnalysisRecord, String> {
+    AnalysisRecord::load(&id)
+}
+
+// List archived price snapshots, newest first
+#[tauri::command]
+fn list_price_snapshots() -> Vec<SnapshotInfo> {
+    prices::list_snapshots()
+}
+
+// Use an archived price snapshot as the current prices, e.g. to recompute past
+// profitability. It stays in use until prices are next fetched
+#[tauri::command]
+async fn load_price_snapshot(
+    state: State<'_, AppState>,
+    id: String,
+) -> Result<PriceSnapshot, String> {
+    let snapshot = PriceSnapshot::load_archived(&id)?;
+    *state.price_cache.write().await = Some(snapshot.clone());
+    state.subtree_cache.invalidate();
+    Ok(snapshot)
+}
+
+// Compare two stored analyses and return per-reaction profit and margin changes from A to B
+#[tauri::command]
+fn compare_analyses(a: String, b: String) -> Result<AnalysisComparison, String> {
+    let a = AnalysisRecord::load(&a)?;
+    let b = AnalysisRecord::load(&b)?;
+    Ok(history::compare(&a, &b))
+}
+
+// Recompute reaction profits against the cached prices with what-if adjustments applied
+#[tauri::command]
+async fn simulate_prices(
+    adjustments: PriceAdjustments,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Vec<ReactionProfit>>, String> {
+    let snapshot = cached_prices(&state).await?;
+    let adjusted = adjustments.apply(&snapshot.prices);
+
+    let reactions_db = state.reactions_db().await;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    let stock = stock_by_id(&state, &reactions_db).await;
+    let tree_ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &adjusted,
+        stock: &stock,
+        max_depth: state.memory_mode.read().await.tree_depth_cap(),
+        subtrees: None,
+        overrides: None,
+    };
+
+    let mut profits = analysis::profitable_reactions(
+        &tree_ctx,
+        &sale_options_or_default(&state, sale_options).await,
+        &AnalysisFilters::default(),
+        &CancelToken::default(),
+        |_| {},
+    )?;
+    analysis::sort_results(&mut profits, ResultSort::default(), &reactions_db);
+
+    Ok(WithFreshness {
+        data: profits,
+        freshness: snapshot_freshness(&state, &snapshot).await,
+    })
+}
+
+// Assess a price snapshot against the staleness thresholds
+async fn snapshot_freshness(state: &AppState, snapshot: &PriceSnapshot) -> DataFreshness {
+    let freshness = DataFreshness::assess(
+        Some(snapshot.fetched_at),
+        snapshot.source.clone(),
+        &*state.staleness.read().await,
+    );
+    if snapshot.stale {
+        freshness.offline()
+    } else {
+        freshness
+    }
+}
+
+// Use the given sale options, or the defaults from the settings
+async fn sale_options_or_default(
+    state: &AppState,
+    sale_options: Option<SaleOptions>,
+) -> SaleOptions {
+    match sale_options {
+        Some(options) => options,
+        None => state.settings.read().await.sale_options.clone(),
+    }
+}
+
+// Assess the cached prices against the staleness thresholds
+async fn price_freshness(state: &AppState) -> DataFreshness {
+    if let Some(snapshot) = state.price_cache.read().await.as_ref() {
+        return snapshot_freshness(state, snapshot).await;
+    }
+    let source = state.settings.read().await.price_source();
+    DataFreshness::assess(None, source.describe(), &*state.staleness.read().await)
+}
+
+// Get the thresholds at which responses flag stale data
+#[tauri::command]
+async fn get_staleness_thresholds(
+    state: State<'_, AppState>,
+) -> Result<StalenessThresholds, String> {
+    Ok(state.staleness.read().await.clone())
+}
+
+// Change and persist the thresholds at which responses flag stale data
+#[tauri::command]
+async fn set_staleness_thresholds(
+    thresholds: StalenessThresholds,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    thresholds.save()?;
+    *state.staleness.write().await = thresholds;
+    Ok(())
+}
+
+// Get the IDs of the moon goo the loaded moons produce
+async fn user_material_ids(state: &AppState, reactions_db: &ReactionDatabase) -> HashSet<u32> {
+    let moons = state.moons.read().await;
+    let moon_goo: Vec<String> = state
+        .ore_mappings()
+        .await
+        .ores_to_moon_goo(moons.iter().flat_map(|m| &m.materials))
+        .into_iter()
         .collect();
+    reactions_db.get_user_material_ids(&moon_goo)
+}
 
-    for profit in &mut profits {
-        let tree = reaction_tree::build_full_reaction_tree(
-            &profit.output_name,
-            profit.output_id,
-            profit.output_quantity,
-            &state.reactions_db,
-            &user_material_ids,
-            &prices,
+// Get the goo the extracted ore refines into, by item ID
+async fn extracted_goo_by_id(
+    state: &AppState,
+    reactions_db: &ReactionDatabase,
+) -> Result<HashMap<u32, f64>, String> {
+    let moons = state.moons.read().await;
+    if !moons
+        .iter()
+        .flat_map(|m| &m.materials)
+        .any(|m| m.extracted_units.is_some())
+    {
+        return Err(
+            "No extracted units entered. Enter them or apply the mining ledger first.".to_string(),
         );
-        profit.reaction_tree = Some(tree);
     }
-
-    profits.sort_by(|a, b| {
-        b.margin
-            .partial_cmp(&a.margin)
-            .unwrap_or(std::cmp::Ordering::Equal)
+    let structures = MoonStructures::load();
+    let goo = valuation::extracted_goo(&moons, &*state.ore_mappings().await, |moon| {
+        structures.reprocessing_efficiency(moon)
     });
+    Ok(goo
+        .into_iter()
+        .filter_map(|(name, units)| Some((*reactions_db.name_to_id.get(&name)?, units)))
+        .collect())
+}
 
-    Ok(profits)
+// Get the user's inventory keyed by item ID, for deducting stock while building trees
+async fn stock_by_id(state: &AppState, reactions_db: &ReactionDatabase) -> HashMap<u32, u32> {
+    let inventory = state.inventory.read().await;
+    inventory.by_id(&reactions_db.name_to_id)
 }
 
-#[cfg_attr(mobile, tauri::mobile_entry_point)]
-pub fn run() {
-    // Fix for WebKitGTK on certain Linux/Wayland systems
-    #[cfg(target_os = "linux")]
+// Get the prices fetched by the most recent analysis, with pinned prices applied
+async fn cached_prices(state: &AppState) -> Result<PriceSnapshot, String> {
+    let mut snapshot = state
+        .price_cache
+        .read()
+        .await
+        .clone()
+        .ok_or_else(|| "No prices available yet. Run an analysis first.".to_string())?
+        .with_regional_goo();
+    snapshot.prices = state.price_pins.read().await.apply(&snapshot.prices);
+    Ok(snapshot)
+}
+
+// Get the subtree cache for the current prices, unless low-memory mode turns it off. Take
+// it before reading the prices the trees will use
+async fn subtree_cache(state: &AppState) -> Option<CacheEpoch<'_>> {
+    let low_memory = state.memory_mode.read().await.low_memory;
+    (!low_memory).then(|| state.subtree_cache.current())
+}
+
+// Get prices for the given items, fetching only those not already cached. A cache older
+// than the configured TTL is replaced rather than added to
+async fn prices_for(
+    state: &AppState,
+    names: &[String],
+) -> Result<HashMap<String, PriceInfo>, String> {
+    let (source, retry, ttl_secs) = {
+        let settings = state.settings.read().await;
+        (
+            settings.price_source(),
+            settings.price_retry,
+            settings.price_cache_ttl_secs,
+        )
+    };
+    let (missing, expired): (Vec<String>, bool) = {
+        let cache = state.price_cache.read().await;
+        let expired = cache.as_ref().is_none_or(|snapshot| {
+            prices::now_unix().saturating_sub(snapshot.fetched_at) >= ttl_secs
+        });
+        let missing = names
+            .iter()
+            .filter(|name| {
+                expired
+                    || cache
+                        .as_ref()
+                        .is_none_or(|snapshot| !snapshot.prices.contains_key(*name))
+            })
+            .cloned()
+            .collect();
+        (missing, expired)
+    };
+
+    let mut fetched =
+        prices::fetch_snapshot(&missing, source, retry, &state.structure_market).await?;
+
+    let mut cache = state.price_cache.write().await;
+    match cache.as_mut() {
+        Some(snapshot) if !expired => {
+            snapshot.stale |= fetched.stale;
+            snapshot.merge(fetched.prices);
+        }
+        _ => {
+            // Fresh hub prices don't change what the user's own goo sells for locally
+            if let Some(old) = cache.take() {
+                fetched.regional_goo = old.regional_goo;
+            }
+            *cache = Some(fetched);
+        }
+    }
+    state.subtree_cache.invalidate();
+    let prices = cache
+        .clone()
+        .map(|s| s.with_regional_goo().prices)
+        .unwrap_or_default();
+    Ok(state.price_pins.read().await.apply(&prices))
+}
+
+// Pin an item's price, replacing the market price for `days` days (forever if omitted)
+#[tauri::command]
+async fn pin_price(
+    item: String,
+    price: f64,
+    days: Option<f64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let mut pins = state.price_pins.write().await;
+    pins.pin(item.trim(), price, days)?;
+    state.subtree_cache.invalidate();
+    pins.save()
+}
+
+// Remove a price pin
+#[tauri::command]
+async fn unpin_price(item: String, state: State<'_, AppState>) -> Result<(), String> {
+    let mut pins = state.price_pins.write().await;
+    pins.unpin(item.trim());
+    state.subtree_cache.invalidate();
+    pins.save()
+}
+
+// Get all price pins
+#[tauri::command]
+async fn get_price_pins(state: State<'_, AppState>) -> Result<Vec<PricePin>, String> {
+    Ok(state.price_pins.read().await.list())
+}
+
+// Drop lapsed price pins and tell the frontend and the user about them, so forgotten
+// overrides don't quietly linger
+async fn expire_price_pins(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let mut pins = state.price_pins.write().await;
+    let expired = pins.take_expired();
+    if expired.is_empty() {
+        return;
+    }
+    state.subtree_cache.invalidate();
+    let _ = pins.save();
+    drop(pins);
+
+    let items: Vec<&str> = expired.iter().map(|pin| pin.item.as_str()).collect();
+    let body = format!("Using market prices again for {}", items.join(", "));
+    if let Err(e) = app
+        .notification()
+        .builder()
+        .title("Price pins expired")
+        .body(body)
+        .show()
     {
-        std::env::set_var("WEBKIT_DISABLE_DMABUF_RENDERER", "1");
-        // Set app ID for Wayland taskbar icon
-        std::env::set_var("GDK_BACKEND", "x11");
+        tracing::warn!(error = %e, "Failed to show notification");
     }
+    let _ = app.emit(pins::PINS_EXPIRED_EVENT, expired);
+}
 
-    let reactions_db = ReactionDatabase::load().expect("Failed to load reactions database");
-    let ore_mappings = OreMappings::load().expect("Failed to load ore mappings");
+// Explain step by step how a reaction's profit was calculated
+#[tauri::command]
+async fn explain_calculation(
+    formula_id: u32,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<explain::CalculationExplanation, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
 
-    tauri::Builder::default()
-        .plugin(tauri_plugin_opener::init())
-        .manage(AppState {
-            moons: Mutex::new(Vec::new()),
-            reactions_db,
-            ore_mappings,
-        })
-        .setup(|app| {
-            // Set window icon for Linux/Wayland
-            if let Some(window) = app.get_webview_window("main") {
-                let icon = app.default_window_icon().cloned();
-                if let Some(icon) = icon {
-                    let _ = window.set_icon(icon);
-                }
-            }
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    explain::explain_reaction(
+        reaction,
+        &snapshot,
+        &user_material_ids,
+        &sale_options_or_default(&state, sale_options).await,
+    )
+}
+
+// Show how a price change in each input moves a reaction's margin, 10% by default
+#[tauri::command]
+async fn get_input_sensitivity(
+    formula_id: u32,
+    change_percent: Option<f64>,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<explain::SensitivityReport, String> {
+    let change_percent = change_percent.unwrap_or(10.0);
+    if !(0.0..100.0).contains(&change_percent) {
+        return Err("Price change must be between 0 and 100%".to_string());
+    }
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
 
-            // Send telemetry ping on launch
-            telemetry::send_launch_ping();
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    explain::input_sensitivity(
+        reaction,
+        &snapshot.prices,
+        &user_material_ids,
+        &sale_options_or_default(&state, sale_options).await,
+        change_percent,
+    )
+}
+
+// Compare a reaction's profit under several skill, rig and structure setups
+#[tauri::command]
+async fn compare_bonus_scenarios(
+    formula_id: u32,
+    scenarios: Vec<BonusScenario>,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ScenarioResult>, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
+
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    bonuses::compare_scenarios(
+        reaction,
+        &scenarios,
+        &snapshot.prices,
+        &user_material_ids,
+        &sale_options_or_default(&state, sale_options).await,
+    )
+}
+
+// Compare a reaction's profit in each structure recorded on the moons
+#[tauri::command]
+async fn compare_structures(
+    formula_id: u32,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<Vec<ScenarioResult>, String> {
+    let scenarios = MoonStructures::load().scenarios();
+    if scenarios.is_empty() {
+        return Err("No structures recorded. Add the structure on a moon first.".to_string());
+    }
+    compare_bonus_scenarios(formula_id, scenarios, sale_options, state).await
+}
+
+// Get the structures recorded on moons
+#[tauri::command]
+fn get_moon_structures() -> Vec<MoonStructure> {
+    MoonStructures::load().list()
+}
+
+// Record the structure on a moon, replacing any recorded before
+#[tauri::command]
+fn set_moon_structure(structure: MoonStructure) -> Result<Vec<MoonStructure>, String> {
+    let mut structures = MoonStructures::load();
+    structures.set(structure)?;
+    structures.save()?;
+    Ok(structures.list())
+}
+
+// Forget the structure on a moon
+#[tauri::command]
+fn remove_moon_structure(moon: String) -> Result<(), String> {
+    let mut structures = MoonStructures::load();
+    structures.remove(&moon);
+    structures.save()
+}
+
+// For chains blocked only by one goo the moons don't produce, get the most that goo can
+// cost while keeping the target margin (10% by default)
+#[tauri::command]
+async fn get_max_buy_prices(
+    target_margin: Option<f64>,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<Vec<MaxBuyHint>, String> {
+    let reactions_db = state.reactions_db().await;
+    let subtrees = subtree_cache(&state).await;
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    let no_stock = HashMap::new();
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &no_stock,
+        max_depth: None,
+        subtrees,
+        overrides: None,
+    };
+
+    Ok(substitution::max_buy_prices(
+        &ctx,
+        target_margin.unwrap_or(10.0),
+        &sale_options_or_default(&state, sale_options).await,
+    ))
+}
+
+// Plan a number of runs of a reaction using the cached prices and current inventory
+async fn run_plan_for(
+    state: &AppState,
+    reactions_db: &ReactionDatabase,
+    reaction: &reactions::Reaction,
+    runs: u32,
+    sale_options: &SaleOptions,
+) -> Result<RunPlan, String> {
+    if runs == 0 {
+        return Err("Runs must be at least 1".to_string());
+    }
+
+    let snapshot = cached_prices(state).await?;
+    let user_material_ids = user_material_ids(state, reactions_db).await;
+    let stock = stock_by_id(state, reactions_db).await;
+    let ctx = TreeContext {
+        reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &stock,
+        max_depth: None,
+        subtrees: None,
+        overrides: None,
+    };
+
+    let tree = reaction_tree::build_full_reaction_tree(
+        &reaction.output.name,
+        reaction.output.id,
+        reaction.output.quantity,
+        runs,
+        &ctx,
+    )?;
+
+    planner::plan_runs(&tree, runs, &ctx, sale_options)
+}
+
+// Build the purchase list for a number of runs of the reaction producing an item
+async fn shopping_list_for(
+    state: &AppState,
+    output_id: u32,
+    runs: u32,
+) -> Result<ShoppingList, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let plan = run_plan_for(
+        state,
+        &reactions_db,
+        reaction,
+        runs,
+        &sale_options_or_default(state, None).await,
+    )
+    .await?;
+    Ok(shopping::build_shopping_list(
+        &plan,
+        &reaction.output.name,
+        reaction.output.id,
+    ))
+}
+
+// Aggregate everything that must be bought to run a reaction chain into a purchase list
+#[tauri::command]
+async fn get_shopping_list(
+    output_id: u32,
+    runs: u32,
+    state: State<'_, AppState>,
+) -> Result<ShoppingList, String> {
+    shopping_list_for(&state, output_id, runs).await
+}
+
+// Flatten a reaction chain into one list of every raw material it consumes, with
+// quantities and costs
+#[tauri::command]
+async fn get_bill_of_materials(
+    output_id: u32,
+    runs: u32,
+    state: State<'_, AppState>,
+) -> Result<BillOfMaterials, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let plan = run_plan_for(
+        &state,
+        &reactions_db,
+        reaction,
+        runs,
+        &sale_options_or_default(&state, None).await,
+    )
+    .await?;
+    Ok(shopping::build_bill_of_materials(
+        &plan,
+        &reaction.output.name,
+        reaction.output.id,
+    ))
+}
+
+// Save the purchase list for a number of runs as a persistent appraisal on Goonpraisal or
+// Janice and get its shareable link
+#[tauri::command]
+async fn create_appraisal_link(
+    output_id: u32,
+    runs: u32,
+    service: Option<AppraisalService>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let list = shopping_list_for(&state, output_id, runs).await?;
+    if list.items.is_empty() {
+        return Err("Nothing needs to be bought for this plan".to_string());
+    }
+    let (market, janice_api_key) = {
+        let settings = state.settings.read().await;
+        (settings.market_hub, settings.janice_api_key.clone())
+    };
+    appraisal::share_link(
+        &list.to_multibuy(),
+        service.unwrap_or_default(),
+        market,
+        janice_api_key.as_deref(),
+    )
+    .await
+}
+
+// Format the purchase list as EVE multibuy text for pasting in-game. With a path, or in
+// low-memory mode, the text is written to disk and the file path is returned instead
+#[tauri::command]
+async fn export_multibuy(
+    output_id: u32,
+    runs: u32,
+    path: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let list = shopping_list_for(&state, output_id, runs).await?;
+
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None if state.memory_mode.read().await.low_memory => {
+            storage::data_file(&format!("exports/multibuy-{}-{}.txt", output_id, runs))
+                .ok_or_else(|| "Could not determine data directory".to_string())?
+        }
+        None => return Ok(list.to_multibuy()),
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    let file = std::fs::File::create(&path)
+        .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+    let mut out = std::io::BufWriter::new(file);
+    list.write_multibuy(&mut out)
+        .and_then(|_| out.flush())
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    Ok(path.display().to_string())
+}
+
+// Get the low-memory mode options
+#[tauri::command]
+async fn get_memory_mode(state: State<'_, AppState>) -> Result<MemoryMode, String> {
+    Ok(state.memory_mode.read().await.clone())
+}
+
+// Change and persist the low-memory mode options
+#[tauri::command]
+async fn set_memory_mode(mode: MemoryMode, state: State<'_, AppState>) -> Result<(), String> {
+    mode.save()?;
+    if mode.low_memory {
+        state.subtree_cache.invalidate();
+    }
+    *state.memory_mode.write().await = mode;
+    Ok(())
+}
+
+// Count the runs of every formula, jobs and reaction hours needed to make a quantity of an
+// item
+#[tauri::command]
+async fn get_run_summary(
+    output_id: u32,
+    quantity: u32,
+    state: State<'_, AppState>,
+) -> Result<RunSummary, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let quantity = quantity.max(1);
+    let plan = run_plan_for(
+        &state,
+        &reactions_db,
+        reaction,
+        reaction.runs_for(quantity),
+        &sale_options_or_default(&state, None).await,
+    )
+    .await?;
+    Ok(RunSummary::new(&plan, &reaction.output.name, quantity))
+}
+
+// Lay out the reaction jobs for a product mix over the given characters' reaction slots,
+// intermediates first
+#[tauri::command]
+async fn schedule_reaction_jobs(
+    targets: Vec<ProductTarget>,
+    characters: u32,
+    slots_per_character: u32,
+    state: State<'_, AppState>,
+) -> Result<JobSchedule, String> {
+    slots::slot_count(characters, slots_per_character)?;
+    if targets.is_empty() {
+        return Err("Add at least one product to make".to_string());
+    }
+    let reactions_db = state.reactions_db().await;
+    let sale_options = sale_options_or_default(&state, None).await;
+
+    let mut plans = Vec::new();
+    for target in &targets {
+        let reaction = reactions_db
+            .by_output
+            .get(&target.output_id)
+            .ok_or_else(|| format!("No reaction produces item ID {}", target.output_id))?;
+        let runs = reaction.runs_for(target.quantity.max(1));
+        let plan = run_plan_for(&state, &reactions_db, reaction, runs, &sale_options).await?;
+        plans.push(plan.formulas);
+    }
+
+    slots::schedule_jobs(
+        &slots::merge_formula_runs(plans),
+        &reactions_db,
+        characters,
+        slots_per_character,
+    )
+}
+
+// Open a window that keeps one reaction's tree, job schedule and shopping list on screen
+// while the main window moves on. A reaction that already has a window gets it focused
+// and pointed at the new quantity. Returns the window's label
+#[tauri::command]
+async fn open_detail_window(
+    output_id: u32,
+    quantity: u32,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", output_id))?;
+
+    let label = DetailWindows::label_for(output_id);
+    let target = DetailTarget {
+        output_id,
+        quantity: quantity.max(1),
+        characters: 1,
+        slots_per_character: slots::MAX_SLOTS_PER_CHARACTER,
+    };
+    state.detail_windows.set(&label, target);
+
+    if let Some(window) = app.get_webview_window(&label) {
+        let view = detail_view_for(&state, &label, target).await?;
+        let _ = app.emit_to(label.as_str(), detail::DETAIL_UPDATED_EVENT, view);
+        let _ = window.set_focus();
+        return Ok(label);
+    }
+
+    // The window reads its label from the URL and asks for its view once loaded
+    let url = tauri::WebviewUrl::App(format!("index.html?detail={}", label).into());
+    let window = tauri::WebviewWindowBuilder::new(&app, &label, url)
+        .title(format!("{} - MOON", reaction.output.name))
+        .inner_size(1100.0, 900.0)
+        .build()
+        .map_err(|e| {
+            state.detail_windows.remove(&label);
+            format!("Failed to open window: {}", e)
+        })?;
+
+    let closed_app = app.clone();
+    let closed_label = label.clone();
+    window.on_window_event(move |event| {
+        if let tauri::WindowEvent::Destroyed = event {
+            closed_app
+                .state::<AppState>()
+                .detail_windows
+                .remove(&closed_label);
+        }
+    });
+    Ok(label)
+}
+
+// Get what a detail window shows, built from the current prices and inventory
+#[tauri::command]
+async fn get_detail_view(label: String, state: State<'_, AppState>) -> Result<DetailView, String> {
+    let target = state
+        .detail_windows
+        .get(&label)
+        .ok_or_else(|| format!("No detail window '{}' is open", label))?;
+    detail_view_for(&state, &label, target).await
+}
+
+// Change the quantity and reaction slots a detail window plans for, without touching
+// any other window
+#[tauri::command]
+async fn set_detail_target(
+    label: String,
+    quantity: u32,
+    characters: u32,
+    slots_per_character: u32,
+    state: State<'_, AppState>,
+) -> Result<DetailView, String> {
+    let current = state
+        .detail_windows
+        .get(&label)
+        .ok_or_else(|| format!("No detail window '{}' is open", label))?;
+    slots::slot_count(characters, slots_per_character)?;
+
+    let target = DetailTarget {
+        quantity: quantity.max(1),
+        characters,
+        slots_per_character,
+        ..current
+    };
+    let view = detail_view_for(&state, &label, target).await?;
+    state.detail_windows.set(&label, target);
+    Ok(view)
+}
+
+/// Tree, job schedule and shopping list for a detail window's target
+async fn detail_view_for(
+    state: &AppState,
+    label: &str,
+    target: DetailTarget,
+) -> Result<DetailView, String> {
+    let tree = tree_for(state, target.output_id, target.quantity).await?;
+
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .by_output
+        .get(&target.output_id)
+        .ok_or_else(|| format!("No reaction produces item ID {}", target.output_id))?;
+    let plan = run_plan_for(
+        state,
+        &reactions_db,
+        reaction,
+        reaction.runs_for(target.quantity),
+        &sale_options_or_default(state, None).await,
+    )
+    .await?;
+    let shopping_list =
+        shopping::build_shopping_list(&plan, &reaction.output.name, reaction.output.id);
+    let schedule = slots::schedule_jobs(
+        &slots::merge_formula_runs([plan.formulas]),
+        &reactions_db,
+        target.characters,
+        target.slots_per_character,
+    )?;
+
+    Ok(DetailView {
+        label: label.to_string(),
+        output_name: reaction.output.name.clone(),
+        target,
+        tree,
+        schedule,
+        shopping_list,
+    })
+}
+
+/// Rebuild every open detail window's view and send each to its own window, e.g. after
+/// prices change
+async fn refresh_detail_windows(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    for (label, target) in state.detail_windows.all() {
+        match detail_view_for(&state, &label, target).await {
+            Ok(view) => {
+                let _ = app.emit_to(label.as_str(), detail::DETAIL_UPDATED_EVENT, view);
+            }
+            Err(e) => {
+                tracing::warn!(window = %label, error = %e, "Failed to refresh detail window")
+            }
+        }
+    }
+}
+
+// Scale a reaction chain to a number of runs and total up inputs, cost, revenue and job time
+#[tauri::command]
+async fn plan_runs(
+    formula_id: u32,
+    runs: u32,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<RunPlan, String> {
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
+
+    run_plan_for(
+        &state,
+        &reactions_db,
+        reaction,
+        runs,
+        &sale_options_or_default(&state, sale_options).await,
+    )
+    .await
+}
+
+// Plan repeated cycles of a chain with leftover intermediates carried into the next cycle
+#[tauri::command]
+async fn plan_steady_state(
+    formula_id: u32,
+    runs_per_cycle: u32,
+    cycles: u32,
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<SteadyStatePlan, String> {
+    if runs_per_cycle == 0 || cycles == 0 {
+        return Err("Runs per cycle and cycles must be at least 1".to_string());
+    }
+
+    let reactions_db = state.reactions_db().await;
+    let reaction = reactions_db
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
+
+    let snapshot = cached_prices(&state).await?;
+    let user_material_ids = user_material_ids(&state, &reactions_db).await;
+    let stock = stock_by_id(&state, &reactions_db).await;
+    let ctx = TreeContext {
+        reactions_db: &reactions_db,
+        user_moon_goo_ids: &user_material_ids,
+        prices: &snapshot.prices,
+        stock: &stock,
+        max_depth: None,
+        subtrees: None,
+        overrides: None,
+    };
+
+    planner::plan_steady_state(
+        reaction,
+        runs_per_cycle,
+        cycles,
+        &ctx,
+        &sale_options_or_default(&state, sale_options).await,
+    )
+}
+
+// Price any pasted item list, e.g. a contract or stockpile, and return buy/sell totals
+// with the items producible from the loaded moons marked
+#[tauri::command]
+async fn appraise_paste(
+    input: String,
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Appraisal>, String> {
+    let items = appraisal::parse_item_list(&input);
+    if items.is_empty() {
+        return Err("No items found in paste".to_string());
+    }
+    appraise_for(&state, &items).await
+}
+
+// Price (name, quantity) pairs the same way as a paste, for a quick appraisal without
+// parsing text
+#[tauri::command]
+async fn appraise_items(
+    items: Vec<(String, u64)>,
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Appraisal>, String> {
+    let items: Vec<(String, u64)> = items
+        .into_iter()
+        .map(|(name, quantity)| (name.trim().to_string(), quantity))
+        .filter(|(name, _)| !name.is_empty())
+        .collect();
+    if items.is_empty() {
+        return Err("No items to appraise".to_string());
+    }
+    appraise_for(&state, &items).await
+}
+
+// Price items through the price cache and mark the ones producible from the loaded moons
+async fn appraise_for(
+    state: &AppState,
+    items: &[(String, u64)],
+) -> Result<WithFreshness<Appraisal>, String> {
+    let names: Vec<String> = items.iter().map(|(name, _)| name.clone()).collect();
+    let prices = prices_for(state, &names).await?;
+
+    let reactions_db = state.reactions_db().await;
+    let user_material_ids = user_material_ids(state, &reactions_db).await;
+    let mut appraisal = appraisal::appraise(items, &prices);
+    appraisal.mark_producible(|name| {
+        reactions_db.name_to_id.get(name).is_some_and(|id| {
+            reaction_tree::traces_to_moon_materials(
+                *id,
+                &reactions_db,
+                &user_material_ids,
+                &mut HashSet::new(),
+            )
+        })
+    });
+
+    Ok(WithFreshness {
+        data: appraisal,
+        freshness: price_freshness(state).await,
+    })
+}
+
+// Import a personal mining ledger paste and get what has been mined from each loaded moon
+#[tauri::command]
+async fn import_mining_ledger(
+    input: String,
+    state: State<'_, AppState>,
+) -> Result<LedgerSummary, String> {
+    let entries = ledger::parse_ledger(&input);
+    if entries.is_empty() {
+        return Err("No mining ledger entries found in paste".to_string());
+    }
+
+    // Moons first, the order every lock on per-profile data is taken in
+    let moons = state.moons.read().await;
+    let mut mining_ledger = state.mining_ledger.write().await;
+    let added = mining_ledger.import(entries);
+    mining_ledger.save()?;
+    tracing::info!(added, "Imported mining ledger");
+
+    Ok(mining_ledger.by_moon(&moons, &*state.ore_mappings().await))
+}
+
+// Set each loaded moon's extracted ore units from the imported mining ledger, replacing
+// units entered by hand for the ores it covers. Returns how many materials were updated
+#[tauri::command]
+async fn apply_ledger_extractions(state: State<'_, AppState>) -> Result<usize, String> {
+    let mut moons = state.moons.write().await;
+    let updated = state
+        .mining_ledger
+        .read()
+        .await
+        .apply_to_moons(&mut moons, &*state.ore_mappings().await);
+    profiles::save_moons(&moons)?;
+    Ok(updated)
+}
+
+// Set the ore units extracted for one material of a moon; None clears it
+#[tauri::command]
+async fn set_extracted_units(
+    name: String,
+    material: String,
+    units: Option<f64>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    if units.is_some_and(|units| !units.is_finite() || units < 0.0) {
+        return Err("Extracted units can't be negative".to_string());
+    }
+    let mut moons = state.moons.write().await;
+    let moon = moons
+        .iter_mut()
+        .find(|m| m.name == name)
+        .ok_or_else(|| format!("Moon '{}' isn't loaded", name))?;
+    let entry = moon
+        .materials
+        .iter_mut()
+        .find(|m| m.name == material)
+        .ok_or_else(|| format!("'{}' has no {}", name, material))?;
+    entry.extracted_units = units;
+    profiles::save_moons(&moons)
+}
+
+// Get what the imported mining ledger says has been mined from each loaded moon
+#[tauri::command]
+async fn get_mined_amounts(state: State<'_, AppState>) -> Result<LedgerSummary, String> {
+    let moons = state.moons.read().await;
+    let mining_ledger = state.mining_ledger.read().await;
+    Ok(mining_ledger.by_moon(&moons, &*state.ore_mappings().await))
+}
+
+// Forget all imported mining ledger entries
+#[tauri::command]
+async fn clear_mining_ledger(state: State<'_, AppState>) -> Result<(), String> {
+    let mut mining_ledger = state.mining_ledger.write().await;
+    mining_ledger.clear();
+    mining_ledger.save()
+}
+
+// Get everything currently in the inventory
+#[tauri::command]
+async fn get_inventory(state: State<'_, AppState>) -> Result<Vec<StockEntry>, String> {
+    let inventory = state.inventory.read().await;
+    Ok(inventory.entries())
+}
+
+// Set the quantity of one item in the inventory (zero removes it)
+#[tauri::command]
+async fn set_stock(item: String, quantity: u64, state: State<'_, AppState>) -> Result<(), String> {
+    let mut inventory = state.inventory.write().await;
+    inventory.set(item.trim(), quantity);
+    inventory.save()
+}
+
+// Import an item list paste into the inventory, either adding to or replacing current stock
+#[tauri::command]
+async fn import_inventory(
+    input: String,
+    replace: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<StockEntry>, String> {
+    // Item window copies are tab separated; anything else is read as a loose item list
+    let items = if input.contains('\t') {
+        inventory::parse_item_window(&input)
+    } else {
+        appraisal::parse_item_list(&input)
+    };
+    if items.is_empty() {
+        return Err("No items found in paste".to_string());
+    }
+
+    let mut inventory = state.inventory.write().await;
+    if replace {
+        inventory.clear();
+    }
+    for (name, quantity) in items {
+        inventory.add(&name, quantity);
+    }
+    inventory.save()?;
+    Ok(inventory.entries())
+}
+
+// Get the moon goo in the inventory
+async fn goo_stock(state: &AppState) -> Vec<StockEntry> {
+    let inventory = state.inventory.read().await;
+    inventory
+        .entries()
+        .into_iter()
+        .filter(|entry| ore_mappings::is_moon_goo(&entry.name))
+        .collect()
+}
+
+// Value the moon goo stockpile at current prices and return its value over time
+#[tauri::command]
+async fn get_stockpile_valuation(
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<StockpileValuation>, String> {
+    let stock = goo_stock(&state).await;
+    let names: Vec<String> = stock.iter().map(|entry| entry.name.clone()).collect();
+    let prices = prices_for(&state, &names).await?;
+
+    let mut history = StockpileHistory::load();
+    if state.memory_mode.read().await.retain_history() {
+        history.record(&stock, &prices);
+        history.save()?;
+    }
+
+    Ok(WithFreshness {
+        data: history.valuation(),
+        freshness: price_freshness(&state).await,
+    })
+}
+
+// Price everything in the inventory at current buy and sell prices and total it by raw
+// goo, reaction category, or other
+#[tauri::command]
+async fn value_inventory(
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<InventoryValuation>, String> {
+    let stock = state.inventory.read().await.entries();
+    if stock.is_empty() {
+        return Err("The inventory is empty".to_string());
+    }
+    let names: Vec<String> = stock.iter().map(|entry| entry.name.clone()).collect();
+    let prices = prices_for(&state, &names).await?;
+
+    let reactions_db = state.reactions_db().await;
+    let category_of = |name: &str| {
+        if ore_mappings::is_moon_goo(name) {
+            return "Raw moon goo".to_string();
+        }
+        reactions_db
+            .name_to_id
+            .get(name)
+            .and_then(|id| reactions_db.by_output.get(id))
+            .and_then(|reaction| reactions_db.category_of(reaction.formula_id))
+            .map_or("Other", |category| category.label())
+            .to_string()
+    };
+
+    Ok(WithFreshness {
+        data: stockpile::value_inventory(&stock, &prices, category_of),
+        freshness: price_freshness(&state).await,
+    })
+}
+
+// Value every loaded moon and return them sorted by estimated monthly ISK
+#[tauri::command]
+async fn rank_moons(
+    state: State<'_, AppState>,
+) -> Result<WithFreshness<Vec<MoonValuation>>, String> {
+    let (valuations, snapshot) = moon_valuations(&state).await?;
+    Ok(WithFreshness {
+        data: valuations,
+        freshness: snapshot_freshness(&state, &snapshot).await,
+    })
+}
+
+// Score every loaded moon from 0 to 100 by ore rarity and goo value, best first
+#[tauri::command]
+async fn score_moons(state: State<'_, AppState>) -> Result<WithFreshness<Vec<MoonScore>>, String> {
+    let (valuations, snapshot) = moon_valuations(&state).await?;
+    let mut scores: Vec<MoonScore> = valuations.iter().map(valuation::score_moon).collect();
+    scores.sort_by(|a, b| {
+        b.score
+            .partial_cmp(&a.score)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    Ok(WithFreshness {
+        data: scores,
+        freshness: snapshot_freshness(&state, &snapshot).await,
+    })
+}
+
+// Work out the ore and goo a loaded moon yields from an extraction of a number of days
+#[tauri::command]
+async fn calculate_extraction(
+    moon_name: String,
+    days: f64,
+    state: State<'_, AppState>,
+) -> Result<ExtractionYield, String> {
+    if !days.is_finite() || days <= 0.0 {
+        return Err("Extraction duration must be more than zero days".to_string());
+    }
+
+    let moons = state.moons.read().await;
+    let moon = moons
+        .iter()
+        .find(|m| m.name == moon_name)
+        .ok_or_else(|| format!("Moon '{}' isn't loaded", moon_name))?;
+    Ok(valuation::extraction_yield(
+        moon,
+        days,
+        &*state.ore_mappings().await,
+        MoonStructures::load().reprocessing_efficiency(&moon.name),
+    ))
+}
+
+// Track a moon's extraction, started at `started_at` (now if omitted) and growing for
+// `duration_hours`, replacing any extraction already tracked for the moon
+#[tauri::command]
+async fn schedule_extraction(
+    moon: String,
+    started_at: Option<u64>,
+    duration_hours: f64,
+    state: State<'_, AppState>,
+) -> Result<Vec<UpcomingExtraction>, String> {
+    if !duration_hours.is_finite() || duration_hours <= 0.0 {
+        return Err("Chunk duration must be more than zero hours".to_string());
+    }
+    let started_at = started_at.unwrap_or_else(prices::now_unix);
+    let mut schedule = state.extraction_schedule.write().await;
+    schedule.set(&moon, started_at, (duration_hours * 3600.0).round() as u64)?;
+    schedule.save()?;
+    Ok(schedule.upcoming())
+}
+
+// Stop tracking a moon's extraction
+#[tauri::command]
+async fn remove_extraction(moon: String, state: State<'_, AppState>) -> Result<(), String> {
+    let mut schedule = state.extraction_schedule.write().await;
+    schedule.remove(&moon);
+    schedule.save()
+}
+
+// Get tracked extractions with the time left until each chunk arrives, soonest first
+#[tauri::command]
+async fn get_upcoming_extractions(
+    state: State<'_, AppState>,
+) -> Result<Vec<UpcomingExtraction>, String> {
+    Ok(state.extraction_schedule.read().await.upcoming())
+}
+
+// Write the tracked extractions to an iCalendar file, one event per chunk arrival, for
+// importing into a shared calendar
+#[tauri::command]
+async fn export_extraction_calendar(
+    path: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let extractions = state.extraction_schedule.read().await.extractions();
+    if extractions.is_empty() {
+        return Err("No extractions are being tracked".to_string());
+    }
+    let calendar = schedule::to_ical(&extractions, prices::now_unix());
+    reports::write_report(std::path::Path::new(&path), &calendar)
+}
+
+// Get tracked extractions grouped by the structure on each moon
+#[tauri::command]
+async fn get_extractions_by_structure(
+    state: State<'_, AppState>,
+) -> Result<Vec<StructureExtractions>, String> {
+    let upcoming = state.extraction_schedule.read().await.upcoming();
+    Ok(MoonStructures::load().group_extractions(upcoming))
+}
+
+// Value every loaded moon at fresh prices, best first, with the prices used
+async fn moon_valuations(state: &AppState) -> Result<(Vec<MoonValuation>, PriceSnapshot), String> {
+    let mut moons: Vec<parser::MoonComposition> = state.moons.read().await.clone();
+
+    if moons.is_empty() {
+        return Err("No moons loaded. Add some moons first.".to_string());
+    }
+    let market = state.settings.read().await.market_hub;
+    locate_moons(&mut moons, market);
+
+    let ore_mappings = state.ore_mappings().await;
+    let goo_names: Vec<String> = ore_mappings
+        .ores_to_moon_goo(moons.iter().flat_map(|m| &m.materials))
+        .into_iter()
+        .collect();
+
+    let (source, retry, regional) = {
+        let settings = state.settings.read().await;
+        (
+            settings.price_source(),
+            settings.price_retry,
+            settings.regional_moon_pricing,
+        )
+    };
+    let snapshot =
+        prices::fetch_snapshot(&goo_names, source, retry, &state.structure_market).await?;
+
+    let regional_prices = if regional {
+        regional_goo_prices(state, &moons, &goo_names, &snapshot.prices, source.mode).await
+    } else {
+        HashMap::new()
+    };
+
+    let pins = state.price_pins.read().await;
+    let prices = pins.apply(&snapshot.prices);
+    let regional_prices: HashMap<u32, HashMap<String, PriceInfo>> = regional_prices
+        .into_iter()
+        .map(|(region_id, prices)| (region_id, pins.apply(&prices)))
+        .collect();
+    drop(pins);
+
+    let structures = MoonStructures::load();
+    let mut valuations: Vec<MoonValuation> = moons
+        .iter()
+        .map(|moon| {
+            let efficiency = structures.reprocessing_efficiency(&moon.name);
+            let region = moon
+                .location
+                .as_ref()
+                .filter(|location| regional_prices.contains_key(&location.region_id));
+            let moon_prices =
+                region.map_or(&prices, |location| &regional_prices[&location.region_id]);
+            let mut valuation = valuation::value_moon(moon, &ore_mappings, moon_prices, efficiency);
+            valuation.price_region = region.map(|location| location.region_name.clone());
+            valuation
+        })
+        .collect();
+
+    valuations.sort_by(|a, b| {
+        b.monthly_isk
+            .partial_cmp(&a.monthly_isk)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    Ok((valuations, snapshot))
+}
+
+// Compare selling each moon's monthly output as raw ore, compressed ore, reprocessed goo
+// or reacted products, after fees
+#[tauri::command]
+async fn compare_moon_sales(
+    sale_options: Option<SaleOptions>,
+    state: State<'_, AppState>,
+) -> Result<Vec<MoonSaleComparison>, String> {
+    let moons: Vec<parser::MoonComposition> = state.moons.read().await.clone();
+    if moons.is_empty() {
+        return Err("No moons loaded. Add some moons first.".to_string());
+    }
+    let sale_options = sale_options_or_default(&state, sale_options).await;
+    let ore_mappings = state.ore_mappings().await;
+    let reactions_db = state.reactions_db().await;
+
+    let goo_names: Vec<String> = ore_mappings
+        .ores_to_moon_goo(moons.iter().flat_map(|m| &m.materials))
+        .into_iter()
+        .collect();
+    let goo_ids = reactions_db.get_user_material_ids(&goo_names);
+    let mut names: HashSet<String> = goo_names.iter().cloned().collect();
+    for moon in &moons {
+        for material in &moon.materials {
+            let ore = ore_mappings.english_name(&material.name);
+            names.insert(format!("Compressed {}", ore));
+            names.insert(ore);
+        }
+    }
+    for reaction in reactions_db
+        .reactions
+        .iter()
+        .filter(|r| r.inputs.iter().any(|i| goo_ids.contains(&i.id)))
+    {
+        names.insert(reaction.output.name.clone());
+        names.extend(reaction.inputs.iter().map(|i| i.name.clone()));
+    }
+    let prices = prices_for(&state, &names.into_iter().collect::<Vec<_>>()).await?;
+
+    let goo_reactions =
+        valuation::best_goo_reactions(&goo_names, &reactions_db, &prices, &sale_options);
+    let structures = MoonStructures::load();
+    Ok(moons
+        .iter()
+        .map(|moon| {
+            valuation::compare_sale_stages(
+                moon,
+                &ore_mappings,
+                &prices,
+                structures.reprocessing_efficiency(&moon.name),
+                &sale_options,
+                &goo_reactions,
+            )
+        })
+        .collect())
+}
+
+// Get the weekly summary settings
+#[tauri::command]
+fn get_weekly_report_config() -> WeeklyReportConfig {
+    WeeklyReportConfig::load()
+}
+
+// Change and persist the weekly summary settings, keeping the time of the last report
+#[tauri::command]
+fn set_weekly_report_config(config: WeeklyReportConfig) -> Result<(), String> {
+    WeeklyReportConfig {
+        last_run: WeeklyReportConfig::load().last_run,
+        ..config
+    }
+    .save()
+}
+
+// Generate the weekly summary now and return the path it was written to
+#[tauri::command]
+async fn run_weekly_report(state: State<'_, AppState>) -> Result<String, String> {
+    let mut config = WeeklyReportConfig::load();
+    let path = write_weekly_report(&state, &config).await?;
+    config.last_run = Some(prices::now_unix());
+    config.save()?;
+    Ok(path)
+}
+
+// Write the portfolio, upcoming extractions and top reactions to a dated file and post it to Discord if set up
+async fn write_weekly_report(
+    state: &AppState,
+    config: &WeeklyReportConfig,
+) -> Result<String, String> {
+    let now = prices::now_unix();
+    // An empty portfolio still gets a report rather than failing the job
+    let moons = moon_valuations(state)
+        .await
+        .map(|(valuations, _)| valuations)
+        .unwrap_or_default();
+    let top: Vec<ReactionProfit> = state
+        .analysis_results
+        .read()
+        .await
+        .iter()
+        .take(config.top_reactions)
+        .cloned()
+        .collect();
+
+    let extractions = state.extraction_schedule.read().await.upcoming();
+    let report = reports::render_weekly(now, &moons, &extractions, &top);
+    let path = config.report_path(now)?;
+    reports::write_report(&path, &report)?;
+
+    if let Some(webhook) = &config.discord_webhook {
+        reports::post_to_discord(webhook, &report).await?;
+    }
+
+    Ok(path.display().to_string())
+}
+
+// Write a standalone HTML report of the moons, the top reactions of the last analysis,
+// shopping lists for a full job of the best few and a schedule for those jobs. Written to
+// `path`, or to the reports folder, and the path is returned
+#[tauri::command]
+async fn generate_report(
+    path: Option<String>,
+    options: Option<HtmlReportOptions>,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let options = options.unwrap_or_default();
+    let now = prices::now_unix();
+    let moons = moon_valuations(&state)
+        .await
+        .map(|(valuations, _)| valuations)
+        .unwrap_or_default();
+    let top: Vec<ReactionProfit> = state
+        .analysis_results
+        .read()
+        .await
+        .iter()
+        .take(options.top_reactions)
+        .cloned()
+        .collect();
+
+    let reactions_db = state.reactions_db().await;
+    let sale_options = sale_options_or_default(&state, None).await;
+    let mut shopping_lists = Vec::new();
+    let mut plans = Vec::new();
+    for result in top.iter().take(options.shopping_lists) {
+        let Some(reaction) = reactions_db.by_output.get(&result.output_id) else {
+            continue;
+        };
+        let runs = reaction.max_runs_per_job.max(1);
+        shopping_lists.push(shopping_list_for(&state, result.output_id, runs).await?);
+        plans.push(
+            run_plan_for(&state, &reactions_db, reaction, runs, &sale_options)
+                .await?
+                .formulas,
+        );
+    }
+    let schedule = if plans.is_empty() {
+        None
+    } else {
+        Some(slots::schedule_jobs(
+            &slots::merge_formula_runs(plans),
+            &reactions_db,
+            options.characters,
+            options.slots_per_character,
+        )?)
+    };
+
+    let html = reports::render_html(&HtmlReport {
+        now,
+        moons: &moons,
+        top_reactions: &top,
+        shopping_lists: &shopping_lists,
+        schedule: schedule.as_ref(),
+    });
+    let path = match path {
+        Some(path) => std::path::PathBuf::from(path),
+        None => reports::html_report_path(now)?,
+    };
+    reports::write_report(&path, &html)?;
+    Ok(path.display().to_string())
+}
+
+// Generate the weekly summary if it is enabled and a week has passed since the last one
+async fn weekly_report_if_due(app: &AppHandle) {
+    let mut config = WeeklyReportConfig::load();
+    let now = prices::now_unix();
+    if !config.is_due(now) {
+        return;
+    }
+    let state = app.state::<AppState>();
+    if let Err(e) = write_weekly_report(&state, &config).await {
+        tracing::error!(error = %e, "Weekly report failed");
+    }
+    // Wait a full week before trying again either way, rather than retrying every hour
+    config.last_run = Some(now);
+    let _ = config.save();
+}
+
+// Show a desktop notification for each tracked chunk arriving within the configured lead
+// time that hasn't been announced yet
+async fn notify_upcoming_extractions(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let lead_secs = {
+        let settings = state.settings.read().await;
+        if !settings.extraction_alerts {
+            return;
+        }
+        (settings.extraction_alert_hours * 3600.0) as u64
+    };
+
+    let now = prices::now_unix();
+    let mut schedule = state.extraction_schedule.write().await;
+    let due = schedule.take_due_alerts(now, lead_secs);
+    if due.is_empty() {
+        return;
+    }
+    let _ = schedule.save();
+
+    for extraction in due {
+        let body = match extraction
+            .arrives_at()
+            .ok()
+            .and_then(|at| at.checked_sub(now))
+        {
+            Some(secs) if secs > 0 => format!(
+                "{} chunk arrives in {}h {:02}m",
+                extraction.moon,
+                secs / 3600,
+                secs / 60 % 60
+            ),
+            _ => format!("{} chunk has arrived", extraction.moon),
+        };
+        if let Err(e) = app
+            .notification()
+            .builder()
+            .title("Moon extraction")
+            .body(body)
+            .show()
+        {
+            tracing::warn!(error = %e, moon = %extraction.moon, "Failed to show notification");
+        }
+    }
+}
+
+// Refetch every cached price once the refresh interval has passed, keeping numbers shown
+// during a long session current, and tell the frontend
+async fn refresh_prices_if_due(app: &AppHandle) {
+    let state = app.state::<AppState>();
+    let (source, retry, interval_secs) = {
+        let settings = state.settings.read().await;
+        if !settings.auto_refresh_prices {
+            return;
+        }
+        (
+            settings.price_source(),
+            settings.price_retry,
+            settings.price_refresh_mins.saturating_mul(60),
+        )
+    };
+    let names: Vec<String> = match state.price_cache.read().await.as_ref() {
+        Some(snapshot)
+            if prices::now_unix().saturating_sub(snapshot.fetched_at) >= interval_secs =>
+        {
+            snapshot.prices.keys().cloned().collect()
+        }
+        _ => return,
+    };
+    if names.is_empty() {
+        return;
+    }
+
+    let mut snapshot =
+        match prices::fetch_snapshot(&names, source, retry, &state.structure_market).await {
+            Ok(snapshot) if !snapshot.stale => snapshot,
+            Ok(_) => {
+                tracing::warn!("Price source unreachable, keeping cached prices");
+                return;
+            }
+            Err(e) => {
+                tracing::warn!(error = %e, "Background price refresh failed");
+                return;
+            }
+        };
+    // The market may have been changed while fetching
+    let regional = {
+        let settings = state.settings.read().await;
+        if settings.price_source() != source {
+            return;
+        }
+        settings.regional_moon_pricing
+    };
+
+    let updated = prices::PricesUpdated {
+        source: snapshot.source.clone(),
+        fetched_at: snapshot.fetched_at,
+        items: snapshot.prices.len(),
+    };
+    // Keep the user's goo at the mined-region prices the last analysis used
+    let mut cache = state.price_cache.write().await;
+    if regional {
+        if let Some(old) = cache.take() {
+            snapshot.regional_goo = old.regional_goo;
+        }
+    }
+    *cache = Some(snapshot);
+    drop(cache);
+    state.subtree_cache.invalidate();
+    tracing::info!(items = updated.items, "Refreshed prices");
+    let _ = app.emit(prices::PRICES_UPDATED_EVENT, updated);
+    refresh_detail_windows(app).await;
+}
+
+// Get the watched reactions and how often they are re-priced
+#[tauri::command]
+fn get_margin_alerts() -> MarginAlerts {
+    MarginAlerts::load()
+}
+
+// Watch a reaction's margin and alert when it goes above (or below) the threshold
+#[tauri::command]
+async fn set_margin_alert(
+    formula_id: u32,
+    threshold: f64,
+    crossing: Option<Crossing>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let formula_name = state
+        .reactions_db()
+        .await
+        .reactions
+        .iter()
+        .find(|r| r.formula_id == formula_id)
+        .map(|r| r.formula_name.clone())
+        .ok_or_else(|| format!("Unknown formula ID {}", formula_id))?;
+
+    MarginAlerts::update(|alerts| {
+        alerts.set(MarginAlert {
+            formula_id,
+            formula_name,
+            threshold,
+            crossing: crossing.unwrap_or_default(),
+            last_margin: None,
+            triggered: false,
+        })
+    })
+}
+
+// Stop watching a reaction's margin
+#[tauri::command]
+fn remove_margin_alert(formula_id: u32) -> Result<(), String> {
+    MarginAlerts::update(|alerts| {
+        alerts.remove(formula_id);
+        Ok(())
+    })
+}
+
+// Turn margin alerts on or off and set how often the watched reactions are re-priced
+#[tauri::command]
+fn set_margin_alert_schedule(enabled: bool, interval_mins: u64) -> Result<(), String> {
+    MarginAlerts::update(|alerts| {
+        alerts.set_interval(interval_mins)?;
+        alerts.enabled = enabled;
+        Ok(())
+    })
+}
+
+// Re-price the watched reactions at fresh prices when a check is due, and notify about
+// margins that crossed their threshold
+async fn check_margin_alerts(app: &AppHandle) {
+    let alerts = MarginAlerts::load();
+    let now = prices::now_unix();
+    if !alerts.is_due(now) {
+        return;
+    }
+
+    let state = app.state::<AppState>();
+    let margins = match watched_margins(&state, &alerts).await {
+        Ok(margins) => margins,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to re-price watched reactions");
+            return;
+        }
+    };
+    // Alerts may have been changed while prices were fetched, so record on a fresh copy
+    let fired = match MarginAlerts::update(|alerts| {
+        Ok(alerts.record(now, |formula_id| margins.get(&formula_id).copied()))
+    }) {
+        Ok(fired) => fired,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to save margin alerts");
+            return;
+        }
+    };
+    if fired.is_empty() {
+        return;
+    }
+
+    let _ = app.emit(alerts::MARGIN_ALERT_EVENT, &fired);
+    for alert in fired {
+        let side = match alert.crossing {
+            Crossing::Above => "above",
+            Crossing::Below => "below",
+        };
+        let body = format!(
+            "{} margin is {:.1}%, {} your {:.1}% alert",
+            alert.formula_name, alert.margin, side, alert.threshold
+        );
+        if let Err(e) = app
+            .notification()
+            .builder()
+            .title("Reaction margin")
+            .body(body)
+            .show()
+        {
+            tracing::warn!(error = %e, formula = %alert.formula_name, "Failed to show notification");
+        }
+    }
+}
+
+// Margins of the watched reactions at freshly fetched prices, by formula ID
+async fn watched_margins(
+    state: &AppState,
+    alerts: &MarginAlerts,
+) -> Result<HashMap<u32, f64>, String> {
+    let reactions_db = state.reactions_db().await;
+    let watched: Vec<&reactions::Reaction> = reactions_db
+        .reactions
+        .iter()
+        .filter(|r| alerts.alerts.iter().any(|a| a.formula_id == r.formula_id))
+        .collect();
+    let names: Vec<String> = watched
+        .iter()
+        .flat_map(|r| std::iter::once(&r.output).chain(&r.inputs))
+        .map(|item| item.name.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    let (source, retry, sale_options) = {
+        let settings = state.settings.read().await;
+        (
+            settings.price_source(),
+            settings.price_retry,
+            settings.sale_options.clone(),
+        )
+    };
+    let snapshot = prices::fetch_snapshot(&names, source, retry, &state.structure_market).await?;
+    let prices = state.price_pins.read().await.apply(&snapshot.prices);
+    let user_material_ids = user_material_ids(state, &reactions_db).await;
+
+    Ok(watched
+        .into_iter()
+        .filter_map(|reaction| {
+            prices::calculate_reaction_profit(reaction, &prices, &user_material_ids, &sale_options)
+                .map(|profit| (reaction.formula_id, profit.margin))
+        })
+        .collect())
+}
+
+// Get every profile and which one is active
+#[tauri::command]
+fn list_profiles() -> ProfileRegistry {
+    ProfileRegistry::load()
+}
+
+// Add an empty profile
+#[tauri::command]
+fn create_profile(name: String) -> Result<ProfileRegistry, String> {
+    let mut registry = ProfileRegistry::load();
+    registry.create(&name)?;
+    registry.save()?;
+    Ok(registry)
+}
+
+// Delete a profile other than the active one, along with its moons, inventory and settings
+#[tauri::command]
+fn delete_profile(name: String) -> Result<ProfileRegistry, String> {
+    let mut registry = ProfileRegistry::load();
+    registry.delete(&name)?;
+    registry.save()?;
+    Ok(registry)
+}
+
+// Make another profile active and load its moons, inventory, settings and trackers. Prices
+// and analysis results are dropped, since the new profile may price differently
+#[tauri::command]
+async fn switch_profile(
+    name: String,
+    state: State<'_, AppState>,
+) -> Result<ProfileRegistry, String> {
+    let mut data = state.lock_profile_data().await;
+    let mut registry = ProfileRegistry::load();
+    registry.switch(&name)?;
+    registry.save()?;
+    {
+        let _alerts = MarginAlerts::lock();
+        storage::set_active_profile(&registry.active);
+    }
+    data.reload();
+    drop(data);
+
+    reload_last_analysis(&state).await;
+    tracing::info!(profile = %name, "Switched profile");
+
+    Ok(registry)
+}
+
+// Re-read the active profile's last analysis, dropping results and prices from before
+async fn reload_last_analysis(state: &AppState) {
+    let last = LastAnalysis::load();
+    *state.analyzed_at.write().await = last.as_ref().map(|last| last.timestamp);
+    *state.price_cache.write().await = last.as_ref().map(|last| last.snapshot.clone());
+    *state.analysis_results.write().await = last.map(|last| last.results).unwrap_or_default();
+    state.subtree_cache.invalidate();
+}
+
+/// Re-read everything kept in memory after the data directory was replaced or wiped,
+/// including downloaded and custom reaction and ore data
+async fn reload_all_data(state: &AppState) -> Result<(), String> {
+    let mut data = state.lock_profile_data().await;
+    {
+        let _alerts = MarginAlerts::lock();
+        profiles::init();
+    }
+    data.reload();
+    drop(data);
+    reload_last_analysis(state).await;
+    *state.price_pins.write().await = PricePins::load();
+    *state.memory_mode.write().await = MemoryMode::load();
+    *state.staleness.write().await = StalenessThresholds::load();
+    swap_databases(state).await
+}
+
+// Write all moons, settings, inventory, plans and price snapshots, for every profile, to
+// one archive for moving to another machine. The ESI login and sync secret are only
+// included with `include_credentials`; anyone holding such an archive can use them
+#[tauri::command]
+fn backup_data(path: String, include_credentials: Option<bool>) -> Result<BackupSummary, String> {
+    let summary = backup::backup_data(
+        std::path::Path::new(&path),
+        include_credentials.unwrap_or(false),
+    )?;
+    tracing::info!(files = summary.files, "Backed up data");
+    Ok(summary)
+}
+
+// Replace all local data with a backup's and reload it. The current data is copied into
+// the backups folder first
+#[tauri::command]
+async fn restore_data(path: String, state: State<'_, AppState>) -> Result<BackupSummary, String> {
+    let summary = backup::restore_data(std::path::Path::new(&path))?;
+    reload_all_data(&state).await?;
+    tracing::info!(files = summary.files, "Restored data");
+    Ok(summary)
+}
+
+// Delete the telemetry device ID and, with `wipe_all`, every moon, setting, cache and
+// profile kept on this machine, leaving a clean slate. Logs are kept
+#[tauri::command]
+async fn purge_local_data(wipe_all: bool, state: State<'_, AppState>) -> Result<(), String> {
+    telemetry::reset_device_id()?;
+    if wipe_all {
+        let removed = storage::remove_all_except(&[logging::LOG_DIR])?;
+        reload_all_data(&state).await?;
+        tracing::info!(removed, "Purged local data");
+    } else {
+        tracing::info!("Reset telemetry device ID");
+    }
+    Ok(())
+}
+
+// Get the app settings
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> Result<Settings, String> {
+    Ok(state.settings.read().await.clone())
+}
+
+// Validate, persist and apply new app settings. Changing the market, structure or pricing
+// mode drops the cached prices so nothing mixes prices from two sources
+#[tauri::command]
+async fn update_settings(settings: Settings, state: State<'_, AppState>) -> Result<(), String> {
+    settings.validate()?;
+    settings.save()?;
+
+    let mut current = state.settings.write().await;
+    if current.price_source() != settings.price_source() {
+        *state.price_cache.write().await = None;
+        state.subtree_cache.invalidate();
+    }
+    *current = settings;
+    Ok(())
+}
+
+// Get whether the user has been asked about telemetry and what they answered
+#[tauri::command]
+fn get_telemetry_consent() -> TelemetryConsent {
+    telemetry::consent()
+}
+
+// Record the user's telemetry decision. Nothing is sent until they agree
+#[tauri::command]
+async fn set_telemetry_consent(
+    granted: bool,
+    state: State<'_, AppState>,
+) -> Result<TelemetryConsent, String> {
+    let consent = telemetry::set_consent(granted)?;
+    let mut settings = state.settings.write().await;
+    if settings.telemetry_enabled != granted {
+        settings.telemetry_enabled = granted;
+        settings.save()?;
+    }
+    Ok(consent)
+}
+
+// Get the exact payload the launch ping sends. Asking doesn't create the device ID
+#[tauri::command]
+fn get_telemetry_payload() -> LaunchPayload {
+    telemetry::preview_payload()
+}
+
+// Get the ESI application details and whether a character is logged in
+#[tauri::command]
+fn get_esi_auth() -> EsiAuthStatus {
+    EsiAuth::load().status()
+}
+
+// Start an EVE SSO login for structure market access and return the URL to open. The
+// browser is sent to the callback URL afterwards, which is passed to complete_esi_login
+#[tauri::command]
+async fn begin_esi_login(
+    client_id: String,
+    callback_url: String,
+    state: State<'_, AppState>,
+) -> Result<String, String> {
+    let _login = state.structure_market.lock_login().await;
+    let mut auth = EsiAuth::load();
+    let url = auth.begin_login(&client_id, &callback_url)?;
+    auth.save()?;
+    Ok(url)
+}
+
+// Finish the EVE SSO login with the address the browser was sent back to
+#[tauri::command]
+async fn complete_esi_login(
+    redirected_url: String,
+    state: State<'_, AppState>,
+) -> Result<EsiAuthStatus, String> {
+    let _login = state.structure_market.lock_login().await;
+    let mut auth = EsiAuth::load();
+    auth.complete_login(&redirected_url).await?;
+    auth.save()?;
+    tracing::info!("Logged in to ESI");
+    Ok(auth.status())
+}
+
+// Forget the ESI login
+#[tauri::command]
+async fn esi_logout(state: State<'_, AppState>) -> Result<(), String> {
+    let _login = state.structure_market.lock_login().await;
+    let mut auth = EsiAuth::load();
+    auth.logout();
+    auth.save()
+}
+
+// Get the version of the reaction and ore data in use
+#[tauri::command]
+fn get_dataset_info() -> DatasetInfo {
+    datasets::installed()
+}
+
+// Download the latest reaction and ore data if there is a newer release. The new data is
+// used after reload_databases or the next start
+#[tauri::command]
+async fn update_datasets() -> Result<DatasetInfo, String> {
+    datasets::update().await
+}
+
+// Re-read the reaction and ore data (downloaded, or bundled if there is none) with the
+// user's custom files merged over it, and swap it in without restarting
+#[tauri::command]
+async fn reload_databases(state: State<'_, AppState>) -> Result<DatasetInfo, String> {
+    swap_databases(&state).await?;
+    Ok(datasets::installed())
+}
+
+/// Load the reaction and ore data from disk and replace the ones in use
+async fn swap_databases(state: &AppState) -> Result<(), String> {
+    let reactions_db = ReactionDatabase::load()?;
+    let ore_mappings = OreMappings::load()?;
+
+    // Hold both locks so no reader sees new reactions with old mappings
+    let mut reactions_slot = state.reactions_db.write().await;
+    let mut mappings_slot = state.ore_mappings.write().await;
+    *reactions_slot = Arc::new(reactions_db);
+    *mappings_slot = Arc::new(ore_mappings);
+    state.subtree_cache.invalidate();
+    Ok(())
+}
+
+// Check GitHub for a newer release of the app
+#[tauri::command]
+async fn check_for_updates() -> Result<UpdateInfo, String> {
+    updates::check().await
+}
+
+// Get the last `n` lines of the log (200 by default) to attach to bug reports
+#[tauri::command]
+fn get_recent_logs(n: Option<usize>) -> Result<Vec<String>, String> {
+    logging::recent_lines(n.unwrap_or(200))
+}
+
+// Get release notes, data update notices and market warnings, refreshing the cached feed
+// when it is old (or always, with `refresh`)
+#[tauri::command]
+async fn get_announcements(
+    refresh: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<Vec<AnnouncementEntry>, String> {
+    let ttl_secs = state.settings.read().await.announcement_ttl_secs;
+    AnnouncementCache::refreshed_entries(refresh.unwrap_or_default(), ttl_secs).await
+}
+
+// Mark an announcement as read
+#[tauri::command]
+fn mark_announcement_read(id: String) -> Result<(), String> {
+    AnnouncementCache::mark_read(&id)
+}
+
+#[cfg_attr(mobile, tauri::mobile_entry_point)]
+pub fn run() {
+    // Fix for WebKitGTK on certain Linux/Wayland systems
+    #[cfg(target_os = "linux")]
+    {
+        std::env::set_var("WEBKIT_DISABLE_DMABUF_RENDERER", "1");
+        // Set app ID for Wayland taskbar icon
+        std::env::set_var("GDK_BACKEND", "x11");
+    }
+
+    // Keep the guard until the app exits so buffered log lines get written
+    let _log_guard = logging::init();
+    crash::install_panic_hook();
+
+    // Upgrade data written by older versions before anything reads it
+    if let Err(e) = migrations::run() {
+        tracing::error!(error = %e, "Data migration failed");
+    }
+
+    profiles::init();
+    let settings = Settings::load();
+    // A broken custom data file shouldn't stop the app from starting
+    let reactions_db = ReactionDatabase::load()
+        .or_else(|e| {
+            tracing::warn!(error = %e, "Ignoring custom reactions");
+            ReactionDatabase::load_standard()
+        })
+        .expect("Failed to load reactions database");
+    let ore_mappings = OreMappings::load()
+        .or_else(|e| {
+            tracing::warn!(error = %e, "Ignoring custom ore mappings");
+            OreMappings::load_standard()
+        })
+        .expect("Failed to load ore mappings");
+
+    let telemetry_enabled = settings.telemetry_enabled;
+    // Results and prices of the last analysis, so they show without recomputing
+    let last_analysis = LastAnalysis::load();
+
+    tauri::Builder::default()
+        .plugin(tauri_plugin_opener::init())
+        .plugin(tauri_plugin_notification::init())
+        .manage(AppState {
+            moons: RwLock::new(profiles::load_moons()),
+            analyzed_at: RwLock::new(last_analysis.as_ref().map(|last| last.timestamp)),
+            price_cache: RwLock::new(last_analysis.as_ref().map(|last| last.snapshot.clone())),
+            analysis_results: RwLock::new(
+                last_analysis.map(|last| last.results).unwrap_or_default(),
+            ),
+            inventory: RwLock::new(Inventory::load()),
+            mining_ledger: RwLock::new(MiningLedger::load()),
+            extraction_schedule: RwLock::new(ExtractionSchedule::load()),
+            staleness: RwLock::new(StalenessThresholds::load()),
+            analysis_cancel: CancelToken::default(),
+            memory_mode: RwLock::new(MemoryMode::load()),
+            price_pins: RwLock::new(PricePins::load()),
+            reactions_db: RwLock::new(Arc::new(reactions_db)),
+            ore_mappings: RwLock::new(Arc::new(ore_mappings)),
+            subtree_cache: SubtreeCache::default(),
+            settings: RwLock::new(settings),
+            detail_windows: DetailWindows::default(),
+            structure_market: StructureMarket::default(),
+        })
+        .setup(move |app| {
+            // Set window icon for Linux/Wayland
+            if let Some(window) = app.get_webview_window("main") {
+                let icon = app.default_window_icon().cloned();
+                if let Some(icon) = icon {
+                    let _ = window.set_icon(icon);
+                }
+            }
+
+            // Send telemetry ping and any crash reports from earlier runs on launch, if the
+            // user agreed to telemetry and hasn't turned it off since
+            if telemetry_enabled {
+                telemetry::send_launch_ping();
+                telemetry::send_crash_reports(crash::unsubmitted());
+            }
+
+            // Check for lapsed price pins every minute
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    expire_price_pins(&handle).await;
+                }
+            });
+
+            // Notify about chunks arriving soon, checking every minute so alerts still show
+            // while the window is minimized
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    notify_upcoming_extractions(&handle).await;
+                }
+            });
+
+            // Refresh cached prices when the interval set in settings has passed
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    refresh_prices_if_due(&handle).await;
+                }
+            });
+
+            // Re-price watched reactions when their check is due
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+                loop {
+                    interval.tick().await;
+                    check_margin_alerts(&handle).await;
+                }
+            });
+
+            // Write the weekly summary when it is due, checking every hour
+            let handle = app.handle().clone();
+            tauri::async_runtime::spawn(async move {
+                let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+                loop {
+                    interval.tick().await;
+                    weekly_report_if_due(&handle).await;
+                }
+            });
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             parse_moon_data,
+            parse_moon_data_lenient,
             add_moon,
             delete_moon,
             get_moons,
+            set_moon_notes,
+            export_moonpack,
+            import_moonpack,
+            get_sync_config,
+            set_sync_config,
+            sync_moons,
             get_unique_materials,
-            analyze_reactions
+            search_everything,
+            search_reactions,
+            analyze_reactions,
+            cancel_analysis,
+            get_analysis_page,
+            get_last_analysis,
+            export_analysis,
+            get_reaction_detail,
+            build_tree,
+            export_tree_dot,
+            export_tree_text,
+            recompute_tree,
+            simulate_prices,
+            list_analyses,
+            get_analysis,
+            list_price_snapshots,
+            load_price_snapshot,
+            compare_analyses,
+            rank_moons,
+            score_moons,
+            explain_calculation,
+            get_input_sensitivity,
+            get_margin_alerts,
+            set_margin_alert,
+            remove_margin_alert,
+            set_margin_alert_schedule,
+            compare_bonus_scenarios,
+            compare_structures,
+            get_moon_structures,
+            set_moon_structure,
+            remove_moon_structure,
+            get_max_buy_prices,
+            get_shopping_list,
+            get_bill_of_materials,
+            get_run_summary,
+            schedule_reaction_jobs,
+            open_detail_window,
+            get_detail_view,
+            set_detail_target,
+            export_multibuy,
+            create_appraisal_link,
+            plan_runs,
+            plan_steady_state,
+            appraise_paste,
+            appraise_items,
+            import_mining_ledger,
+            get_mined_amounts,
+            clear_mining_ledger,
+            apply_ledger_extractions,
+            set_extracted_units,
+            calculate_extraction,
+            compare_moon_sales,
+            list_profiles,
+            create_profile,
+            delete_profile,
+            switch_profile,
+            purge_local_data,
+            backup_data,
+            restore_data,
+            schedule_extraction,
+            remove_extraction,
+            get_upcoming_extractions,
+            get_extractions_by_structure,
+            export_extraction_calendar,
+            get_inventory,
+            set_stock,
+            import_inventory,
+            get_stockpile_valuation,
+            value_inventory,
+            get_staleness_thresholds,
+            set_staleness_thresholds,
+            get_memory_mode,
+            set_memory_mode,
+            get_settings,
+            update_settings,
+            get_telemetry_consent,
+            set_telemetry_consent,
+            get_telemetry_payload,
+            get_esi_auth,
+            begin_esi_login,
+            complete_esi_login,
+            esi_logout,
+            get_dataset_info,
+            update_datasets,
+            reload_databases,
+            get_recent_logs,
+            check_for_updates,
+            get_announcements,
+            mark_announcement_read,
+            pin_price,
+            unpin_price,
+            get_price_pins,
+            get_weekly_report_config,
+            set_weekly_report_config,
+            run_weekly_report,
+            generate_report
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,73 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader};
+
+use tracing::Level;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+
+use crate::storage;
+
+/// Kept when local data is purged, since the current log file is open
+pub const LOG_DIR: &str = "logs";
+const LOG_PREFIX: &str = "moon";
+const LOG_SUFFIX: &str = "log";
+/// Daily log files kept before the oldest is deleted
+const MAX_LOG_FILES: usize = 7;
+
+/// Start writing logs to a daily rotated file in the data directory. The returned guard
+/// flushes buffered lines when dropped, so keep it alive for as long as the app runs
+pub fn init() -> Option<WorkerGuard> {
+    let dir = storage::data_file(LOG_DIR)?;
+    let appender = RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix(LOG_PREFIX)
+        .filename_suffix(LOG_SUFFIX)
+        .max_log_files(MAX_LOG_FILES)
+        .build(&dir)
+        .ok()?;
+    let (writer, guard) = tracing_appender::non_blocking(appender);
+
+    tracing_subscriber::fmt()
+        .with_writer(writer)
+        .with_ansi(false)
+        .with_max_level(Level::INFO)
+        .try_init()
+        .ok()?;
+    Some(guard)
+}
+
+/// The last `count` log lines, oldest first, reading back through older files as needed
+pub fn recent_lines(count: usize) -> Result<Vec<String>, String> {
+    let Some(dir) = storage::data_file(LOG_DIR) else {
+        return Ok(Vec::new());
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(Vec::new());
+    };
+
+    // Rotated files are named by date, so name order is age order
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with(LOG_PREFIX))
+        })
+        .collect();
+    files.sort();
+
+    let mut lines: VecDeque<String> = VecDeque::with_capacity(count);
+    for path in files.iter().rev() {
+        let file = fs::File::open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let file_lines: Vec<String> = BufReader::new(file).lines().map_while(Result::ok).collect();
+        for line in file_lines.into_iter().rev() {
+            if lines.len() == count {
+                return Ok(lines.into());
+            }
+            lines.push_front(line);
+        }
+    }
+    Ok(lines.into())
+}
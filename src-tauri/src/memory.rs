@@ -0,0 +1,44 @@
+use serde::{Deserialize, Serialize};
+
+use crate::storage;
+
+const MEMORY_MODE_FILE: &str = "memory_mode.json";
+
+/// Trade-offs for running on machines with little memory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MemoryMode {
+    /// Skip history retention, cap tree depth and write exports straight to disk
+    pub low_memory: bool,
+    /// Deepest reaction level expanded in analysis trees while in low-memory mode
+    pub max_tree_depth: usize,
+}
+
+impl Default for MemoryMode {
+    fn default() -> Self {
+        Self {
+            low_memory: false,
+            max_tree_depth: 3,
+        }
+    }
+}
+
+impl MemoryMode {
+    pub fn load() -> Self {
+        storage::load_json(MEMORY_MODE_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(MEMORY_MODE_FILE, self)
+    }
+
+    /// Depth limit for analysis trees, if any
+    pub fn tree_depth_cap(&self) -> Option<usize> {
+        self.low_memory.then_some(self.max_tree_depth)
+    }
+
+    /// Whether analysis and price history should be kept on disk
+    pub fn retain_history(&self) -> bool {
+        !self.low_memory
+    }
+}
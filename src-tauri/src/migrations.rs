@@ -0,0 +1,171 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::prices::now_unix;
+use crate::storage;
+
+const LAYOUT_FILE: &str = "layout_version.json";
+/// Copies of the data directory taken before it is changed in bulk
+pub const BACKUP_DIR: &str = "backups";
+
+/// Version of the on-disk layout the data directory is at
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LayoutVersion {
+    version: u32,
+}
+
+/// One upgrade step, taking the data directory from `version - 1` to `version`
+struct Migration {
+    version: u32,
+    description: &'static str,
+    run: fn(&Path) -> Result<(), String>,
+}
+
+/// Every migration, in order. Data written before versioning existed is version 0
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Move the bare device_id file into telemetry.json",
+        run: migrate_device_id,
+    },
+    Migration {
+        version: 2,
+        description: "Move per-profile files into the default profile",
+        run: migrate_default_profile,
+    },
+];
+
+/// Files that moved from the top of the data directory into each profile's folder in v2
+const PROFILE_FILES: &[&str] = &[
+    "inventory.json",
+    "settings.json",
+    "mining_ledger.json",
+    "extraction_schedule.json",
+    "weekly_report.json",
+    "stockpile_history.json",
+];
+
+/// Layout version this build reads and writes
+pub fn current_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or(0)
+}
+
+/// Bring the data directory up to the current layout, backing it up first if anything changes
+pub fn run() -> Result<(), String> {
+    let Some(data_dir) = storage::data_dir() else {
+        return Ok(());
+    };
+
+    let has_data = fs::read_dir(&data_dir)
+        .map(|mut entries| entries.next().is_some())
+        .unwrap_or(false);
+    let stamped = storage::load_json::<LayoutVersion>(LAYOUT_FILE);
+    let version = match &stamped {
+        Some(layout) => layout.version,
+        // Nothing on disk yet: a fresh install starts at the current layout
+        None if !has_data => current_version(),
+        None => 0,
+    };
+
+    let pending: Vec<&Migration> = MIGRATIONS.iter().filter(|m| m.version > version).collect();
+    if !pending.is_empty() {
+        let backup = back_up(&format!("v{}", version))?;
+
+        for migration in pending {
+            (migration.run)(&data_dir).map_err(|e| {
+                format!(
+                    "Migration {} ({}) failed, a backup is in {}: {}",
+                    migration.version,
+                    migration.description,
+                    backup.display(),
+                    e
+                )
+            })?;
+            storage::save_json(
+                LAYOUT_FILE,
+                &LayoutVersion {
+                    version: migration.version,
+                },
+            )?;
+        }
+    } else if stamped.is_none() {
+        storage::save_json(
+            LAYOUT_FILE,
+            &LayoutVersion {
+                version: current_version(),
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Record the layout the data directory is at, e.g. after restoring files from a backup
+pub fn stamp(version: u32) -> Result<(), String> {
+    storage::save_json(LAYOUT_FILE, &LayoutVersion { version })
+}
+
+/// Copy the data directory into the backups folder, named by `label` and the time, and
+/// return where it went
+pub fn back_up(label: &str) -> Result<std::path::PathBuf, String> {
+    let data_dir = storage::data_dir().ok_or("Could not determine app data directory")?;
+    let backup = data_dir
+        .join(BACKUP_DIR)
+        .join(format!("{}-{}", label, now_unix()));
+    copy_dir(&data_dir, &backup)?;
+    Ok(backup)
+}
+
+/// Copy a directory tree, leaving out earlier backups
+fn copy_dir(from: &Path, to: &Path) -> Result<(), String> {
+    fs::create_dir_all(to).map_err(|e| format!("Failed to create {}: {}", to.display(), e))?;
+
+    let entries =
+        fs::read_dir(from).map_err(|e| format!("Failed to read {}: {}", from.display(), e))?;
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if entry.file_name() == BACKUP_DIR {
+            continue;
+        }
+        let target = to.join(entry.file_name());
+        if path.is_dir() {
+            copy_dir(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)
+                .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+        }
+    }
+    Ok(())
+}
+
+/// v2: moons, inventory, settings and trackers now belong to a profile; whatever existed
+/// becomes the default profile's
+fn migrate_default_profile(data_dir: &Path) -> Result<(), String> {
+    let profile_dir = data_dir.join("profiles").join(storage::DEFAULT_PROFILE);
+    for file in PROFILE_FILES {
+        let legacy = data_dir.join(file);
+        if !legacy.exists() {
+            continue;
+        }
+        fs::create_dir_all(&profile_dir)
+            .map_err(|e| format!("Failed to create {}: {}", profile_dir.display(), e))?;
+        fs::rename(&legacy, profile_dir.join(file))
+            .map_err(|e| format!("Failed to move {}: {}", legacy.display(), e))?;
+    }
+    Ok(())
+}
+
+/// v1: the device ID used to be a bare text file; it now lives in telemetry.json
+fn migrate_device_id(data_dir: &Path) -> Result<(), String> {
+    let legacy = data_dir.join("device_id");
+    let Ok(contents) = fs::read_to_string(&legacy) else {
+        return Ok(());
+    };
+
+    let device_id = contents.trim();
+    if !device_id.is_empty() {
+        crate::telemetry::save_device_id(device_id)?;
+    }
+    fs::remove_file(&legacy).map_err(|e| format!("Failed to remove {}: {}", legacy.display(), e))
+}
@@ -0,0 +1,112 @@
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::parser::MoonComposition;
+use crate::prices::now_unix;
+use crate::storage;
+use crate::valuation::MoonValuation;
+
+/// Version of the bundle layout written by this build
+const FORMAT_VERSION: u32 = 1;
+/// Largest bundle read, uncompressed; far more than any corp's moons take
+const MAX_PACK_BYTES: u64 = 64 * 1_000_000;
+
+/// A corp's scanned moons in one file: gzip compressed JSON with the `.moonpack` extension
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoonPack {
+    pub format_version: u32,
+    /// Unix timestamp (seconds)
+    pub created_at: u64,
+    pub moons: Vec<MoonComposition>,
+    /// Valuations at the exporter's prices, if they could be fetched
+    #[serde(default)]
+    pub valuations: Vec<MoonValuation>,
+}
+
+/// What importing a bundle changed
+#[derive(Debug, Clone, Serialize)]
+pub struct MoonPackImport {
+    /// Moons that weren't loaded before
+    pub added: Vec<String>,
+    /// Moons already loaded that gained tags or notes
+    pub updated: Vec<String>,
+    /// Valuations carried in the bundle
+    pub valuations: Vec<MoonValuation>,
+}
+
+impl MoonPack {
+    pub fn new(moons: Vec<MoonComposition>, valuations: Vec<MoonValuation>) -> Self {
+        Self {
+            format_version: FORMAT_VERSION,
+            created_at: now_unix(),
+            moons,
+            valuations,
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+        let mut encoder = GzEncoder::new(BufWriter::new(file), Compression::default());
+        serde_json::to_writer(&mut encoder, self)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        encoder
+            .finish()
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let contents = storage::read_gzip(path, MAX_PACK_BYTES)?;
+        let pack: MoonPack = serde_json::from_slice(&contents)
+            .map_err(|e| format!("{} isn't a valid moon pack: {}", path.display(), e))?;
+        if pack.format_version > FORMAT_VERSION {
+            return Err(format!(
+                "{} was made by a newer version of the app",
+                path.display()
+            ));
+        }
+        Ok(pack)
+    }
+
+    /// Merge the bundle into the loaded moons. New moons are added; for moons already
+    /// loaded (matched by celestial IDs, or by name), the local scan is kept, tags are
+    /// combined and notes are filled in if empty
+    pub fn merge_into(self, moons: &mut Vec<MoonComposition>) -> MoonPackImport {
+        let mut added = Vec::new();
+        let mut updated = Vec::new();
+
+        for incoming in self.moons {
+            let Some(existing) = moons.iter_mut().find(|m| m.is_same_moon(&incoming)) else {
+                added.push(incoming.name.clone());
+                moons.push(incoming);
+                continue;
+            };
+
+            let mut changed = false;
+            for tag in incoming.tags {
+                if !existing.tags.contains(&tag) {
+                    existing.tags.push(tag);
+                    changed = true;
+                }
+            }
+            if existing.notes.trim().is_empty() && !incoming.notes.trim().is_empty() {
+                existing.notes = incoming.notes;
+                changed = true;
+            }
+            if changed {
+                updated.push(existing.name.clone());
+            }
+        }
+
+        MoonPackImport {
+            added,
+            updated,
+            valuations: self.valuations,
+        }
+    }
+}
@@ -1,7 +1,15 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
-#[derive(Debug, Deserialize)]
+use crate::datasets;
+use crate::parser::{MaterialEntry, MoonComposition};
+use crate::storage;
+
+/// Ore mappings in the app data directory that add to or replace the standard data
+const CUSTOM_MAPPINGS_FILE: &str = "mappings.custom.json";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 struct OreMappingsFile {
     #[serde(rename = "R4_Ubiquitous")]
     r4: HashMap<String, HashMap<String, u32>>,
@@ -13,16 +21,104 @@ struct OreMappingsFile {
     r32: HashMap<String, HashMap<String, u32>>,
     #[serde(rename = "R64_Exceptional")]
     r64: HashMap<String, HashMap<String, u32>>,
+    /// Ore names and variant prefixes as shown by non-English clients, by language, mapped
+    /// to their English names
+    #[serde(rename = "Localized")]
+    localized: HashMap<String, HashMap<String, String>>,
+    /// Ore type IDs (as listed in moon scans) mapped to their base ore names
+    #[serde(rename = "TypeIds")]
+    type_ids: HashMap<u32, String>,
+}
+
+impl OreMappingsFile {
+    fn tiers_mut(&mut self) -> [&mut HashMap<String, HashMap<String, u32>>; 5] {
+        [
+            &mut self.r4,
+            &mut self.r8,
+            &mut self.r16,
+            &mut self.r32,
+            &mut self.r64,
+        ]
+    }
+
+    /// Add custom ores, replacing standard ores of the same name (even in another tier), and
+    /// custom translations and type IDs
+    fn merge(&mut self, mut custom: OreMappingsFile) {
+        self.type_ids.extend(custom.type_ids.drain());
+        for (language, names) in custom.localized.drain() {
+            self.localized.entry(language).or_default().extend(names);
+        }
+        for (index, custom_tier) in custom.tiers_mut().into_iter().enumerate() {
+            for (ore_name, materials) in custom_tier.drain() {
+                for tier in self.tiers_mut() {
+                    tier.remove(&ore_name);
+                }
+                self.tiers_mut()[index].insert(ore_name, materials);
+            }
+        }
+    }
+}
+
+fn parse_mappings(json_str: &str) -> Result<OreMappingsFile, String> {
+    serde_json::from_str(json_str).map_err(|e| format!("Failed to parse mappings: {}", e))
+}
+
+/// The downloaded ore mappings, or the bundled copy if there are none or they don't parse
+fn standard_mappings() -> Result<OreMappingsFile, String> {
+    match datasets::installed_mappings().map(|json| parse_mappings(&json)) {
+        Some(Ok(mappings)) => Ok(mappings),
+        _ => parse_mappings(datasets::BUNDLED_MAPPINGS),
+    }
+}
+
+/// Volume of one unit of any moon ore
+pub const MOON_ORE_VOLUME_M3: f64 = 10.0;
+
+/// Asteroid ores that moons can also carry, under the moon variant prefixes, with the
+/// volume of one unit
+const ASTEROID_ORES: &[(&str, f64)] = &[
+    ("Arkonor", 16.0),
+    ("Bistot", 16.0),
+    ("Crokite", 16.0),
+    ("Dark Ochre", 8.0),
+    ("Gneiss", 5.0),
+    ("Hedbergite", 3.0),
+    ("Hemorphite", 3.0),
+    ("Jaspet", 2.0),
+    ("Kernite", 1.2),
+    ("Mercoxit", 40.0),
+    ("Omber", 0.6),
+    ("Plagioclase", 0.35),
+    ("Pyroxeres", 0.3),
+    ("Scordite", 0.15),
+    ("Spodumain", 16.0),
+    ("Veldspar", 0.1),
+];
+
+fn is_variant_prefix(word: &str) -> bool {
+    ORE_PREFIXES
+        .iter()
+        .any(|prefix| prefix.strip_suffix(' ') == Some(word))
+}
+
+fn asteroid_ore_volume(base_ore: &str) -> Option<f64> {
+    ASTEROID_ORES
+        .iter()
+        .find(|(name, _)| *name == base_ore)
+        .map(|(_, volume)| *volume)
 }
 
 /// Known ore variant prefixes that should be stripped to get base ore name
 const ORE_PREFIXES: &[&str] = &[
     "Bountiful ",
+    "Brimful ",
     "Copious ",
     "Dazzling ",
     "Flawless ",
     "Gilded ",
+    "Glistening ",
     "Glossy ",
+    "Glowing ",
     "Immaculate ",
     "Lavish ",
     "Lustrous ",
@@ -35,62 +131,169 @@ const ORE_PREFIXES: &[&str] = &[
     "Replete ",
     "Resplendent ",
     "Shimmering ",
+    "Shining ",
     "Sparkling ",
     "Stable ",
     "Twinkling ",
     "Brilliant ",
 ];
 
+/// Rarity tier of a moon ore
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum OreTier {
+    R4,
+    R8,
+    R16,
+    R32,
+    R64,
+}
+
+impl OreTier {
+    pub const ALL: [OreTier; 5] = [
+        OreTier::R4,
+        OreTier::R8,
+        OreTier::R16,
+        OreTier::R32,
+        OreTier::R64,
+    ];
+}
+
 pub struct OreMappings {
     /// Map from base ore name -> list of moon goo materials it produces
     ore_to_goo: HashMap<String, Vec<String>>,
+    /// Map from base ore name -> moon goo units yielded per reprocessing batch
+    ore_goo_yields: HashMap<String, HashMap<String, u32>>,
+    /// Map from base ore name -> rarity tier
+    ore_tiers: HashMap<String, OreTier>,
+    /// Map from localized ore name or variant prefix (any language) -> English name
+    localized_names: HashMap<String, String>,
+    /// Map from ore type ID -> base ore name
+    type_id_ores: HashMap<u32, String>,
 }
 
 impl OreMappings {
+    /// Load the standard ore mappings with the user's custom mappings merged over them
     pub fn load() -> Result<Self, String> {
-        let json_str = include_str!("../mappings.json");
-        let mappings: OreMappingsFile = serde_json::from_str(json_str)
-            .map_err(|e| format!("Failed to parse mappings: {}", e))?;
+        let mut mappings = standard_mappings()?;
+
+        if let Some(json) = storage::load_text(CUSTOM_MAPPINGS_FILE) {
+            let custom =
+                parse_mappings(&json).map_err(|e| format!("{} in {}", e, CUSTOM_MAPPINGS_FILE))?;
+            mappings.merge(custom);
+        }
+
+        Ok(Self::from_file(mappings))
+    }
+
+    /// Load the standard ore mappings only, ignoring custom mappings
+    pub fn load_standard() -> Result<Self, String> {
+        Ok(Self::from_file(standard_mappings()?))
+    }
+
+    /// Parse ore mappings in the `mappings.json` format
+    pub fn from_json(json_str: &str) -> Result<Self, String> {
+        Ok(Self::from_file(parse_mappings(json_str)?))
+    }
 
+    fn from_file(mappings: OreMappingsFile) -> Self {
         let mut ore_to_goo: HashMap<String, Vec<String>> = HashMap::new();
+        let mut ore_goo_yields: HashMap<String, HashMap<String, u32>> = HashMap::new();
+        let mut ore_tiers: HashMap<String, OreTier> = HashMap::new();
 
         // Combine all tiers
-        for tier in [
-            mappings.r4,
-            mappings.r8,
-            mappings.r16,
-            mappings.r32,
-            mappings.r64,
+        for (tier, ores) in [
+            (OreTier::R4, mappings.r4),
+            (OreTier::R8, mappings.r8),
+            (OreTier::R16, mappings.r16),
+            (OreTier::R32, mappings.r32),
+            (OreTier::R64, mappings.r64),
         ] {
-            for (ore_name, materials) in tier {
-                let goo_materials: Vec<String> = materials
-                    .keys()
-                    .filter(|name| is_moon_goo(name))
-                    .cloned()
+            for (ore_name, materials) in ores {
+                let goo_yields: HashMap<String, u32> = materials
+                    .into_iter()
+                    .filter(|(name, _)| is_moon_goo(name))
                     .collect();
-                ore_to_goo.insert(ore_name, goo_materials);
+                ore_to_goo.insert(ore_name.clone(), goo_yields.keys().cloned().collect());
+                ore_goo_yields.insert(ore_name.clone(), goo_yields);
+                ore_tiers.insert(ore_name, tier);
             }
         }
 
-        Ok(Self { ore_to_goo })
+        let localized_names = mappings.localized.into_values().flatten().collect();
+
+        Self {
+            ore_to_goo,
+            ore_goo_yields,
+            ore_tiers,
+            localized_names,
+            type_id_ores: mappings.type_ids,
+        }
+    }
+
+    /// Names of all known base ores
+    pub fn ore_names(&self) -> impl Iterator<Item = &String> {
+        self.ore_tiers.keys()
+    }
+
+    /// Translate a localized ore name to English, word by word if the whole name isn't
+    /// known (so a localized prefix and ore name are each translated). Languages that put
+    /// the variant after the ore name, such as French, are turned around
+    pub fn english_name(&self, ore_name: &str) -> String {
+        let ore_name = ore_name.trim();
+        if let Some(english) = self.localized_names.get(ore_name) {
+            return english.clone();
+        }
+        let Some((first, rest)) = ore_name.split_once(' ') else {
+            return ore_name.to_string();
+        };
+        let first = self.english_name(first);
+        let rest = self.english_name(rest);
+        if is_variant_prefix(&rest) && !is_variant_prefix(&first) {
+            format!("{} {}", rest, first)
+        } else {
+            format!("{} {}", first, rest)
+        }
     }
 
-    /// Strip variant prefix from ore name to get base ore
-    pub fn get_base_ore_name(ore_name: &str) -> String {
+    /// Translate an ore name to English and strip its variant prefix to get the base ore
+    pub fn base_ore_name(&self, ore_name: &str) -> String {
+        let ore_name = self.english_name(ore_name);
         for prefix in ORE_PREFIXES {
             if let Some(stripped) = ore_name.strip_prefix(prefix) {
                 return stripped.to_string();
             }
         }
-        ore_name.to_string()
+        ore_name
+    }
+
+    /// Base ore of a scanned material, by type ID where known and by name otherwise
+    pub fn base_ore(&self, material: &MaterialEntry) -> String {
+        self.type_id_ores
+            .get(&material.item_id)
+            .cloned()
+            .unwrap_or_else(|| self.base_ore_name(&material.name))
+    }
+
+    /// Whether a scanned material is a moon ore or one of the asteroid ores moons carry
+    pub fn is_known_ore(&self, material: &MaterialEntry) -> bool {
+        let base_ore = self.base_ore(material);
+        self.ore_tiers.contains_key(&base_ore) || asteroid_ore_volume(&base_ore).is_some()
+    }
+
+    /// Volume of one unit of a base ore; unknown ores are assumed to be moon ore sized
+    pub fn ore_volume_m3(&self, base_ore: &str) -> f64 {
+        asteroid_ore_volume(base_ore).unwrap_or(MOON_ORE_VOLUME_M3)
     }
 
-    /// Given a list of ore names from moon scans, return the set of moon goo materials
-    pub fn ores_to_moon_goo(&self, ore_names: &[String]) -> HashSet<String> {
+    /// Given the materials from moon scans, return the set of moon goo materials
+    pub fn ores_to_moon_goo<'a>(
+        &self,
+        materials: impl IntoIterator<Item = &'a MaterialEntry>,
+    ) -> HashSet<String> {
         let mut goo_materials = HashSet::new();
 
-        for ore_name in ore_names {
-            let base_ore = Self::get_base_ore_name(ore_name);
+        for material in materials {
+            let base_ore = self.base_ore(material);
             if let Some(materials) = self.ore_to_goo.get(&base_ore) {
                 for mat in materials {
                     goo_materials.insert(mat.clone());
@@ -100,10 +303,31 @@ impl OreMappings {
 
         goo_materials
     }
+
+    /// Get the rarity tier of an ore (variant prefixes are stripped)
+    pub fn tier_of(&self, ore_name: &str) -> Option<OreTier> {
+        self.ore_tiers.get(&self.base_ore_name(ore_name)).copied()
+    }
+
+    /// Tag each material with its ore tier and note the moon's rarest tier. The share of
+    /// the moon in each tier is worked out with its value, see `valuation::value_moon`
+    pub fn classify(&self, moon: &mut MoonComposition) {
+        for material in &mut moon.materials {
+            material.tier = self.tier_of(&self.base_ore(material));
+        }
+        moon.highest_tier = crate::valuation::tier_fractions(moon, self)
+            .into_keys()
+            .max();
+    }
+
+    /// Get the moon goo units yielded by reprocessing one batch of an ore
+    pub fn goo_yields(&self, ore_name: &str) -> Option<&HashMap<String, u32>> {
+        self.ore_goo_yields.get(&self.base_ore_name(ore_name))
+    }
 }
 
 /// Check if a material name is moon goo (used in reactions) vs regular minerals
-fn is_moon_goo(name: &str) -> bool {
+pub fn is_moon_goo(name: &str) -> bool {
     matches!(
         name,
         // R4 moon goo
@@ -118,3 +342,54 @@ fn is_moon_goo(name: &str) -> bool {
         "Promethium" | "Neodymium" | "Dysprosium" | "Thulium"
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_localized_names_resolve_to_base_ores() {
+        let mappings = OreMappings::from_json(datasets::BUNDLED_MAPPINGS).unwrap();
+
+        assert_eq!(mappings.base_ore_name("Zinnober"), "Cinnabar");
+        assert_eq!(mappings.base_ore_name("Glitzernde Zeolithe"), "Zeolites");
+        assert_eq!(
+            mappings.english_name("Zéolites scintillant"),
+            "Glistening Zeolites"
+        );
+        assert_eq!(mappings.base_ore_name("Сияющий Иттербит"), "Ytterbite");
+        assert_eq!(mappings.base_ore_name("豊富な コバルタイト"), "Cobaltite");
+        // English names, including multi-word asteroid ores, are left alone
+        assert_eq!(
+            mappings.english_name("Glossy Dark Ochre"),
+            "Glossy Dark Ochre"
+        );
+        assert_eq!(mappings.tier_of("Shining Loparite"), Some(OreTier::R64));
+    }
+
+    #[test]
+    fn test_variant_ores_resolve_by_type_id() {
+        let mappings = OreMappings::from_json(datasets::BUNDLED_MAPPINGS).unwrap();
+        let material = |name: &str, item_id: u32| MaterialEntry {
+            name: name.to_string(),
+            quantity: 0.5,
+            item_id,
+            system_id: 0,
+            region_id: 0,
+            additional_id: 0,
+            tier: None,
+            extracted_units: None,
+        };
+
+        // Improved and jackpot variants, whatever name the client shows
+        assert_eq!(
+            mappings.base_ore(&material("Brimful Zeolites", 46280)),
+            "Zeolites"
+        );
+        assert_eq!(mappings.base_ore(&material("???", 46319)), "Ytterbite");
+        assert_eq!(
+            mappings.base_ore(&material("Copious Cobaltite", 0)),
+            "Cobaltite"
+        );
+    }
+}
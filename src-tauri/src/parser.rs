@@ -1,12 +1,29 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::ore_mappings::OreTier;
+use crate::universe::SystemInfo;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MoonComposition {
     pub name: String,
     pub materials: Vec<MaterialEntry>,
+    /// Labels for sorting moons, e.g. the owning corp or "contested"
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub notes: String,
+    /// Solar system and region, resolved from the scan's system ID when moons are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<SystemInfo>,
+    /// Jumps from the moon's system to the selected market hub, filled in with `location`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub jumps_to_market: Option<u32>,
+    /// Rarest ore tier the moon has any of, filled in when moons are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub highest_tier: Option<OreTier>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct MaterialEntry {
     pub name: String,
     pub quantity: f64,
@@ -14,30 +31,155 @@ pub struct MaterialEntry {
     pub system_id: u32,
     pub region_id: u32,
     pub additional_id: u32,
+    /// Rarity tier of the ore, filled in when moons are listed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tier: Option<OreTier>,
+    /// Ore units actually extracted, entered by hand or taken from the mining ledger
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extracted_units: Option<f64>,
+}
+
+impl MoonComposition {
+    /// Solar system, planet and moon IDs from the scan, which every material line repeats.
+    /// None when the scan didn't carry them
+    pub fn celestial_ids(&self) -> Option<(u32, u32, u32)> {
+        self.materials
+            .iter()
+            .map(|m| (m.system_id, m.region_id, m.additional_id))
+            .find(|ids| *ids != (0, 0, 0))
+    }
+
+    /// Whether both are scans of the same moon: by celestial IDs when both have them, so
+    /// a renamed or differently pasted moon still matches, otherwise by name
+    pub fn is_same_moon(&self, other: &MoonComposition) -> bool {
+        match (self.celestial_ids(), other.celestial_ids()) {
+            (Some(a), Some(b)) => a == b,
+            _ => self.name == other.name,
+        }
+    }
+}
+
+/// What was wrong with the pasted text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseErrorKind {
+    /// A material line has fewer than the 6 expected fields
+    MissingFields,
+    /// A quantity or ID isn't a number
+    InvalidNumber,
+    /// A material line came before any moon name
+    MaterialBeforeMoon,
+    /// A moon name isn't followed by any material lines
+    MoonWithoutMaterials,
+    /// Nothing in the paste looks like a moon scan
+    NoMoonData,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub message: String,
+    /// 1-based line number in the pasted text; None for errors about the paste as a whole
+    pub line: Option<usize>,
+    /// 1-based character column of the offending field, when one field is to blame
+    pub column: Option<usize>,
+    /// The offending line as pasted
+    pub text: Option<String>,
 }
 
-#[derive(Debug)]
-pub enum ParseError {
-    InvalidFormat(String),
-    InvalidNumber(String),
+impl ParseError {
+    fn new(kind: ParseErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+            line: None,
+            column: None,
+            text: None,
+        }
+    }
+
+    fn at_column(mut self, column: usize) -> Self {
+        self.column = Some(column);
+        self
+    }
+
+    fn on_line(mut self, line: usize, text: &str) -> Self {
+        self.line = Some(line);
+        self.text = Some(text.to_string());
+        self
+    }
 }
 
 impl std::fmt::Display for ParseError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            ParseError::InvalidFormat(msg) => write!(f, "Invalid format: {}", msg),
-            ParseError::InvalidNumber(msg) => write!(f, "Invalid number: {}", msg),
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "Line {}, column {}: {}", line, column, self.message)
+            }
+            (Some(line), None) => write!(f, "Line {}: {}", line, self.message),
+            _ => write!(f, "{}", self.message),
         }
     }
 }
 
 impl std::error::Error for ParseError {}
 
+/// How `parse_moon_data_with` treats lines it can't make sense of
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParseMode {
+    /// Fail the whole paste on the first bad line
+    #[default]
+    Strict,
+    /// Skip bad lines and moons without materials, reporting each as a warning
+    Lenient,
+}
+
+/// How far a moon's material quantities may sum from 1.0 before it's flagged
+const QUANTITY_SUM_TOLERANCE: f64 = 0.02;
+
+/// Why a parse produced a warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParseWarningKind {
+    /// A line was skipped by a lenient parse
+    SkippedLine,
+    /// A moon's material quantities don't add up to roughly 1.0
+    QuantitySum,
+    /// A material isn't a known ore
+    UnknownOre,
+}
+
+/// Something a parse skipped or found suspicious, without failing the paste
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseWarning {
+    pub kind: ParseWarningKind,
+    /// 1-based line number in the pasted text, when the warning is about a skipped line
+    pub line: Option<usize>,
+    /// Moon the warning is about, when it's about a parsed moon
+    pub moon: Option<String>,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ParseResult {
+    pub moons: Vec<MoonComposition>,
+    pub warnings: Vec<ParseWarning>,
+}
+
 pub fn parse_moon_data(input: &str) -> Result<Vec<MoonComposition>, ParseError> {
+    parse_moon_data_with(input, ParseMode::Strict).map(|result| result.moons)
+}
+
+pub fn parse_moon_data_with(input: &str, mode: ParseMode) -> Result<ParseResult, ParseError> {
     let mut moons = Vec::new();
-    let mut current_moon: Option<MoonComposition> = None;
+    let mut warnings = Vec::new();
+    // Current moon and the line its name was on
+    let mut current_moon: Option<(MoonComposition, usize)> = None;
+
+    for (index, line) in input.lines().enumerate() {
+        let line_number = index + 1;
 
-    for line in input.lines() {
         // Skip empty lines
         if line.trim().is_empty() {
             continue;
@@ -50,63 +192,153 @@ pub fn parse_moon_data(input: &str) -> Result<Vec<MoonComposition>, ParseError>
 
         if leading_spaces >= 4 {
             // Material entry
-            if let Some(ref mut moon) = current_moon {
-                let material = parse_material_line(line)?;
-                moon.materials.push(material);
+            if let Some((ref mut moon, _)) = current_moon {
+                match parse_material_line(line) {
+                    Ok(material) => moon.materials.push(material),
+                    Err(e) => skip_or_fail(mode, &mut warnings, e.on_line(line_number, line))?,
+                }
             } else {
-                return Err(ParseError::InvalidFormat(
-                    "Material entry found before moon name".to_string(),
-                ));
+                let error = ParseError::new(
+                    ParseErrorKind::MaterialBeforeMoon,
+                    "Material entry found before moon name",
+                );
+                skip_or_fail(mode, &mut warnings, error.on_line(line_number, line))?;
             }
         } else {
             // Moon name - save previous moon if exists
-            if let Some(moon) = current_moon.take() {
-                if moon.materials.is_empty() {
-                    return Err(ParseError::InvalidFormat(format!(
-                        "Moon '{}' has no materials",
-                        moon.name
-                    )));
-                }
-                moons.push(moon);
+            if let Some((moon, line)) = current_moon.take() {
+                finish_moon(moon, line, mode, &mut moons, &mut warnings)?;
             }
 
             // Start new moon
-            current_moon = Some(MoonComposition {
-                name: line.trim().to_string(),
-                materials: Vec::new(),
-            });
+            current_moon = Some((
+                MoonComposition {
+                    name: line.trim().to_string(),
+                    materials: Vec::new(),
+                    tags: Vec::new(),
+                    notes: String::new(),
+                    location: None,
+                    jumps_to_market: None,
+                    highest_tier: None,
+                },
+                line_number,
+            ));
         }
     }
 
     // Handle last moon
-    if let Some(moon) = current_moon {
-        if moon.materials.is_empty() {
-            return Err(ParseError::InvalidFormat(format!(
-                "Moon '{}' has no materials",
-                moon.name
-            )));
-        }
-        moons.push(moon);
+    if let Some((moon, line)) = current_moon {
+        finish_moon(moon, line, mode, &mut moons, &mut warnings)?;
     }
 
     // Ensure we parsed at least one moon
     if moons.is_empty() {
-        return Err(ParseError::InvalidFormat(
-            "No valid moon data found".to_string(),
+        return Err(ParseError::new(
+            ParseErrorKind::NoMoonData,
+            "No valid moon data found",
         ));
     }
 
-    Ok(moons)
+    Ok(ParseResult { moons, warnings })
+}
+
+/// Keep a moon only if it has materials
+fn finish_moon(
+    moon: MoonComposition,
+    line: usize,
+    mode: ParseMode,
+    moons: &mut Vec<MoonComposition>,
+    warnings: &mut Vec<ParseWarning>,
+) -> Result<(), ParseError> {
+    if moon.materials.is_empty() {
+        let error = ParseError::new(
+            ParseErrorKind::MoonWithoutMaterials,
+            format!("Moon '{}' has no materials", moon.name),
+        );
+        return skip_or_fail(mode, warnings, error.on_line(line, &moon.name));
+    }
+    moons.push(moon);
+    Ok(())
+}
+
+/// Fail a strict parse, or record the line as skipped in a lenient one
+fn skip_or_fail(
+    mode: ParseMode,
+    warnings: &mut Vec<ParseWarning>,
+    error: ParseError,
+) -> Result<(), ParseError> {
+    match mode {
+        ParseMode::Strict => Err(error),
+        ParseMode::Lenient => {
+            warnings.push(ParseWarning {
+                kind: ParseWarningKind::SkippedLine,
+                line: error.line,
+                moon: None,
+                reason: error.message,
+            });
+            Ok(())
+        }
+    }
+}
+
+/// Check that each moon's quantities sum to roughly 1.0 and that each material is an ore
+/// `is_known_ore` recognises, so garbled pastes are caught before they're analyzed
+pub fn validate_compositions(
+    moons: &[MoonComposition],
+    is_known_ore: impl Fn(&MaterialEntry) -> bool,
+) -> Vec<ParseWarning> {
+    let mut warnings = Vec::new();
+
+    for moon in moons {
+        let total: f64 = moon.materials.iter().map(|m| m.quantity).sum();
+        if (total - 1.0).abs() > QUANTITY_SUM_TOLERANCE {
+            warnings.push(ParseWarning {
+                kind: ParseWarningKind::QuantitySum,
+                line: None,
+                moon: Some(moon.name.clone()),
+                reason: format!(
+                    "Material quantities add up to {:.1}% instead of 100%",
+                    total * 100.0
+                ),
+            });
+        }
+
+        for material in moon.materials.iter().filter(|m| !is_known_ore(m)) {
+            warnings.push(ParseWarning {
+                kind: ParseWarningKind::UnknownOre,
+                line: None,
+                moon: Some(moon.name.clone()),
+                reason: format!("'{}' isn't a known ore", material.name),
+            });
+        }
+    }
+
+    warnings
 }
 
 fn parse_material_line(line: &str) -> Result<MaterialEntry, ParseError> {
-    let parts: Vec<&str> = line.split_whitespace().collect();
+    // Fields with the 1-based character column each starts at
+    let mut parts: Vec<(usize, &str)> = Vec::new();
+    let mut field_start = None;
+    for (column, (offset, c)) in line.char_indices().enumerate() {
+        match (c.is_whitespace(), field_start) {
+            (false, None) => field_start = Some((column + 1, offset)),
+            (true, Some((start_column, start))) => {
+                parts.push((start_column, &line[start..offset]));
+                field_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((start_column, start)) = field_start {
+        parts.push((start_column, &line[start..]));
+    }
 
     if parts.len() < 6 {
-        return Err(ParseError::InvalidFormat(format!(
-            "Expected at least 6 fields, got {}",
-            parts.len()
-        )));
+        return Err(ParseError::new(
+            ParseErrorKind::MissingFields,
+            format!("Expected at least 6 fields, got {}", parts.len()),
+        ));
     }
 
     // Material name might be multiple words, so we need to find where the numbers start
@@ -116,27 +348,36 @@ fn parse_material_line(line: &str) -> Result<MaterialEntry, ParseError> {
     let name_parts = &parts[..parts.len() - num_count];
     let number_parts = &parts[parts.len() - num_count..];
 
-    let name = name_parts.join(" ");
+    let name = name_parts
+        .iter()
+        .map(|(_, part)| *part)
+        .collect::<Vec<_>>()
+        .join(" ");
 
+    let invalid = |(column, _): (usize, &str), field: &str, e: &dyn std::fmt::Display| {
+        ParseError::new(
+            ParseErrorKind::InvalidNumber,
+            format!("Invalid {}: {}", field, e),
+        )
+        .at_column(column)
+    };
+
+    // Clients set to German, French or Russian write the fraction with a decimal comma
     let quantity = number_parts[0]
+        .1
+        .replace(',', ".")
         .parse::<f64>()
-        .map_err(|e| ParseError::InvalidNumber(format!("Invalid quantity: {}", e)))?;
-
-    let item_id = number_parts[1]
-        .parse::<u32>()
-        .map_err(|e| ParseError::InvalidNumber(format!("Invalid item_id: {}", e)))?;
-
-    let system_id = number_parts[2]
-        .parse::<u32>()
-        .map_err(|e| ParseError::InvalidNumber(format!("Invalid system_id: {}", e)))?;
+        .map_err(|e| invalid(number_parts[0], "quantity", &e))?;
 
-    let region_id = number_parts[3]
-        .parse::<u32>()
-        .map_err(|e| ParseError::InvalidNumber(format!("Invalid region_id: {}", e)))?;
-
-    let additional_id = number_parts[4]
-        .parse::<u32>()
-        .map_err(|e| ParseError::InvalidNumber(format!("Invalid additional_id: {}", e)))?;
+    let mut ids = [0u32; 4];
+    for (i, field) in ["item_id", "system_id", "region_id", "additional_id"]
+        .into_iter()
+        .enumerate()
+    {
+        let part = number_parts[i + 1];
+        ids[i] = part.1.parse().map_err(|e| invalid(part, field, &e))?;
+    }
+    let [item_id, system_id, region_id, additional_id] = ids;
 
     Ok(MaterialEntry {
         name,
@@ -145,6 +386,8 @@ fn parse_material_line(line: &str) -> Result<MaterialEntry, ParseError> {
         system_id,
         region_id,
         additional_id,
+        tier: None,
+        extracted_units: None,
     })
 }
 
@@ -173,6 +416,70 @@ mod tests {
         assert_eq!(first_material.item_id, 46687);
     }
 
+    #[test]
+    fn test_same_moon_by_celestial_ids() {
+        let scan = |text: &str| parse_moon_data(text).unwrap().remove(0);
+        let moon = scan(
+            "OP9L-F II - Moon 1\n    Sylvite 0.08  45491   30002173    40138526    40138527\n",
+        );
+        let renamed =
+            scan("Home moon\n    Sylvite 0.08  45491   30002173    40138526    40138527\n");
+        let other = scan(
+            "OP9L-F II - Moon 1\n    Sylvite 0.08  45491   30002173    40138526    40138528\n",
+        );
+
+        assert!(moon.is_same_moon(&renamed));
+        assert!(!moon.is_same_moon(&other));
+    }
+
+    #[test]
+    fn test_parse_decimal_comma() {
+        let input = "Mond 1\n    Sylvit 0,083220936358  45491   30002173    40138526    40138527\n";
+
+        let moons = parse_moon_data(input).unwrap();
+
+        assert!((moons[0].materials[0].quantity - 0.083220936358).abs() < 0.0001);
+    }
+
+    #[test]
+    fn test_lenient_skips_stray_lines() {
+        let input = "Moon Product Quantity\nOP9L-F II - Moon 1\n    Sylvite 0.08  45491   30002173    40138526    40138527\n    [12:00:01] Pilot > o7\n";
+
+        assert!(parse_moon_data(input).is_err());
+
+        let result = parse_moon_data_with(input, ParseMode::Lenient).unwrap();
+        assert_eq!(result.moons.len(), 1);
+        assert_eq!(result.moons[0].materials.len(), 1);
+        let lines: Vec<Option<usize>> = result.warnings.iter().map(|w| w.line).collect();
+        assert_eq!(lines, vec![Some(1), Some(4)]);
+    }
+
+    #[test]
+    fn test_error_points_at_bad_field() {
+        let input = "Moon 1\n    Sylvite 0.08  45491   3000x173    40138526    40138527\n";
+
+        let error = parse_moon_data(input).unwrap_err();
+
+        assert_eq!(error.kind, ParseErrorKind::InvalidNumber);
+        assert_eq!(error.line, Some(2));
+        assert_eq!(error.column, Some(27));
+        assert_eq!(error.text.as_deref(), Some(input.lines().nth(1).unwrap()));
+    }
+
+    #[test]
+    fn test_validate_flags_short_sums_and_unknown_ores() {
+        let input = "Moon 1\n    Sylvite 0.5  45491   30002173    40138526    40138527\n    Banana 0.2  1   30002173    40138526    40138527\n";
+        let moons = parse_moon_data(input).unwrap();
+
+        let warnings = validate_compositions(&moons, |m| m.name != "Banana");
+
+        let kinds: Vec<ParseWarningKind> = warnings.iter().map(|w| w.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![ParseWarningKind::QuantitySum, ParseWarningKind::UnknownOre]
+        );
+    }
+
     #[test]
     fn test_reject_arbitrary_text() {
         let input = "some random text without proper format";
@@ -0,0 +1,107 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::prices::{now_unix, PriceInfo};
+use crate::storage;
+
+const PINS_FILE: &str = "price_pins.json";
+
+/// Event emitted with the pins that lapsed
+pub const PINS_EXPIRED_EVENT: &str = "prices://pins-expired";
+
+/// A user-set price that replaces the market price, optionally until a deadline
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePin {
+    pub item: String,
+    /// Used for both buy and sell
+    pub price: f64,
+    pub pinned_at: u64,
+    /// Unix timestamp (seconds) after which the pin no longer applies; None pins forever
+    pub expires_at: Option<u64>,
+}
+
+impl PricePin {
+    fn is_expired(&self, now: u64) -> bool {
+        self.expires_at.is_some_and(|at| at <= now)
+    }
+}
+
+/// Persisted price pins, by item name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PricePins {
+    pins: HashMap<String, PricePin>,
+}
+
+impl PricePins {
+    pub fn load() -> Self {
+        storage::load_json(PINS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(PINS_FILE, self)
+    }
+
+    /// Pin an item's price, for `days` days or indefinitely
+    pub fn pin(&mut self, item: &str, price: f64, days: Option<f64>) -> Result<(), String> {
+        if !price.is_finite() || price < 0.0 {
+            return Err("Pinned price can't be negative".to_string());
+        }
+        if days.is_some_and(|days| !days.is_finite() || days <= 0.0) {
+            return Err("Pin duration must be a positive number of days".to_string());
+        }
+        let now = now_unix();
+        self.pins.insert(
+            item.to_string(),
+            PricePin {
+                item: item.to_string(),
+                price,
+                pinned_at: now,
+                // The cast saturates, so a huge duration pins until the end of time
+                expires_at: days.map(|days| now.saturating_add((days * 86_400.0) as u64)),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, item: &str) {
+        self.pins.remove(item);
+    }
+
+    /// Pins sorted by item name
+    pub fn list(&self) -> Vec<PricePin> {
+        let mut pins: Vec<PricePin> = self.pins.values().cloned().collect();
+        pins.sort_by(|a, b| a.item.cmp(&b.item));
+        pins
+    }
+
+    /// Remove and return the pins that have lapsed
+    pub fn take_expired(&mut self) -> Vec<PricePin> {
+        let now = now_unix();
+        let expired: Vec<PricePin> = self
+            .pins
+            .values()
+            .filter(|pin| pin.is_expired(now))
+            .cloned()
+            .collect();
+        for pin in &expired {
+            self.pins.remove(&pin.item);
+        }
+        expired
+    }
+
+    /// Return a copy of the prices with every pin still in effect applied
+    pub fn apply(&self, prices: &HashMap<String, PriceInfo>) -> HashMap<String, PriceInfo> {
+        let now = now_unix();
+        let mut pinned = prices.clone();
+        for pin in self.pins.values().filter(|pin| !pin.is_expired(now)) {
+            pinned.insert(
+                pin.item.clone(),
+                PriceInfo {
+                    buy: pin.price,
+                    sell: pin.price,
+                },
+            );
+        }
+        pinned
+    }
+}
@@ -0,0 +1,491 @@
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+
+use crate::fees::{self, SaleOptions};
+use crate::reaction_tree::{self, ReactionTreeNode, SourceType, TreeContext};
+use crate::reactions::Reaction;
+use crate::shopping::ShoppingItem;
+
+/// Runs of one formula needed somewhere in the chain
+#[derive(Debug, Clone, Serialize)]
+pub struct FormulaRuns {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub output_name: String,
+    pub runs: u32,
+    pub jobs: u32,
+    pub duration_seconds: u64,
+    /// Output produced beyond what the chain consumes because of rounding up to whole runs
+    pub surplus: u32,
+}
+
+/// Intermediate produced for one branch whose rounding leftovers feed another branch
+#[derive(Debug, Clone, Serialize)]
+pub struct RecycledItem {
+    pub name: String,
+    pub id: u32,
+    /// Units that would have been produced again if each branch were planned separately
+    pub quantity: u32,
+}
+
+/// Item left over after a plan completes: rounding surplus or unused stock
+#[derive(Debug, Clone, Serialize)]
+pub struct LeftoverItem {
+    pub name: String,
+    pub id: u32,
+    pub quantity: u32,
+}
+
+/// Totals for running a reaction chain a number of times
+#[derive(Debug, Clone, Serialize)]
+pub struct RunPlan {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub runs: u32,
+    pub output_quantity: u32,
+    /// Raw inputs (bought, from moons or from stock) aggregated across the whole chain
+    pub inputs: Vec<ShoppingItem>,
+    pub formulas: Vec<FormulaRuns>,
+    pub recycled: Vec<RecycledItem>,
+    /// Intermediates and stock of this chain still on hand afterwards
+    pub leftover: Vec<LeftoverItem>,
+    pub total_cost: f64,
+    pub total_revenue: f64,
+    pub profit: f64,
+    pub jobs_needed: u32,
+    pub job_time_seconds: u64,
+    /// Volume of every raw input, stock and moon materials included, in m3
+    pub input_volume_m3: f64,
+    /// Volume of the final output, in m3
+    pub output_volume_m3: f64,
+}
+
+/// Runs and job slots a chain needs to make a quantity of its output
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub output_name: String,
+    /// Quantity asked for
+    pub quantity: u32,
+    /// Quantity made once the final formula is rounded up to whole runs
+    pub output_quantity: u32,
+    /// Runs of every formula in the chain, final product included
+    pub formulas: Vec<FormulaRuns>,
+    pub total_runs: u32,
+    /// Jobs to start, each taking a reaction slot
+    pub total_jobs: u32,
+    /// Reaction time summed over every job, before time bonuses
+    pub total_hours: f64,
+}
+
+impl RunSummary {
+    pub fn new(plan: &RunPlan, output_name: &str, quantity: u32) -> Self {
+        Self {
+            output_name: output_name.to_string(),
+            quantity,
+            output_quantity: plan.output_quantity,
+            formulas: plan.formulas.clone(),
+            total_runs: plan.formulas.iter().map(|f| f.runs).sum(),
+            total_jobs: plan.jobs_needed,
+            total_hours: plan.job_time_seconds as f64 / 3600.0,
+        }
+    }
+}
+
+/// A tree rebuilt with make/buy overrides and the plan it leads to
+#[derive(Debug, Clone, Serialize)]
+pub struct RecomputedTree {
+    pub tree: ReactionTreeNode,
+    pub plan: RunPlan,
+}
+
+/// Plan a number of runs of a reaction.
+///
+/// The tree decides which items are reacted and which are leaves; quantities are then
+/// re-derived by netting demand for each intermediate across all branches before
+/// rounding up to whole runs, so leftovers from one branch are reused by another.
+pub fn plan_runs(
+    tree: &ReactionTreeNode,
+    runs: u32,
+    ctx: &TreeContext,
+    sale_options: &SaleOptions,
+) -> Result<RunPlan, String> {
+    let reactions_db = ctx.reactions_db;
+    let reaction = reactions_db
+        .by_output
+        .get(&tree.id)
+        .ok_or_else(|| format!("No reaction produces '{}'", tree.name))?;
+
+    let mut reacted: HashSet<u32> = HashSet::new();
+    let mut names: HashMap<u32, String> = HashMap::new();
+    let mut separate_runs: HashMap<u32, u32> = HashMap::new();
+    collect_tree_items(tree, ctx, &mut reacted, &mut names, &mut separate_runs);
+
+    // Process items so every consumer comes before what it consumes
+    let levels = item_levels(tree.id, &reacted, ctx);
+    let mut order: Vec<u32> = levels.keys().copied().collect();
+    order.sort_by_key(|id| (levels[id], *id));
+
+    let too_many_runs = || format!("{} runs of {} is too many to plan", runs, tree.name);
+    let mut demand: HashMap<u32, u32> = HashMap::new();
+    demand.insert(tree.id, tree.quantity);
+    let mut remaining_stock = ctx.stock.clone();
+    let mut formulas: Vec<FormulaRuns> = Vec::new();
+    let mut recycled: Vec<RecycledItem> = Vec::new();
+    let mut inputs: Vec<ShoppingItem> = Vec::new();
+
+    for item_id in order {
+        let needed = demand.get(&item_id).copied().unwrap_or(0);
+        let name = names.get(&item_id).cloned().unwrap_or_default();
+        let is_moon = ctx.user_moon_goo_ids.contains(&item_id);
+        let from_stock = if item_id == tree.id || is_moon {
+            0
+        } else {
+            reaction_tree::take_from_stock(&mut remaining_stock, item_id, needed)
+        };
+        let remaining = needed - from_stock;
+
+        if reacted.contains(&item_id) {
+            let Some(r) = reactions_db.by_output.get(&item_id) else {
+                continue;
+            };
+            let formula_runs = if item_id == tree.id {
+                runs
+            } else {
+                r.runs_for(remaining)
+            };
+            for input in &r.inputs {
+                let demanded = demand.entry(input.id).or_insert(0);
+                *demanded = input
+                    .quantity
+                    .checked_mul(formula_runs)
+                    .and_then(|quantity| demanded.checked_add(quantity))
+                    .ok_or_else(too_many_runs)?;
+            }
+            let produced = formula_runs
+                .checked_mul(r.output.quantity)
+                .ok_or_else(too_many_runs)?;
+
+            let unshared = separate_runs.get(&item_id).copied().unwrap_or(0);
+            if unshared > formula_runs {
+                recycled.push(RecycledItem {
+                    name: name.clone(),
+                    id: item_id,
+                    quantity: (unshared - formula_runs).saturating_mul(r.output.quantity),
+                });
+            }
+            if formula_runs > 0 {
+                formulas.push(FormulaRuns {
+                    formula_id: r.formula_id,
+                    formula_name: r.formula_name.clone(),
+                    output_name: r.output.name.clone(),
+                    runs: formula_runs,
+                    jobs: r.jobs_for(formula_runs),
+                    duration_seconds: formula_runs as u64 * r.run_seconds as u64,
+                    surplus: produced.saturating_sub(remaining),
+                });
+            }
+            if item_id != tree.id {
+                let surplus = produced.saturating_sub(remaining);
+                let stock = remaining_stock.entry(item_id).or_insert(0);
+                *stock = stock.saturating_add(surplus);
+            }
+        } else if needed > 0 {
+            let source = if is_moon {
+                SourceType::Moon
+            } else if remaining == 0 {
+                SourceType::Stock
+            } else {
+                SourceType::Buy
+            };
+            let unit_price = ctx.prices.get(&name).map(|p| p.sell).unwrap_or(0.0);
+            inputs.push(ShoppingItem {
+                name,
+                id: item_id,
+                quantity: remaining,
+                from_stock,
+                source,
+                unit_price,
+                total_price: unit_price * remaining as f64,
+                unit_volume_m3: reactions_db.volume_m3(item_id),
+            });
+        }
+    }
+
+    let mut leftover: Vec<LeftoverItem> = names
+        .iter()
+        .filter_map(|(id, name)| {
+            let quantity = remaining_stock.get(id).copied().filter(|q| *q > 0)?;
+            Some(LeftoverItem {
+                name: name.clone(),
+                id: *id,
+                quantity,
+            })
+        })
+        .collect();
+
+    inputs.sort_by(|a, b| a.name.cmp(&b.name));
+    leftover.sort_by(|a, b| a.name.cmp(&b.name));
+    formulas.sort_by(|a, b| a.formula_name.cmp(&b.formula_name));
+    recycled.sort_by(|a, b| a.name.cmp(&b.name));
+
+    // Stock is valued at what it could be sold for, like every other input
+    let total_cost = inputs
+        .iter()
+        .map(|i| (i.quantity + i.from_stock) as f64 * i.unit_price)
+        .sum();
+    let total_revenue = ctx
+        .prices
+        .get(&tree.name)
+        .map(|price| fees::value_output(price, tree.quantity, sale_options).net_value)
+        .unwrap_or(0.0);
+
+    Ok(RunPlan {
+        formula_id: reaction.formula_id,
+        formula_name: reaction.formula_name.clone(),
+        runs,
+        output_quantity: tree.quantity,
+        total_cost,
+        total_revenue,
+        profit: total_revenue - total_cost,
+        jobs_needed: formulas.iter().map(|f| f.jobs).sum(),
+        job_time_seconds: formulas.iter().map(|f| f.duration_seconds).sum(),
+        input_volume_m3: inputs
+            .iter()
+            .map(|i| (i.quantity + i.from_stock) as f64 * i.unit_volume_m3)
+            .sum(),
+        output_volume_m3: tree.quantity as f64 * reactions_db.volume_m3(tree.id),
+        inputs,
+        formulas,
+        recycled,
+        leftover,
+    })
+}
+
+/// Purchases for one cycle of a repeated chain
+#[derive(Debug, Clone, Serialize)]
+pub struct CyclePlan {
+    pub cycle: u32,
+    pub purchases: Vec<ShoppingItem>,
+    pub purchase_cost: f64,
+    /// Stock carried into the next cycle
+    pub leftover: Vec<LeftoverItem>,
+}
+
+/// Per-cycle purchases when a chain is run repeatedly and leftovers carry over
+#[derive(Debug, Clone, Serialize)]
+pub struct SteadyStatePlan {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub runs_per_cycle: u32,
+    pub cycles: Vec<CyclePlan>,
+    /// Purchase cost of one cycle planned on its own, without carryover
+    pub single_cycle_cost: f64,
+    pub average_cycle_cost: f64,
+}
+
+/// Plan several consecutive cycles of a chain, carrying leftover intermediates from
+/// rounding into the next cycle instead of treating them as waste
+pub fn plan_steady_state(
+    reaction: &Reaction,
+    runs_per_cycle: u32,
+    cycles: u32,
+    ctx: &TreeContext,
+    sale_options: &SaleOptions,
+) -> Result<SteadyStatePlan, String> {
+    let mut carry = ctx.stock.clone();
+    let mut cycle_plans = Vec::new();
+    let mut single_cycle_cost = 0.0;
+
+    for cycle in 1..=cycles {
+        let cycle_ctx = TreeContext {
+            stock: &carry,
+            ..*ctx
+        };
+        let tree = reaction_tree::build_full_reaction_tree(
+            &reaction.output.name,
+            reaction.output.id,
+            reaction.output.quantity,
+            runs_per_cycle,
+            &cycle_ctx,
+        )?;
+        let plan = plan_runs(&tree, runs_per_cycle, &cycle_ctx, sale_options)?;
+
+        let purchases: Vec<ShoppingItem> = plan
+            .inputs
+            .into_iter()
+            .filter(|item| item.source == SourceType::Buy)
+            .collect();
+        let purchase_cost = purchases.iter().map(|i| i.total_price).sum();
+        if cycle == 1 {
+            single_cycle_cost = purchase_cost;
+        }
+
+        // Only this chain's items matter to the next cycle, so its leftovers become the stock
+        carry = plan
+            .leftover
+            .iter()
+            .map(|item| (item.id, item.quantity))
+            .collect();
+
+        cycle_plans.push(CyclePlan {
+            cycle,
+            purchases,
+            purchase_cost,
+            leftover: plan.leftover,
+        });
+    }
+
+    let average_cycle_cost = if cycle_plans.is_empty() {
+        0.0
+    } else {
+        cycle_plans.iter().map(|c| c.purchase_cost).sum::<f64>() / cycle_plans.len() as f64
+    };
+
+    Ok(SteadyStatePlan {
+        formula_id: reaction.formula_id,
+        formula_name: reaction.formula_name.clone(),
+        runs_per_cycle,
+        cycles: cycle_plans,
+        single_cycle_cost,
+        average_cycle_cost,
+    })
+}
+
+/// Record which items the tree reacts, every item's name, and how many runs each
+/// intermediate would take if every branch were produced separately
+fn collect_tree_items(
+    node: &ReactionTreeNode,
+    ctx: &TreeContext,
+    reacted: &mut HashSet<u32>,
+    names: &mut HashMap<u32, String>,
+    separate_runs: &mut HashMap<u32, u32>,
+) {
+    names.entry(node.id).or_insert_with(|| node.name.clone());
+    if matches!(node.source, SourceType::React | SourceType::Output) {
+        reacted.insert(node.id);
+        if let Some(reaction) = ctx.reactions_db.by_output.get(&node.id) {
+            *separate_runs.entry(node.id).or_insert(0) +=
+                reaction.runs_for(node.remaining_quantity());
+        }
+    }
+    for child in &node.children {
+        collect_tree_items(child, ctx, reacted, names, separate_runs);
+    }
+}
+
+/// Longest distance of every item from the root, following only reacted items' inputs
+fn item_levels(root_id: u32, reacted: &HashSet<u32>, ctx: &TreeContext) -> HashMap<u32, usize> {
+    let mut levels: HashMap<u32, usize> = HashMap::new();
+    levels.insert(root_id, 0);
+
+    // Reaction chains are shallow, so relaxing until nothing changes is cheap. Levels
+    // are capped by the number of reacted items in case the data ever contains a cycle.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        let current: Vec<(u32, usize)> = levels.iter().map(|(id, level)| (*id, *level)).collect();
+        for (item_id, level) in current {
+            if !reacted.contains(&item_id) || level >= reacted.len() {
+                continue;
+            }
+            let Some(reaction) = ctx.reactions_db.by_output.get(&item_id) else {
+                continue;
+            };
+            for input in &reaction.inputs {
+                let entry = levels.entry(input.id).or_insert(0);
+                if *entry < level + 1 {
+                    *entry = level + 1;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    levels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prices::PriceInfo;
+    use crate::reaction_tree::build_full_reaction_tree;
+    use crate::reactions::test_support::{item, reaction};
+    use crate::reactions::ReactionDatabase;
+
+    #[test]
+    fn test_shared_intermediate_is_netted_across_branches() {
+        // Top needs Left and Right; both need 50 Shared, which is made 200 per run
+        let db = ReactionDatabase::from_reactions(vec![
+            reaction(
+                1,
+                item("Top", 10, 1),
+                vec![item("Left", 11, 1), item("Right", 12, 1)],
+            ),
+            reaction(2, item("Left", 11, 1), vec![item("Shared", 13, 50)]),
+            reaction(3, item("Right", 12, 1), vec![item("Shared", 13, 50)]),
+            reaction(4, item("Shared", 13, 200), vec![item("Goo", 14, 100)]),
+        ]);
+        let moon_ids = HashSet::new();
+        let prices = HashMap::new();
+        let stock = HashMap::new();
+        let ctx = TreeContext {
+            reactions_db: &db,
+            user_moon_goo_ids: &moon_ids,
+            prices: &prices,
+            stock: &stock,
+            max_depth: None,
+            subtrees: None,
+            overrides: None,
+        };
+
+        let tree = build_full_reaction_tree("Top", 10, 1, 1, &ctx).unwrap();
+        let plan = plan_runs(&tree, 1, &ctx, &SaleOptions::default()).unwrap();
+
+        let shared = plan
+            .formulas
+            .iter()
+            .find(|f| f.output_name == "Shared")
+            .unwrap();
+        assert_eq!(shared.runs, 1);
+        assert_eq!(shared.surplus, 100);
+        assert_eq!(plan.recycled.len(), 1);
+        assert_eq!(plan.recycled[0].quantity, 200);
+        assert_eq!(plan.inputs.len(), 1);
+        assert_eq!(plan.inputs[0].name, "Goo");
+        assert_eq!(plan.inputs[0].quantity, 100);
+    }
+
+    #[test]
+    fn test_cheaper_intermediate_is_bought() {
+        // One Mid is made from 10 Goo at 5 ISK each, but sells for 20 ISK
+        let db = ReactionDatabase::from_reactions(vec![
+            reaction(1, item("Top", 10, 1), vec![item("Mid", 11, 1)]),
+            reaction(2, item("Mid", 11, 1), vec![item("Goo", 12, 10)]),
+        ]);
+        let price = |sell| PriceInfo { buy: sell, sell };
+        let prices = HashMap::from([
+            ("Mid".to_string(), price(20.0)),
+            ("Goo".to_string(), price(5.0)),
+        ]);
+        let moon_ids = HashSet::new();
+        let stock = HashMap::new();
+        let ctx = TreeContext {
+            reactions_db: &db,
+            user_moon_goo_ids: &moon_ids,
+            prices: &prices,
+            stock: &stock,
+            max_depth: None,
+            subtrees: None,
+            overrides: None,
+        };
+
+        let tree = build_full_reaction_tree("Top", 10, 1, 1, &ctx).unwrap();
+
+        let mid = &tree.children[0];
+        assert_eq!(mid.source, SourceType::Buy);
+        assert!(mid.children.is_empty());
+        let alternative = mid.alternative.as_ref().unwrap();
+        assert_eq!(alternative.source, SourceType::React);
+        assert_eq!(alternative.cost, 50.0);
+    }
+}
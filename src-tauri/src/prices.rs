@@ -1,5 +1,11 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::storage;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceInfo {
@@ -7,6 +13,347 @@ pub struct PriceInfo {
     pub sell: f64,
 }
 
+/// Most items appraised in one request, to stay under the upstream body size limit
+const PRICE_CHUNK_SIZE: usize = 100;
+/// Most price requests in flight at once
+const MAX_CONCURRENT_PRICE_REQUESTS: usize = 4;
+
+/// Trade hub prices are read from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MarketHub {
+    #[default]
+    Jita,
+    Amarr,
+    Dodixie,
+    Rens,
+    Hek,
+}
+
+impl MarketHub {
+    fn name(self) -> &'static str {
+        match self {
+            MarketHub::Jita => "Jita",
+            MarketHub::Amarr => "Amarr",
+            MarketHub::Dodixie => "Dodixie",
+            MarketHub::Rens => "Rens",
+            MarketHub::Hek => "Hek",
+        }
+    }
+
+    /// Solar system the hub is in
+    pub fn system_id(self) -> u32 {
+        match self {
+            MarketHub::Jita => 30000142,
+            MarketHub::Amarr => 30002187,
+            MarketHub::Dodixie => 30002659,
+            MarketHub::Rens => 30002510,
+            MarketHub::Hek => 30002053,
+        }
+    }
+
+    /// Market name Goonpraisal expects
+    pub fn market_param(self) -> &'static str {
+        match self {
+            MarketHub::Jita => "jita",
+            MarketHub::Amarr => "amarr",
+            MarketHub::Dodixie => "dodixie",
+            MarketHub::Rens => "rens",
+            MarketHub::Hek => "hek",
+        }
+    }
+}
+
+/// Order book statistic used as an item's price
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PricingMode {
+    #[default]
+    Percentile,
+    Median,
+    Average,
+}
+
+impl PricingMode {
+    fn name(self) -> &'static str {
+        match self {
+            PricingMode::Percentile => "percentile",
+            PricingMode::Median => "median",
+            PricingMode::Average => "average",
+        }
+    }
+}
+
+/// Where and how prices are fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PriceSource {
+    pub market: MarketHub,
+    pub mode: PricingMode,
+    /// Player structure whose market is used where it has orders, the hub for the rest
+    pub structure_id: Option<u64>,
+}
+
+impl PriceSource {
+    /// Human-readable name of the price source
+    pub fn describe(&self) -> String {
+        match self.structure_id {
+            Some(structure_id) => format!(
+                "Structure {} ({}), Goonpraisal {} for the rest",
+                structure_id,
+                self.mode.name(),
+                self.market.name()
+            ),
+            None => format!("Goonpraisal ({}, {})", self.market.name(), self.mode.name()),
+        }
+    }
+}
+
+/// How failed price requests are retried. Only network errors, rate limiting (429) and
+/// server errors (5xx) are retried
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Attempts per request, including the first
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles with every further retry
+    pub base_delay_ms: u64,
+    /// Longest delay between attempts
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            base_delay_ms: 500,
+            max_delay_ms: 8_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `retry` (starting at 1): exponential backoff where the
+    /// second half is random, so parallel requests don't retry in lockstep
+    fn delay(&self, retry: u32) -> Duration {
+        let backoff = self
+            .base_delay_ms
+            .saturating_mul(1 << (retry - 1).min(16))
+            .min(self.max_delay_ms);
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(0);
+        let jitter = nanos % (backoff / 2 + 1);
+        Duration::from_millis(backoff / 2 + jitter)
+    }
+}
+
+/// A failed price request and whether trying again might help
+struct RequestError {
+    message: String,
+    retryable: bool,
+    /// Wait the server asked for with Retry-After, at most `MAX_RETRY_AFTER_SECS`
+    retry_after: Option<Duration>,
+}
+
+/// Longest Retry-After wait honoured, so a bad header can't stall a price fetch
+const MAX_RETRY_AFTER_SECS: u64 = 60;
+
+/// Event emitted after the background price refresh replaced the cached prices
+pub const PRICES_UPDATED_EVENT: &str = "prices://updated";
+
+/// Sent with `PRICES_UPDATED_EVENT`
+#[derive(Debug, Clone, Serialize)]
+pub struct PricesUpdated {
+    pub source: String,
+    pub fetched_at: u64,
+    pub items: usize,
+}
+
+/// Where the most recent full set of prices is kept for use when offline
+const LAST_SNAPSHOT_FILE: &str = "last_prices.json";
+/// Every full set of prices fetched, one file each
+const SNAPSHOT_DIR: &str = "price_snapshots";
+/// Archived snapshots kept; older ones are deleted as new ones are archived
+const MAX_ARCHIVED_SNAPSHOTS: usize = 500;
+
+/// A set of prices fetched together, with where and when they came from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceSnapshot {
+    pub prices: HashMap<String, PriceInfo>,
+    pub source: String,
+    /// Unix timestamp (seconds) of the fetch
+    pub fetched_at: u64,
+    /// Loaded from disk because the price source couldn't be reached
+    #[serde(skip)]
+    pub stale: bool,
+    /// The user's own goo priced where it's mined, laid over the hub prices when read
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub regional_goo: HashMap<String, PriceInfo>,
+}
+
+impl PriceSnapshot {
+    pub fn new(prices: HashMap<String, PriceInfo>, source: PriceSource) -> Self {
+        Self {
+            prices,
+            source: source.describe(),
+            fetched_at: now_unix(),
+            stale: false,
+            regional_goo: HashMap::new(),
+        }
+    }
+
+    /// This snapshot with the regional goo prices laid over the hub prices, and the
+    /// source saying so
+    pub fn with_regional_goo(mut self) -> Self {
+        if !self.regional_goo.is_empty() {
+            self.prices.extend(std::mem::take(&mut self.regional_goo));
+            self.source = format!("{}, own goo at mined-region prices", self.source);
+        }
+        self
+    }
+
+    /// Keep this snapshot to fall back on when the price source is unreachable
+    pub fn save_last(&self) -> Result<(), String> {
+        storage::save_json(LAST_SNAPSHOT_FILE, self)
+    }
+
+    /// The last kept snapshot, marked as stale
+    pub fn load_last() -> Option<Self> {
+        storage::load_json(LAST_SNAPSHOT_FILE).map(|snapshot| Self {
+            stale: true,
+            ..snapshot
+        })
+    }
+
+    /// Archive the snapshot so it can be listed and loaded later, deleting the oldest ones
+    /// beyond the limit. Only full sets of prices are archived, so any of them can stand
+    /// in when offline. Returns the snapshot's ID
+    pub fn archive(&self) -> Result<String, String> {
+        let id = format!(
+            "{}-{}",
+            self.fetched_at,
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        );
+        storage::save_json(&snapshot_file(&id), self)?;
+
+        // IDs start with the fetch time, so the file names sort oldest first
+        if let Some(dir) = storage::data_file(SNAPSHOT_DIR) {
+            let mut files: Vec<std::path::PathBuf> = std::fs::read_dir(dir)
+                .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+                .unwrap_or_default();
+            files.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+            files.sort();
+            let excess = files.len().saturating_sub(MAX_ARCHIVED_SNAPSHOTS);
+            for path in &files[..excess] {
+                let _ = std::fs::remove_file(path);
+            }
+        }
+        Ok(id)
+    }
+
+    /// An archived snapshot, marked as stale
+    pub fn load_archived(id: &str) -> Result<Self, String> {
+        if id.contains(['/', '\\', '.']) {
+            return Err(format!("Invalid snapshot ID '{}'", id));
+        }
+        storage::load_json(&snapshot_file(id))
+            .map(|snapshot| Self {
+                stale: true,
+                ..snapshot
+            })
+            .ok_or_else(|| format!("Price snapshot '{}' not found", id))
+    }
+
+    /// Add freshly fetched prices for items this snapshot didn't cover
+    pub fn merge(&mut self, prices: HashMap<String, PriceInfo>) {
+        for (name, price) in prices {
+            self.prices.entry(name).or_insert(price);
+        }
+    }
+}
+
+fn snapshot_file(id: &str) -> String {
+    format!("{}/{}.json", SNAPSHOT_DIR, id)
+}
+
+/// Identifying details of an archived price snapshot
+#[derive(Debug, Clone, Serialize)]
+pub struct SnapshotInfo {
+    pub id: String,
+    pub fetched_at: u64,
+    pub source: String,
+    pub item_count: usize,
+}
+
+/// All archived snapshots, newest first
+pub fn list_snapshots() -> Vec<SnapshotInfo> {
+    let Some(dir) = storage::data_file(SNAPSHOT_DIR) else {
+        return Vec::new();
+    };
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut infos: Vec<SnapshotInfo> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "json" {
+                return None;
+            }
+            let id = path.file_stem()?.to_str()?.to_string();
+            let snapshot = PriceSnapshot::load_archived(&id).ok()?;
+            Some(SnapshotInfo {
+                id,
+                fetched_at: snapshot.fetched_at,
+                source: snapshot.source,
+                item_count: snapshot.prices.len(),
+            })
+        })
+        .collect();
+    infos.sort_by_key(|info| std::cmp::Reverse(info.fetched_at));
+    infos
+}
+
+/// Current time as a unix timestamp in seconds
+pub fn now_unix() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// What-if percentage changes to apply to prices, e.g. -20 for a 20% crash
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PriceAdjustments {
+    /// Applied to every item
+    pub global_percent: f64,
+    /// Applied per item name, on top of the global change
+    pub items: HashMap<String, f64>,
+}
+
+impl PriceAdjustments {
+    /// Return a copy of the prices with the adjustments applied to both buy and sell
+    pub fn apply(&self, prices: &HashMap<String, PriceInfo>) -> HashMap<String, PriceInfo> {
+        let global = 1.0 + self.global_percent / 100.0;
+        prices
+            .iter()
+            .map(|(name, price)| {
+                let item = 1.0 + self.items.get(name).copied().unwrap_or(0.0) / 100.0;
+                let factor = (global * item).max(0.0);
+                (
+                    name.clone(),
+                    PriceInfo {
+                        buy: price.buy * factor,
+                        sell: price.sell * factor,
+                    },
+                )
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Deserialize)]
 struct AppraisalItem {
     #[serde(rename = "typeName")]
@@ -23,6 +370,19 @@ struct AppraisalPrices {
 #[derive(Debug, Deserialize)]
 struct PriceDetail {
     percentile: Option<f64>,
+    median: Option<f64>,
+    avg: Option<f64>,
+}
+
+impl PriceDetail {
+    fn price(&self, mode: PricingMode) -> f64 {
+        match mode {
+            PricingMode::Percentile => self.percentile,
+            PricingMode::Median => self.median,
+            PricingMode::Average => self.avg,
+        }
+        .unwrap_or(0.0)
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -35,14 +395,123 @@ struct AppraisalResponse {
     appraisal: AppraisalInner,
 }
 
-/// Fetch prices for a list of item names from Goonpraisal
-pub async fn fetch_prices(item_names: &[String]) -> Result<HashMap<String, PriceInfo>, String> {
+/// Fetch prices like `fetch_prices`, falling back to the last kept snapshot if the price
+/// source can't be reached
+pub async fn fetch_snapshot(
+    item_names: &[String],
+    source: PriceSource,
+    retry: RetryPolicy,
+    structures: &StructureMarket,
+) -> Result<PriceSnapshot, String> {
+    match fetch_prices(item_names, source, retry, structures).await {
+        Ok(prices) => Ok(PriceSnapshot::new(prices, source)),
+        Err(e) => {
+            // The newest archived snapshot when no full set has been kept yet
+            let last = PriceSnapshot::load_last()
+                .or_else(|| {
+                    let newest = list_snapshots().into_iter().next()?;
+                    PriceSnapshot::load_archived(&newest.id).ok()
+                })
+                .ok_or(e)?;
+            tracing::warn!(
+                fetched_at = last.fetched_at,
+                "Offline, using last kept prices"
+            );
+            Ok(last)
+        }
+    }
+}
+
+/// Fetch prices for a list of item names from Goonpraisal, with the structure market's
+/// laid over them when the source names one
+pub async fn fetch_prices(
+    item_names: &[String],
+    source: PriceSource,
+    retry: RetryPolicy,
+    structures: &StructureMarket,
+) -> Result<HashMap<String, PriceInfo>, String> {
     if item_names.is_empty() {
         return Ok(HashMap::new());
     }
 
+    let started = std::time::Instant::now();
     let client = reqwest::Client::new();
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_PRICE_REQUESTS));
+
+    // Appraise the list in chunks, a few requests at a time
+    let mut requests = JoinSet::new();
+    for chunk in item_names.chunks(PRICE_CHUNK_SIZE) {
+        let client = client.clone();
+        let permits = Arc::clone(&permits);
+        let chunk = chunk.to_vec();
+        requests.spawn(async move {
+            let _permit = permits
+                .acquire_owned()
+                .await
+                .map_err(|_| "Internal error: price request limiter closed".to_string())?;
+            fetch_price_chunk_with_retry(&client, &chunk, source, retry).await
+        });
+    }
 
+    let mut prices = HashMap::new();
+    while let Some(result) = requests.join_next().await {
+        let chunk_prices = result
+            .map_err(|e| format!("Internal error: price request failed: {}", e))
+            .and_then(|chunk| chunk)
+            .inspect_err(|e| tracing::warn!(error = %e, "Price fetch failed"))?;
+        prices.extend(chunk_prices);
+    }
+
+    if let Some(structure_id) = source.structure_id {
+        let local = structures
+            .prices(structure_id, item_names, source.mode)
+            .await?;
+        overlay_prices(&mut prices, local);
+    }
+
+    tracing::info!(
+        items = item_names.len(),
+        priced = prices.len(),
+        source = %source.describe(),
+        elapsed_ms = started.elapsed().as_millis() as u64,
+        "Fetched prices"
+    );
+    Ok(prices)
+}
+
+/// Appraise one chunk of items, retrying transient failures according to the policy
+async fn fetch_price_chunk_with_retry(
+    client: &reqwest::Client,
+    item_names: &[String],
+    source: PriceSource,
+    retry: RetryPolicy,
+) -> Result<HashMap<String, PriceInfo>, String> {
+    let mut attempt = 1;
+    loop {
+        match fetch_price_chunk(client, item_names, source).await {
+            Ok(prices) => return Ok(prices),
+            Err(e) if e.retryable && attempt < retry.max_attempts => {
+                let delay = e.retry_after.unwrap_or_else(|| retry.delay(attempt));
+                tracing::info!(
+                    attempt,
+                    delay_ms = delay.as_millis() as u64,
+                    error = %e.message,
+                    "Retrying price request"
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            Err(e) => return Err(e.message),
+        }
+    }
+}
+
+/// Appraise one chunk of items in a single request
+async fn fetch_price_chunk(
+    client: &reqwest::Client,
+    item_names: &[String],
+    source: PriceSource,
+) -> Result<HashMap<String, PriceInfo>, RequestError> {
     // Build the request body - one item per line
     let raw_textarea = item_names.join("\n");
 
@@ -50,33 +519,46 @@ pub async fn fetch_prices(item_names: &[String]) -> Result<HashMap<String, Price
         .post("https://appraise.gnf.lt/appraisal.json")
         .header("User-Agent", "MOON-Reaction-Calculator/1.0")
         .form(&[
-            ("market", "jita"),
+            ("market", source.market.market_param()),
             ("raw_textarea", &raw_textarea),
             ("persist", "no"),
         ])
         .send()
         .await
-        .map_err(|e| format!("Failed to fetch prices: {}", e))?;
+        .map_err(|e| RequestError {
+            message: format!("Failed to fetch prices: {}", e),
+            retryable: true,
+            retry_after: None,
+        })?;
 
-    if !response.status().is_success() {
-        return Err(format!(
-            "Goonpraisal returned status: {}",
-            response.status()
-        ));
+    let status = response.status();
+    if !status.is_success() {
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(|secs| Duration::from_secs(secs.min(MAX_RETRY_AFTER_SECS)));
+        return Err(RequestError {
+            message: format!("Goonpraisal returned status: {}", status),
+            retryable: status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error(),
+            retry_after,
+        });
     }
 
-    let appraisal: AppraisalResponse = response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse price response: {}", e))?;
+    let appraisal: AppraisalResponse = response.json().await.map_err(|e| RequestError {
+        message: format!("Failed to parse price response: {}", e),
+        retryable: false,
+        retry_after: None,
+    })?;
 
     let mut prices = HashMap::new();
     for item in appraisal.appraisal.items {
         prices.insert(
             item.type_name,
             PriceInfo {
-                buy: item.prices.buy.percentile.unwrap_or(0.0),
-                sell: item.prices.sell.percentile.unwrap_or(0.0),
+                buy: item.prices.buy.price(source.mode),
+                sell: item.prices.sell.price(source.mode),
             },
         );
     }
@@ -84,16 +566,392 @@ pub async fn fetch_prices(item_names: &[String]) -> Result<HashMap<String, Price
     Ok(prices)
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Order book aggregates for any region, used where the trade hub prices don't apply
+const REGIONAL_PRICES_URL: &str = "https://market.fuzzwork.co.uk/aggregates/";
+
+#[derive(Debug, Deserialize)]
+struct RegionalAggregate {
+    buy: RegionalSide,
+    sell: RegionalSide,
+}
+
+/// One side of a regional order book; the aggregator sends numbers as strings
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegionalSide {
+    weighted_average: String,
+    median: String,
+    percentile: String,
+    order_count: String,
+}
+
+impl RegionalSide {
+    fn price(&self, mode: PricingMode) -> f64 {
+        let value = match mode {
+            PricingMode::Percentile => &self.percentile,
+            PricingMode::Median => &self.median,
+            PricingMode::Average => &self.weighted_average,
+        };
+        value.parse().unwrap_or(0.0)
+    }
+
+    fn has_orders(&self) -> bool {
+        self.order_count
+            .parse::<f64>()
+            .is_ok_and(|count| count > 0.0)
+    }
+}
+
+/// Fetch prices in one region's market for items given by name and type ID. Only sides
+/// with orders in the region are set, so callers can fall back to hub prices for the rest
+pub async fn fetch_regional_prices(
+    region_id: u32,
+    items: &[(String, u32)],
+    mode: PricingMode,
+) -> Result<HashMap<String, PartialPrice>, String> {
+    if items.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let types = items
+        .iter()
+        .map(|(_, id)| id.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let response = reqwest::Client::new()
+        .get(REGIONAL_PRICES_URL)
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .query(&[("region", region_id.to_string()), ("types", types)])
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to fetch regional prices: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!(
+            "Regional price source returned status: {}",
+            response.status()
+        ));
+    }
+
+    let aggregates: HashMap<String, RegionalAggregate> = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse regional prices: {}", e))?;
+
+    Ok(items
+        .iter()
+        .filter_map(|(name, id)| {
+            let aggregate = aggregates.get(&id.to_string())?;
+            let side = |side: &RegionalSide| side.has_orders().then(|| side.price(mode));
+            let price = PartialPrice {
+                buy: side(&aggregate.buy),
+                sell: side(&aggregate.sell),
+            };
+            (price != PartialPrice::default()).then(|| (name.clone(), price))
+        })
+        .collect())
+}
+
+const ESI_URL: &str = "https://esi.evetech.net/latest";
+/// Most names ESI resolves to IDs in one request
+const ESI_NAMES_PER_REQUEST: usize = 500;
+/// Share of the traded volume, from the best price, averaged for the percentile price
+const PERCENTILE_VOLUME_SHARE: f64 = 0.05;
+
+#[derive(Debug, Deserialize)]
+struct StructureOrder {
+    type_id: u32,
+    is_buy_order: bool,
+    price: f64,
+    volume_remain: u64,
+}
+
+/// Price and volume of each order on the two sides of one item's book
+#[derive(Debug, Default)]
+struct OrderBook {
+    buy: Vec<(f64, u64)>,
+    sell: Vec<(f64, u64)>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ResolvedIds {
+    #[serde(default)]
+    inventory_types: Vec<ResolvedId>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResolvedId {
+    id: u32,
+    name: String,
+}
+
+/// How long a structure's orders are reused before the market is read again; ESI itself
+/// only updates structure markets every 5 minutes
+const STRUCTURE_MARKET_CACHE_SECS: u64 = 300;
+
+/// Prices from a smaller market, for only the sides of the book that have orders
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PartialPrice {
+    pub buy: Option<f64>,
+    pub sell: Option<f64>,
+}
+
+/// Lay prices from a smaller market over hub prices, side by side. A side without orders
+/// there keeps the hub price
+pub fn overlay_prices(
+    prices: &mut HashMap<String, PriceInfo>,
+    local: HashMap<String, PartialPrice>,
+) {
+    for (name, local) in local {
+        let price = prices.entry(name).or_insert(PriceInfo {
+            buy: 0.0,
+            sell: 0.0,
+        });
+        if let Some(buy) = local.buy {
+            price.buy = buy;
+        }
+        if let Some(sell) = local.sell {
+            price.sell = sell;
+        }
+    }
+}
+
+/// Player structure market access shared by every price fetch
+#[derive(Debug, Default)]
+pub struct StructureMarket {
+    /// Held while the ESI login is loaded, refreshed and saved: each refresh token works
+    /// only once, so two refreshes at the same time would leave a used one on disk
+    login: tokio::sync::Mutex<()>,
+    /// The last market read, held while reading so concurrent fetches wait for one read
+    books: tokio::sync::Mutex<Option<StructureBooks>>,
+    /// Type IDs by item name, `None` for names ESI doesn't know
+    type_ids: tokio::sync::Mutex<HashMap<String, Option<u32>>>,
+}
+
+/// Every order book in one structure's market, best price first on each side
+#[derive(Debug)]
+struct StructureBooks {
+    structure_id: u64,
+    fetched_at: u64,
+    books: HashMap<u32, OrderBook>,
+}
+
+impl StructureMarket {
+    /// Wait for any ESI login change or token refresh in progress to finish, and keep
+    /// others out while the guard is held
+    pub async fn lock_login(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.login.lock().await
+    }
+
+    /// Prices in a player structure's market through ESI, which needs a character with
+    /// docking access to be logged in. Items without orders there are left out
+    pub async fn prices(
+        &self,
+        structure_id: u64,
+        item_names: &[String],
+        mode: PricingMode,
+    ) -> Result<HashMap<String, PartialPrice>, String> {
+        let client = reqwest::Client::new();
+        let type_ids = self.resolve_type_ids(&client, item_names).await?;
+
+        let mut cached = self.books.lock().await;
+        let fresh = cached.as_ref().is_some_and(|books| {
+            books.structure_id == structure_id
+                && now_unix().saturating_sub(books.fetched_at) < STRUCTURE_MARKET_CACHE_SECS
+        });
+        if !fresh {
+            let token = {
+                let _login = self.lock_login().await;
+                let mut auth = crate::esi_auth::EsiAuth::load();
+                let token = auth.access_token().await?;
+                if let Err(e) = auth.save() {
+                    tracing::warn!(error = %e, "Failed to keep the ESI login");
+                }
+                token
+            };
+            *cached = Some(StructureBooks {
+                structure_id,
+                fetched_at: now_unix(),
+                books: fetch_structure_books(&client, structure_id, &token).await?,
+            });
+        }
+        let Some(books) = cached.as_ref() else {
+            return Ok(HashMap::new());
+        };
+
+        Ok(item_names
+            .iter()
+            .filter_map(|name| {
+                let book = books.books.get(type_ids.get(name)?)?;
+                Some((
+                    name.clone(),
+                    PartialPrice {
+                        buy: order_book_price(&book.buy, mode),
+                        sell: order_book_price(&book.sell, mode),
+                    },
+                ))
+            })
+            .collect())
+    }
+
+    /// Type IDs of the named items, asking ESI only for names not looked up before
+    async fn resolve_type_ids(
+        &self,
+        client: &reqwest::Client,
+        item_names: &[String],
+    ) -> Result<HashMap<String, u32>, String> {
+        let mut known = self.type_ids.lock().await;
+        let unknown: Vec<String> = item_names
+            .iter()
+            .filter(|name| !known.contains_key(*name))
+            .cloned()
+            .collect();
+
+        for chunk in unknown.chunks(ESI_NAMES_PER_REQUEST) {
+            let response = client
+                .post(format!("{}/universe/ids/", ESI_URL))
+                .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+                .json(chunk)
+                .timeout(Duration::from_secs(30))
+                .send()
+                .await
+                .map_err(|e| format!("Failed to reach ESI: {}", e))?;
+            if !response.status().is_success() {
+                return Err(format!("ESI returned status: {}", response.status()));
+            }
+            let ids: ResolvedIds = response
+                .json()
+                .await
+                .map_err(|e| format!("Failed to parse ESI response: {}", e))?;
+            for name in chunk {
+                known.insert(name.clone(), None);
+            }
+            known.extend(
+                ids.inventory_types
+                    .into_iter()
+                    .map(|t| (t.name, Some(t.id))),
+            );
+        }
+
+        Ok(item_names
+            .iter()
+            .filter_map(|name| Some((name.clone(), (*known.get(name)?)?)))
+            .collect())
+    }
+}
+
+/// Read every order in a structure's market, page by page
+async fn fetch_structure_books(
+    client: &reqwest::Client,
+    structure_id: u64,
+    token: &str,
+) -> Result<HashMap<u32, OrderBook>, String> {
+    let mut books: HashMap<u32, OrderBook> = HashMap::new();
+    let mut page = 1;
+    loop {
+        let response = client
+            .get(format!("{}/markets/structures/{}/", ESI_URL, structure_id))
+            .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+            .bearer_auth(token)
+            .query(&[("page", page)])
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch structure market: {}", e))?;
+        match response.status() {
+            reqwest::StatusCode::FORBIDDEN => {
+                return Err(format!(
+                    "The logged in character can't see the market of structure {}",
+                    structure_id
+                ))
+            }
+            status if !status.is_success() => {
+                return Err(format!("ESI returned status: {}", status))
+            }
+            _ => {}
+        }
+        let pages: u32 = response
+            .headers()
+            .get("x-pages")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(1);
+        let orders: Vec<StructureOrder> = response
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse structure market: {}", e))?;
+
+        for order in orders {
+            let book = books.entry(order.type_id).or_default();
+            let side = if order.is_buy_order {
+                &mut book.buy
+            } else {
+                &mut book.sell
+            };
+            side.push((order.price, order.volume_remain));
+        }
+        if page >= pages {
+            break;
+        }
+        page += 1;
+    }
+
+    // Best price first
+    for book in books.values_mut() {
+        book.buy.sort_by(|a, b| b.0.total_cmp(&a.0));
+        book.sell.sort_by(|a, b| a.0.total_cmp(&b.0));
+    }
+    Ok(books)
+}
+
+/// Price of one side of an order book sorted best price first, weighted by volume: the
+/// best 5% of volume for percentile, as the hub aggregates work. None without orders
+fn order_book_price(orders: &[(f64, u64)], mode: PricingMode) -> Option<f64> {
+    let total: u64 = orders.iter().map(|(_, volume)| volume).sum();
+    if total == 0 {
+        return None;
+    }
+    let weighted_average = |limit: f64| {
+        let mut volume_left = limit;
+        let mut value = 0.0;
+        for (price, volume) in orders {
+            let taken = (*volume as f64).min(volume_left);
+            value += price * taken;
+            volume_left -= taken;
+            if volume_left <= 0.0 {
+                break;
+            }
+        }
+        value / (limit - volume_left)
+    };
+
+    match mode {
+        PricingMode::Percentile => Some(weighted_average(
+            (total as f64 * PERCENTILE_VOLUME_SHARE).max(1.0),
+        )),
+        PricingMode::Average => Some(weighted_average(total as f64)),
+        PricingMode::Median => {
+            let mut counted = 0;
+            orders.iter().find_map(|(price, volume)| {
+                counted += volume;
+                (counted * 2 >= total).then_some(*price)
+            })
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputBreakdown {
     pub name: String,
     pub quantity: u32,
     pub unit_price: f64,
     pub total_price: f64,
     pub from_moon: bool, // true if user has this from their moons (but still has opportunity cost)
+    pub hauling_cost: f64, // Freight to bring it to the refinery, zero for moon materials
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReactionProfit {
     pub formula_id: u32,
     pub formula_name: String,
@@ -102,25 +960,56 @@ pub struct ReactionProfit {
     pub output_quantity: u32,
     pub output_unit_price: f64,
     pub output_value: f64,
-    pub input_cost: f64, // Total opportunity cost of all inputs (sell value)
+    pub output_destination: crate::fees::OutputDestination,
+    pub output_fees: f64, // Broker fee, expected relist fees and sales tax on the output
+    pub input_cost: f64,  // Total opportunity cost of all inputs (sell value)
+    pub import_hauling_cost: f64, // Freight for bought inputs
+    pub export_hauling_cost: f64, // Freight for taking the output to market
     pub profit: f64,
     pub margin: f64,
+    /// Cost of the inputs that have to be bought; moon materials cost nothing
+    #[serde(default)]
+    pub cash_input_cost: f64,
+    /// Profit when moon materials are counted as free rather than at their sell value
+    #[serde(default)]
+    pub cash_profit: f64,
+    /// Cash profit as a percentage of the cash input cost, zero when nothing is bought
+    #[serde(default)]
+    pub cash_margin: f64,
+    pub input_volume_m3: f64,
+    pub output_volume_m3: f64,
+    pub isk_per_m3: f64, // Output value per m3 hauled, zero when the volume isn't known
+    pub isk_per_day: f64, // Profit one job slot makes in a day
+    pub breakeven_price: f64, // Output unit price at which profit is zero after fees, 0 if none
+    pub price_headroom: f64, // How far the output price can fall before breakeven, in percent
     pub inputs: Vec<InputBreakdown>,
     pub uses_user_materials: bool, // true if at least one input is from user's moons
+    /// Runs the extracted goo is enough for, when the analysis is limited to it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub available_runs: Option<u32>,
     pub reaction_tree: Option<crate::reaction_tree::ReactionTreeNode>,
 }
 
-/// Calculate profit for a reaction (inputs priced at sell value for opportunity cost)
+/// Calculate profit for a reaction (inputs priced at sell value for opportunity cost,
+/// output valued according to where it will be sold)
 pub fn calculate_reaction_profit(
     reaction: &crate::reactions::Reaction,
     prices: &HashMap<String, PriceInfo>,
     user_material_ids: &HashSet<u32>,
+    sale_options: &crate::fees::SaleOptions,
 ) -> Option<ReactionProfit> {
     let output_price = prices.get(&reaction.output.name)?;
-    let output_unit_price = output_price.sell;
-    let output_value = output_unit_price * reaction.output.quantity as f64;
+    let sale = crate::fees::value_output(output_price, reaction.output.quantity, sale_options);
+    let output_unit_price = sale.unit_price;
+    let output_value = sale.gross_value;
+    let output_fees = sale.gross_value - sale.net_value;
+    let hauling = &sale_options.hauling;
+    let export_hauling_cost =
+        hauling.cost(reaction.output.quantity as f64 * reaction.output.volume_m3);
 
     let mut input_cost = 0.0;
+    let mut cash_input_cost = 0.0;
+    let mut import_hauling_cost = 0.0;
     let mut inputs = Vec::new();
     let mut uses_user_materials = false;
 
@@ -135,6 +1024,17 @@ pub fn calculate_reaction_profit(
 
         let total_price = unit_price * input.quantity as f64;
         input_cost += total_price;
+        if !from_moon {
+            cash_input_cost += total_price;
+        }
+
+        // Moon materials are already at the refinery
+        let hauling_cost = if from_moon {
+            0.0
+        } else {
+            hauling.cost(input.quantity as f64 * input.volume_m3)
+        };
+        import_hauling_cost += hauling_cost;
 
         inputs.push(InputBreakdown {
             name: input.name.clone(),
@@ -142,15 +1042,50 @@ pub fn calculate_reaction_profit(
             unit_price,
             total_price,
             from_moon,
+            hauling_cost,
         });
     }
 
-    let profit = output_value - input_cost;
+    let profit =
+        output_value - output_fees - input_cost - import_hauling_cost - export_hauling_cost;
     let margin = if input_cost > 0.0 {
         (profit / input_cost) * 100.0
     } else {
         0.0
     };
+    let cash_profit = profit + (input_cost - cash_input_cost);
+    let cash_margin = if cash_input_cost > 0.0 {
+        (cash_profit / cash_input_cost) * 100.0
+    } else {
+        0.0
+    };
+    // Fees scale with the price, so the net value of the output at 1 ISK per unit gives
+    // the price that just covers the costs
+    let net_per_isk = crate::fees::value_output(
+        &PriceInfo {
+            buy: 1.0,
+            sell: 1.0,
+        },
+        reaction.output.quantity,
+        sale_options,
+    )
+    .net_value;
+    let breakeven_price = if net_per_isk > 0.0 {
+        (input_cost + import_hauling_cost + export_hauling_cost) / net_per_isk
+    } else {
+        0.0 // Fees take the whole sale, no price breaks even
+    };
+    let price_headroom = if output_unit_price > 0.0 {
+        (output_unit_price - breakeven_price) / output_unit_price * 100.0
+    } else {
+        0.0
+    };
+    let output_volume_m3 = reaction.output_volume_m3();
+    let isk_per_m3 = if output_volume_m3 > 0.0 {
+        output_value / output_volume_m3
+    } else {
+        0.0
+    };
 
     Some(ReactionProfit {
         formula_id: reaction.formula_id,
@@ -160,11 +1095,25 @@ pub fn calculate_reaction_profit(
         output_quantity: reaction.output.quantity,
         output_unit_price,
         output_value,
+        output_destination: sale_options.destination,
+        output_fees,
         input_cost,
+        import_hauling_cost,
+        export_hauling_cost,
         profit,
         margin,
+        cash_input_cost,
+        cash_profit,
+        cash_margin,
+        input_volume_m3: reaction.input_volume_m3(),
+        output_volume_m3,
+        isk_per_m3,
+        isk_per_day: profit * reaction.runs_per_day(),
+        breakeven_price,
+        price_headroom,
         inputs,
         uses_user_materials,
+        available_runs: None,
         reaction_tree: None, // Will be populated separately
     })
 }
@@ -0,0 +1,135 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+use crate::parser::MoonComposition;
+use crate::prices::now_unix;
+use crate::storage::{self, DEFAULT_PROFILE};
+
+const PROFILES_FILE: &str = "profiles.json";
+const MOONS_FILE: &str = "moons.json";
+
+/// A named set of moons, inventory, settings and trackers, e.g. one per corporation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    /// Unix timestamp (seconds)
+    pub created_at: u64,
+}
+
+/// Every profile and which one is in use
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileRegistry {
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+impl Default for ProfileRegistry {
+    fn default() -> Self {
+        Self {
+            active: DEFAULT_PROFILE.to_string(),
+            profiles: vec![Profile {
+                name: DEFAULT_PROFILE.to_string(),
+                created_at: now_unix(),
+            }],
+        }
+    }
+}
+
+impl ProfileRegistry {
+    pub fn load() -> Self {
+        storage::load_json(PROFILES_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(PROFILES_FILE, self)
+    }
+
+    /// Stored name of a profile, matched ignoring case: macOS and Windows treat "Work" and
+    /// "work" as the same folder
+    fn find(&self, name: &str) -> Option<String> {
+        self.profiles
+            .iter()
+            .find(|p| p.name.to_lowercase() == name.to_lowercase())
+            .map(|p| p.name.clone())
+    }
+
+    fn contains(&self, name: &str) -> bool {
+        self.find(name).is_some()
+    }
+
+    pub fn create(&mut self, name: &str) -> Result<(), String> {
+        let name = name.trim();
+        validate_name(name)?;
+        if self.contains(name) {
+            return Err(format!("Profile '{}' already exists", name));
+        }
+        self.profiles.push(Profile {
+            name: name.to_string(),
+            created_at: now_unix(),
+        });
+        Ok(())
+    }
+
+    /// Make a profile the active one. Per-profile files keep pointing at the old profile
+    /// until the registry is saved and `storage::set_active_profile` is called
+    pub fn switch(&mut self, name: &str) -> Result<(), String> {
+        let name = self
+            .find(name)
+            .ok_or_else(|| format!("Unknown profile '{}'", name))?;
+        self.active = name;
+        Ok(())
+    }
+
+    /// Remove a profile and its files. The active profile can't be deleted
+    pub fn delete(&mut self, name: &str) -> Result<(), String> {
+        let name = self
+            .find(name)
+            .ok_or_else(|| format!("Unknown profile '{}'", name))?;
+        if name == self.active {
+            return Err("Switch to another profile before deleting this one".to_string());
+        }
+        self.profiles.retain(|p| p.name != name);
+        if let Some(dir) = storage::data_file(&format!("profiles/{}", name)) {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)
+                    .map_err(|e| format!("Failed to remove {}: {}", dir.display(), e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Profile names become folder names, so keep them to characters safe on every platform
+fn validate_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.len() > 64 {
+        return Err("Profile names must be 1 to 64 characters".to_string());
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_' | '.'))
+        || name.starts_with('.')
+    {
+        return Err(
+            "Profile names may only use letters, numbers, spaces, '-', '_' and '.'".to_string(),
+        );
+    }
+    // Windows drops a trailing space or '.' from folder names, so "alt." would be "alt"
+    if name.ends_with(' ') || name.ends_with('.') {
+        return Err("Profile names can't end with a space or '.'".to_string());
+    }
+    Ok(())
+}
+
+/// Load the profile registry and make its active profile the one files are read from
+pub fn init() {
+    storage::set_active_profile(&ProfileRegistry::load().active);
+}
+
+/// Moons saved in the active profile
+pub fn load_moons() -> Vec<MoonComposition> {
+    storage::load_json(&storage::profile_file(MOONS_FILE)).unwrap_or_default()
+}
+
+pub fn save_moons(moons: &[MoonComposition]) -> Result<(), String> {
+    storage::save_json(&storage::profile_file(MOONS_FILE), &moons)
+}
@@ -1,5 +1,7 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 
 use crate::prices::PriceInfo;
 use crate::reactions::ReactionDatabase;
@@ -53,21 +55,24 @@ pub fn reaction_uses_user_materials(
 }
 
 /// Source type for a material in the reaction tree
-#[derive(Debug, Clone, Serialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SourceType {
     Moon,   // Comes from user's moons
     Buy,    // Must be purchased
     React,  // Produced by running a reaction
     Output, // Final output (sell this)
+    Stock,  // Fully covered by the user's inventory
 }
 
 /// A node in the reaction tree
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReactionTreeNode {
     pub name: String,
     pub id: u32,
     pub quantity: u32,
+    /// Part of the quantity taken from the user's inventory
+    pub from_stock: u32,
     pub source: SourceType,
     pub unit_price: f64,
     pub total_price: f64,
@@ -75,6 +80,132 @@ pub struct ReactionTreeNode {
     pub reaction_name: Option<String>,
     /// Child nodes (inputs needed to produce this)
     pub children: Vec<ReactionTreeNode>,
+    /// For items that can be both made and bought, the source that wasn't chosen
+    pub alternative: Option<NodeAlternative>,
+}
+
+/// The other way to get an item that can be made or bought, with what it would cost
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NodeAlternative {
+    pub source: SourceType,
+    pub cost: f64,
+}
+
+impl ReactionTreeNode {
+    /// Quantity that still has to be bought, reacted or mined after using stock
+    pub fn remaining_quantity(&self) -> u32 {
+        self.quantity - self.from_stock
+    }
+
+    /// Cost of the node at sell prices: its price for leaves, the stock used plus every
+    /// input for reacted items
+    pub fn chain_cost(&self) -> f64 {
+        match self.source {
+            SourceType::React | SourceType::Output => {
+                self.from_stock as f64 * self.unit_price
+                    + self
+                        .children
+                        .iter()
+                        .map(ReactionTreeNode::chain_cost)
+                        .sum::<f64>()
+            }
+            _ => self.total_price,
+        }
+    }
+}
+
+/// Shared inputs for building reaction trees
+pub struct TreeContext<'a> {
+    pub reactions_db: &'a ReactionDatabase,
+    pub user_moon_goo_ids: &'a HashSet<u32>,
+    pub prices: &'a HashMap<String, PriceInfo>,
+    /// Quantities the user already has in inventory, by item ID
+    pub stock: &'a HashMap<u32, u32>,
+    /// Reaction levels to expand before treating deeper intermediates as bought
+    pub max_depth: Option<usize>,
+    /// Previously built subtrees to reuse, if caching is enabled
+    pub subtrees: Option<CacheEpoch<'a>>,
+    /// Items forced to be bought or reacted instead of the cheaper source, by item ID
+    pub overrides: Option<&'a HashMap<u32, SourceOverride>>,
+}
+
+/// Source forced on an item that can be made or bought
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceOverride {
+    Buy,
+    React,
+}
+
+/// Identifies a subtree built for a given pricing epoch. The remaining depth is part of
+/// the key because a depth cap cuts the same chain off at different levels
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct SubtreeKey {
+    item_id: u32,
+    epoch: u64,
+    quantity: u32,
+    depth_left: Option<usize>,
+}
+
+/// Subtrees of intermediate chains already built against the current prices, so chains
+/// shared by many reactions are only expanded once
+#[derive(Default)]
+pub struct SubtreeCache {
+    epoch: AtomicU64,
+    subtrees: Mutex<HashMap<SubtreeKey, ReactionTreeNode>>,
+}
+
+impl SubtreeCache {
+    /// Handle for building trees against the prices in effect right now. Take it before
+    /// reading the prices so a concurrent change can't file old subtrees under a new epoch
+    pub fn current(&self) -> CacheEpoch<'_> {
+        CacheEpoch {
+            cache: self,
+            epoch: self.epoch.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Start a new pricing epoch, dropping every cached subtree. Call whenever prices,
+    /// moons or reaction data change
+    pub fn invalidate(&self) {
+        self.epoch.fetch_add(1, Ordering::SeqCst);
+        if let Ok(mut subtrees) = self.subtrees.lock() {
+            subtrees.clear();
+        }
+    }
+
+    fn get(&self, key: &SubtreeKey) -> Option<ReactionTreeNode> {
+        self.subtrees.lock().ok()?.get(key).cloned()
+    }
+
+    fn insert(&self, key: SubtreeKey, node: &ReactionTreeNode) {
+        // Subtrees from an epoch that has since ended would never be looked up again
+        if key.epoch != self.epoch.load(Ordering::SeqCst) {
+            return;
+        }
+        if let Ok(mut subtrees) = self.subtrees.lock() {
+            subtrees.insert(key, node.clone());
+        }
+    }
+}
+
+/// A subtree cache along with the pricing epoch of the tree being built
+#[derive(Clone, Copy)]
+pub struct CacheEpoch<'a> {
+    cache: &'a SubtreeCache,
+    epoch: u64,
+}
+
+/// Take up to `quantity` of an item from the remaining stock, returning how much was taken
+pub fn take_from_stock(stock: &mut HashMap<u32, u32>, item_id: u32, quantity: u32) -> u32 {
+    match stock.get_mut(&item_id) {
+        Some(available) => {
+            let taken = (*available).min(quantity);
+            *available -= taken;
+            taken
+        }
+        None => 0,
+    }
 }
 
 /// Build a reaction tree for a given output item
@@ -82,128 +213,184 @@ pub fn build_reaction_tree(
     item_name: &str,
     item_id: u32,
     quantity: u32,
-    reactions_db: &ReactionDatabase,
-    user_moon_goo_ids: &HashSet<u32>,
-    prices: &HashMap<String, PriceInfo>,
-    visited: &mut HashSet<u32>, // Prevent infinite loops
+    ctx: &TreeContext,
+    visited: &mut HashSet<u32>,              // Prevent infinite loops
+    remaining_stock: &mut HashMap<u32, u32>, // Stock not yet used by other branches
 ) -> ReactionTreeNode {
-    let unit_price = prices.get(item_name).map(|p| p.sell).unwrap_or(0.0);
+    let unit_price = ctx.prices.get(item_name).map(|p| p.sell).unwrap_or(0.0);
     let total_price = unit_price * quantity as f64;
+    let node = |source: SourceType, from_stock: u32| ReactionTreeNode {
+        name: item_name.to_string(),
+        id: item_id,
+        quantity,
+        from_stock,
+        source,
+        unit_price,
+        total_price,
+        reaction_name: None,
+        children: vec![],
+        alternative: None,
+    };
 
     // Check if this is from user's moons
-    if user_moon_goo_ids.contains(&item_id) {
-        return ReactionTreeNode {
-            name: item_name.to_string(),
-            id: item_id,
-            quantity,
-            source: SourceType::Moon,
-            unit_price,
-            total_price,
-            reaction_name: None,
-            children: vec![],
-        };
+    if ctx.user_moon_goo_ids.contains(&item_id) {
+        return node(SourceType::Moon, 0);
     }
 
-    // Check if this can be produced by a reaction (and we haven't visited it yet)
-    if let Some(reaction) = reactions_db.by_output.get(&item_id) {
-        if !visited.contains(&item_id) {
+    // Use whatever is already in inventory before buying or reacting
+    let from_stock = take_from_stock(remaining_stock, item_id, quantity);
+    let remaining = quantity - from_stock;
+    if remaining == 0 {
+        return node(SourceType::Stock, from_stock);
+    }
+
+    // Check if this can be produced by a reaction (and we haven't visited it yet);
+    // `visited` holds this node's ancestors, so its size is the current depth
+    let forced = ctx.overrides.and_then(|o| o.get(&item_id)).copied();
+    let within_depth = forced == Some(SourceOverride::React)
+        || ctx.max_depth.is_none_or(|max| visited.len() < max);
+    if let Some(reaction) = ctx.reactions_db.by_output.get(&item_id) {
+        if within_depth && !visited.contains(&item_id) {
+            // Subtrees only depend on prices and quantity while no stock is left to draw
+            // on; with stock, each branch sees a different remainder
+            let cache_key = ctx
+                .subtrees
+                .filter(|_| {
+                    ctx.overrides.is_none()
+                        && from_stock == 0
+                        && remaining_stock.values().all(|&left| left == 0)
+                })
+                .map(|subtrees| SubtreeKey {
+                    item_id,
+                    epoch: subtrees.epoch,
+                    quantity,
+                    depth_left: ctx.max_depth.map(|max| max - visited.len()),
+                });
+            if let (Some(subtrees), Some(key)) = (ctx.subtrees, &cache_key) {
+                if let Some(cached) = subtrees.cache.get(key) {
+                    return cached;
+                }
+            }
+
             visited.insert(item_id);
+            // Stock the sub-chain uses goes back if the item is bought instead
+            let stock_before = remaining_stock.clone();
 
             // Calculate how many reaction runs we need
-            let runs_needed = (quantity as f64 / reaction.output.quantity as f64).ceil() as u32;
+            let runs_needed = reaction.runs_for(remaining);
 
             // Build child nodes for each input
             let children: Vec<ReactionTreeNode> = reaction
                 .inputs
                 .iter()
                 .map(|input| {
-                    let input_quantity = input.quantity * runs_needed;
+                    let input_quantity = input.quantity.saturating_mul(runs_needed);
                     build_reaction_tree(
                         &input.name,
                         input.id,
                         input_quantity,
-                        reactions_db,
-                        user_moon_goo_ids,
-                        prices,
+                        ctx,
                         visited,
+                        remaining_stock,
                     )
                 })
                 .collect();
 
             visited.remove(&item_id); // Allow this item to be visited in other branches
 
-            return ReactionTreeNode {
-                name: item_name.to_string(),
-                id: item_id,
-                quantity,
-                source: SourceType::React,
-                unit_price,
-                total_price,
-                reaction_name: Some(reaction.formula_name.clone()),
-                children,
+            // Buy the item instead when that's cheaper than making it, unless overridden
+            let react_cost: f64 = children.iter().map(ReactionTreeNode::chain_cost).sum();
+            let buy_cost = unit_price * remaining as f64;
+            let buy = match forced {
+                Some(source) => source == SourceOverride::Buy,
+                None => unit_price > 0.0 && buy_cost < react_cost,
             };
+            let tree = if buy {
+                *remaining_stock = stock_before;
+                ReactionTreeNode {
+                    alternative: Some(NodeAlternative {
+                        source: SourceType::React,
+                        cost: react_cost,
+                    }),
+                    ..node(SourceType::Buy, from_stock)
+                }
+            } else {
+                ReactionTreeNode {
+                    reaction_name: Some(reaction.formula_name.clone()),
+                    children,
+                    alternative: (unit_price > 0.0 || forced.is_some()).then_some(
+                        NodeAlternative {
+                            source: SourceType::Buy,
+                            cost: buy_cost,
+                        },
+                    ),
+                    ..node(SourceType::React, from_stock)
+                }
+            };
+            if let (Some(subtrees), Some(key)) = (ctx.subtrees, cache_key) {
+                subtrees.cache.insert(key, &tree);
+            }
+            return tree;
         }
     }
 
     // If not from moon and not reactable, it must be bought
-    ReactionTreeNode {
-        name: item_name.to_string(),
-        id: item_id,
-        quantity,
-        source: SourceType::Buy,
-        unit_price,
-        total_price,
-        reaction_name: None,
-        children: vec![],
-    }
+    node(SourceType::Buy, from_stock)
 }
 
-/// Build the full tree for a profitable reaction output
+/// Build the full tree for a profitable reaction output, scaled to a number of runs.
+/// Fails when the runs are too many to count the output or inputs in
 pub fn build_full_reaction_tree(
     output_name: &str,
     output_id: u32,
     output_quantity: u32,
-    reactions_db: &ReactionDatabase,
-    user_moon_goo_ids: &HashSet<u32>,
-    prices: &HashMap<String, PriceInfo>,
-) -> ReactionTreeNode {
-    let unit_price = prices.get(output_name).map(|p| p.sell).unwrap_or(0.0);
+    runs: u32,
+    ctx: &TreeContext,
+) -> Result<ReactionTreeNode, String> {
+    let too_many_runs = || format!("{} runs of {} is too many to plan", runs, output_name);
+    let output_quantity = output_quantity
+        .checked_mul(runs)
+        .ok_or_else(too_many_runs)?;
+    let unit_price = ctx.prices.get(output_name).map(|p| p.sell).unwrap_or(0.0);
     let total_price = unit_price * output_quantity as f64;
 
     // Get the reaction for this output
-    let reaction = reactions_db.by_output.get(&output_id);
+    let reaction = ctx.reactions_db.by_output.get(&output_id);
 
     let children = if let Some(reaction) = reaction {
         let mut visited = HashSet::new();
         visited.insert(output_id); // Mark output as visited to prevent loops
+        let mut remaining_stock = ctx.stock.clone();
 
         reaction
             .inputs
             .iter()
             .map(|input| {
-                build_reaction_tree(
+                let quantity = input.quantity.checked_mul(runs).ok_or_else(too_many_runs)?;
+                Ok(build_reaction_tree(
                     &input.name,
                     input.id,
-                    input.quantity,
-                    reactions_db,
-                    user_moon_goo_ids,
-                    prices,
+                    quantity,
+                    ctx,
                     &mut visited,
-                )
+                    &mut remaining_stock,
+                ))
             })
-            .collect()
+            .collect::<Result<_, String>>()?
     } else {
         vec![]
     };
 
-    ReactionTreeNode {
+    Ok(ReactionTreeNode {
         name: output_name.to_string(),
         id: output_id,
         quantity: output_quantity,
+        from_stock: 0,
         source: SourceType::Output,
         unit_price,
         total_price,
         reaction_name: reaction.map(|r| r.formula_name.clone()),
         children,
-    }
+        alternative: None,
+    })
 }
@@ -1,19 +1,138 @@
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 
+use crate::datasets;
+use crate::storage;
+
+/// Reactions in the app data directory that add to or replace the standard data
+const CUSTOM_REACTIONS_FILE: &str = "reactions.custom.json";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReactionItem {
     pub name: String,
     pub id: u32,
     pub quantity: u32,
+    /// Volume of one unit in m3, zero when the data file doesn't list it
+    #[serde(default)]
+    pub volume_m3: f64,
+}
+
+/// Base duration of one reaction run when the data file doesn't specify one
+pub const DEFAULT_RUN_SECONDS: u32 = 10_800;
+/// Most runs a single reaction job can be started with when the data file doesn't specify
+pub const DEFAULT_MAX_RUNS_PER_JOB: u32 = 100;
+
+fn default_run_seconds() -> u32 {
+    DEFAULT_RUN_SECONDS
+}
+
+fn default_max_runs_per_job() -> u32 {
+    DEFAULT_MAX_RUNS_PER_JOB
+}
+
+/// Broad kind of reaction
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum ReactionCategory {
+    /// Moon goo into intermediate composites
+    #[serde(alias = "intermediate_composite")]
+    Intermediate,
+    /// Intermediates into advanced composites
+    #[serde(alias = "advanced_composite")]
+    Composite,
+    /// Booster gases into boosters
+    #[serde(alias = "biochemical")]
+    Biochem,
+    /// Molecular-forged polymer chain
+    Polymer,
+    /// Alchemy: common goo into unrefined versions of rarer intermediates
+    Unrefined,
+}
+
+impl ReactionCategory {
+    /// Name shown for the category
+    pub fn label(self) -> &'static str {
+        match self {
+            ReactionCategory::Intermediate => "Intermediate composites",
+            ReactionCategory::Composite => "Advanced composites",
+            ReactionCategory::Biochem => "Biochemical",
+            ReactionCategory::Polymer => "Polymers",
+            ReactionCategory::Unrefined => "Unrefined",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Reaction {
     pub formula_id: u32,
     pub formula_name: String,
+    /// Category from the data file; worked out from the reaction's inputs when missing
+    #[serde(default)]
+    pub category: Option<ReactionCategory>,
     pub output: ReactionItem,
     pub inputs: Vec<ReactionItem>,
+    /// Duration of a single run, in seconds
+    #[serde(default = "default_run_seconds")]
+    pub run_seconds: u32,
+    #[serde(default = "default_max_runs_per_job")]
+    pub max_runs_per_job: u32,
+}
+
+impl Reaction {
+    /// Runs needed to produce at least the given quantity of output
+    pub fn runs_for(&self, quantity: u32) -> u32 {
+        quantity.div_ceil(self.output.quantity.max(1))
+    }
+
+    /// Volume of the inputs for one run, in m3
+    pub fn input_volume_m3(&self) -> f64 {
+        self.inputs
+            .iter()
+            .map(|input| input.quantity as f64 * input.volume_m3)
+            .sum()
+    }
+
+    /// Volume of the output of one run, in m3
+    pub fn output_volume_m3(&self) -> f64 {
+        self.output.quantity as f64 * self.output.volume_m3
+    }
+
+    /// Runs one slot gets through in a day, if a finished job is restarted at most once a
+    /// day: short jobs are capped by the runs a job can hold
+    pub fn runs_per_day(&self) -> f64 {
+        let continuous = 86_400.0 / self.run_seconds.max(1) as f64;
+        continuous.min(self.max_runs_per_job.max(1) as f64)
+    }
+
+    /// Jobs needed to start the given number of runs
+    pub fn jobs_for(&self, runs: u32) -> u32 {
+        runs.div_ceil(self.max_runs_per_job.max(1))
+    }
+}
+
+fn parse_reactions(json_str: &str) -> Result<Vec<Reaction>, String> {
+    serde_json::from_str(json_str).map_err(|e| format!("Failed to parse reactions: {}", e))
+}
+
+/// The downloaded reaction data, or the bundled copy if there is none or it doesn't parse
+fn standard_reactions() -> Result<Vec<Reaction>, String> {
+    match datasets::installed_reactions().map(|json| parse_reactions(&json)) {
+        Some(Ok(reactions)) => Ok(reactions),
+        _ => parse_reactions(datasets::BUNDLED_REACTIONS),
+    }
+}
+
+/// Add custom reactions, replacing any standard reaction with the same formula ID
+fn merge_reactions(reactions: &mut Vec<Reaction>, custom: Vec<Reaction>) {
+    for reaction in custom {
+        match reactions
+            .iter_mut()
+            .find(|r| r.formula_id == reaction.formula_id)
+        {
+            Some(existing) => *existing = reaction,
+            None => reactions.push(reaction),
+        }
+    }
 }
 
 /// Loaded reactions database
@@ -23,31 +142,109 @@ pub struct ReactionDatabase {
     pub by_output: HashMap<u32, Reaction>,
     /// Map from item name to item ID
     pub name_to_id: HashMap<String, u32>,
+    /// Map from formula ID to category
+    pub categories: HashMap<u32, ReactionCategory>,
+    /// Map from item ID to the volume of one unit, in m3
+    pub volumes: HashMap<u32, f64>,
 }
 
 impl ReactionDatabase {
+    /// Load the standard reaction data with the user's custom reactions merged over it
     pub fn load() -> Result<Self, String> {
-        let json_str = include_str!("../reactions.json");
-        let reactions: Vec<Reaction> = serde_json::from_str(json_str)
-            .map_err(|e| format!("Failed to parse reactions: {}", e))?;
+        let mut reactions = standard_reactions()?;
+
+        if let Some(json) = storage::load_text(CUSTOM_REACTIONS_FILE) {
+            let custom = parse_reactions(&json)
+                .map_err(|e| format!("{} in {}", e, CUSTOM_REACTIONS_FILE))?;
+            merge_reactions(&mut reactions, custom);
+        }
+
+        Ok(Self::from_reactions(reactions))
+    }
+
+    /// Load the standard reaction data only, ignoring custom reactions
+    pub fn load_standard() -> Result<Self, String> {
+        Ok(Self::from_reactions(standard_reactions()?))
+    }
+
+    /// Parse reaction data in the `reactions.json` format
+    pub fn from_json(json_str: &str) -> Result<Self, String> {
+        Ok(Self::from_reactions(parse_reactions(json_str)?))
+    }
+
+    /// Build the lookup tables for a list of reactions
+    pub fn from_reactions(mut reactions: Vec<Reaction>) -> Self {
+        let categories = categorize(&reactions);
+        for reaction in &mut reactions {
+            reaction.category = categories.get(&reaction.formula_id).copied();
+        }
 
         let mut by_output = HashMap::new();
         let mut name_to_id = HashMap::new();
+        let mut volumes = HashMap::new();
 
         for reaction in &reactions {
             by_output.insert(reaction.output.id, reaction.clone());
             name_to_id.insert(reaction.output.name.clone(), reaction.output.id);
 
-            for input in &reaction.inputs {
-                name_to_id.insert(input.name.clone(), input.id);
+            for item in std::iter::once(&reaction.output).chain(&reaction.inputs) {
+                name_to_id.insert(item.name.clone(), item.id);
+                if item.volume_m3 > 0.0 {
+                    volumes.insert(item.id, item.volume_m3);
+                }
             }
         }
 
-        Ok(Self {
+        Self {
             reactions,
             by_output,
             name_to_id,
-        })
+            categories,
+            volumes,
+        }
+    }
+
+    /// Volume of one unit of an item in m3, zero if no reaction lists it
+    pub fn volume_m3(&self, item_id: u32) -> f64 {
+        self.volumes.get(&item_id).copied().unwrap_or(0.0)
+    }
+
+    /// Category of a reaction
+    pub fn category_of(&self, formula_id: u32) -> Option<ReactionCategory> {
+        self.categories.get(&formula_id).copied()
+    }
+
+    /// Find reactions whose formula, output or any input contains the query
+    /// (case-insensitive). Output and formula matches come before input matches
+    pub fn search(&self, query: &str) -> Vec<&Reaction> {
+        let query = query.trim().to_lowercase();
+        if query.is_empty() {
+            return Vec::new();
+        }
+        let matches = |name: &str| name.to_lowercase().contains(&query);
+
+        let mut found: Vec<(u8, &Reaction)> = self
+            .reactions
+            .iter()
+            .filter_map(|reaction| {
+                let rank = if matches(&reaction.output.name) {
+                    0
+                } else if matches(&reaction.formula_name) {
+                    1
+                } else if reaction.inputs.iter().any(|input| matches(&input.name)) {
+                    2
+                } else {
+                    return None;
+                };
+                Some((rank, reaction))
+            })
+            .collect();
+        found.sort_by(|(rank_a, a), (rank_b, b)| {
+            rank_a
+                .cmp(rank_b)
+                .then_with(|| a.output.name.cmp(&b.output.name))
+        });
+        found.into_iter().map(|(_, reaction)| reaction).collect()
     }
 
     /// Get all unique item names needed for price lookups
@@ -70,3 +267,78 @@ impl ReactionDatabase {
             .collect()
     }
 }
+
+/// Each reaction's category: the one the data file gives, or else worked out from the
+/// shape of the data. Unrefined outputs are alchemy, polymer reactions run without fuel
+/// blocks (along with the reactions that only feed them), reactions using booster gases
+/// are biochemical, reactions consuming other reactions' outputs are composites and
+/// everything else is an intermediate
+fn categorize(reactions: &[Reaction]) -> HashMap<u32, ReactionCategory> {
+    let outputs: HashSet<u32> = reactions.iter().map(|r| r.output.id).collect();
+    let uses_fuel = |r: &Reaction| r.inputs.iter().any(|i| i.name.ends_with("Fuel Block"));
+    let is_booster_gas = |name: &str| name.contains("Cytoserocin") || name.contains("Mykoserocin");
+
+    let polymer_ids: HashSet<u32> = reactions
+        .iter()
+        .filter(|r| !uses_fuel(r))
+        .map(|r| r.formula_id)
+        .collect();
+
+    reactions
+        .iter()
+        .map(|reaction| {
+            if let Some(category) = reaction.category {
+                return (reaction.formula_id, category);
+            }
+            let consumers: Vec<&Reaction> = reactions
+                .iter()
+                .filter(|r| r.inputs.iter().any(|i| i.id == reaction.output.id))
+                .collect();
+            let feeds_only_polymers = !consumers.is_empty()
+                && consumers
+                    .iter()
+                    .all(|r| polymer_ids.contains(&r.formula_id));
+
+            let category = if reaction.output.name.starts_with("Unrefined") {
+                ReactionCategory::Unrefined
+            } else if polymer_ids.contains(&reaction.formula_id) || feeds_only_polymers {
+                ReactionCategory::Polymer
+            } else if reaction.inputs.iter().any(|i| is_booster_gas(&i.name)) {
+                ReactionCategory::Biochem
+            } else if reaction.inputs.iter().any(|i| outputs.contains(&i.id)) {
+                ReactionCategory::Composite
+            } else {
+                ReactionCategory::Intermediate
+            };
+            (reaction.formula_id, category)
+        })
+        .collect()
+}
+
+/// Fixtures for tests that build small reaction databases by hand
+#[cfg(test)]
+pub(crate) mod test_support {
+    use super::{Reaction, ReactionItem};
+
+    pub fn item(name: &str, id: u32, quantity: u32) -> ReactionItem {
+        ReactionItem {
+            name: name.to_string(),
+            id,
+            quantity,
+            volume_m3: 0.0,
+        }
+    }
+
+    /// A one-hour reaction of up to 100 runs per job
+    pub fn reaction(formula_id: u32, output: ReactionItem, inputs: Vec<ReactionItem>) -> Reaction {
+        Reaction {
+            formula_id,
+            formula_name: format!("{} Reaction Formula", output.name),
+            category: None,
+            output,
+            inputs,
+            run_seconds: 3600,
+            max_runs_per_job: 100,
+        }
+    }
+}
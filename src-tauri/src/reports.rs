@@ -0,0 +1,366 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::prices::ReactionProfit;
+use crate::schedule::UpcomingExtraction;
+use crate::shopping::ShoppingList;
+use crate::slots::JobSchedule;
+use crate::storage;
+use crate::valuation::MoonValuation;
+
+const CONFIG_FILE: &str = "weekly_report.json";
+/// Time between automatic reports
+pub const REPORT_INTERVAL_SECS: u64 = 7 * 24 * 60 * 60;
+/// Discord rejects messages longer than this
+const DISCORD_MESSAGE_LIMIT: usize = 2000;
+
+/// Settings for the automatic weekly summary
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WeeklyReportConfig {
+    pub enabled: bool,
+    /// Folder the reports are written to; `reports/` in the data directory by default
+    pub output_dir: Option<String>,
+    /// Discord webhook the summary is also posted to
+    pub discord_webhook: Option<String>,
+    /// Number of reactions listed in the report
+    pub top_reactions: usize,
+    /// Unix timestamp (seconds) of the last generated report
+    pub last_run: Option<u64>,
+}
+
+impl Default for WeeklyReportConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            output_dir: None,
+            discord_webhook: None,
+            top_reactions: 10,
+            last_run: None,
+        }
+    }
+}
+
+impl WeeklyReportConfig {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(CONFIG_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(CONFIG_FILE), self)
+    }
+
+    /// Whether a week has passed since the last report
+    pub fn is_due(&self, now: u64) -> bool {
+        self.enabled
+            && self
+                .last_run
+                .is_none_or(|last| now.saturating_sub(last) >= REPORT_INTERVAL_SECS)
+    }
+
+    /// Path of the report file for the given time
+    pub fn report_path(&self, now: u64) -> Result<PathBuf, String> {
+        let file_name = format!("weekly-{}.md", date_string(now));
+        match &self.output_dir {
+            Some(dir) => Ok(PathBuf::from(dir).join(file_name)),
+            None => storage::data_file(&format!("reports/{}", file_name))
+                .ok_or_else(|| "Could not determine data directory".to_string()),
+        }
+    }
+}
+
+/// Format a unix timestamp as a UTC date (YYYY-MM-DD)
+pub fn date_string(unix_secs: u64) -> String {
+    let (year, month, day) = civil_date(unix_secs);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// UTC year, month and day of a unix timestamp
+pub fn civil_date(unix_secs: u64) -> (i64, i64, i64) {
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let days = (unix_secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Render the weekly summary as Markdown
+pub fn render_weekly(
+    now: u64,
+    moons: &[MoonValuation],
+    extractions: &[UpcomingExtraction],
+    top_reactions: &[ReactionProfit],
+) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# Weekly moon summary - {}\n", date_string(now));
+
+    let _ = writeln!(out, "## Portfolio\n");
+    if moons.is_empty() {
+        let _ = writeln!(out, "No moons loaded.\n");
+    } else {
+        let total: f64 = moons.iter().map(|m| m.monthly_isk).sum();
+        let _ = writeln!(out, "Estimated monthly value: {} ISK\n", format_isk(total));
+        for moon in moons {
+            let _ = writeln!(
+                out,
+                "- {}: {} ISK/month",
+                moon.name,
+                format_isk(moon.monthly_isk)
+            );
+        }
+        out.push('\n');
+    }
+
+    let _ = writeln!(out, "## Upcoming extractions\n");
+    if extractions.is_empty() {
+        let _ = writeln!(out, "No extractions are being tracked.\n");
+    } else {
+        for extraction in extractions {
+            let _ = write!(
+                out,
+                "- {}: {} {:02}:{:02} UTC",
+                extraction.moon,
+                date_string(extraction.arrives_at),
+                extraction.arrives_at % 86_400 / 3600,
+                extraction.arrives_at % 3600 / 60
+            );
+            if extraction.arrived {
+                let _ = writeln!(out, " (arrived)");
+            } else {
+                let secs = extraction.seconds_remaining;
+                let _ = writeln!(out, " (in {}d {}h)", secs / 86_400, secs % 86_400 / 3600);
+            }
+        }
+        out.push('\n');
+    }
+
+    let _ = writeln!(out, "## Top reactions\n");
+    if top_reactions.is_empty() {
+        let _ = writeln!(out, "No analysis has been run yet.");
+    } else {
+        for (rank, result) in top_reactions.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "{}. {}: {} ISK profit ({:.1}% margin)",
+                rank + 1,
+                result.output_name,
+                format_isk(result.profit),
+                result.margin
+            );
+        }
+    }
+
+    out
+}
+
+/// What goes into a printable HTML report
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HtmlReportOptions {
+    /// Reactions listed from the last analysis
+    pub top_reactions: usize,
+    /// How many of the top reactions get a shopping list for one full job each
+    pub shopping_lists: usize,
+    /// Reaction slots the schedule for those jobs is laid out on
+    pub characters: u32,
+    pub slots_per_character: u32,
+}
+
+impl Default for HtmlReportOptions {
+    fn default() -> Self {
+        Self {
+            top_reactions: 10,
+            shopping_lists: 3,
+            characters: 1,
+            slots_per_character: 10,
+        }
+    }
+}
+
+/// Contents of a printable report
+pub struct HtmlReport<'a> {
+    pub now: u64,
+    pub moons: &'a [MoonValuation],
+    pub top_reactions: &'a [ReactionProfit],
+    pub shopping_lists: &'a [ShoppingList],
+    pub schedule: Option<&'a JobSchedule>,
+}
+
+/// Default path of a printable report written at the given time
+pub fn html_report_path(now: u64) -> Result<PathBuf, String> {
+    storage::data_file(&format!("reports/report-{}.html", date_string(now)))
+        .ok_or_else(|| "Could not determine data directory".to_string())
+}
+
+/// Render a standalone HTML page with the moons, top reactions, shopping lists and job
+/// schedule, styled inline so it prints and opens anywhere
+pub fn render_html(report: &HtmlReport) -> String {
+    let mut out = String::new();
+    let date = date_string(report.now);
+    let _ = writeln!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Moon report - {date}</title>\n<style>\n\
+         body {{ font-family: Helvetica, Arial, sans-serif; margin: 2em; color: #222; }}\n\
+         table {{ border-collapse: collapse; margin-bottom: 1.5em; }}\n\
+         th, td {{ border: 1px solid #ccc; padding: 0.3em 0.6em; text-align: left; }}\n\
+         td.num {{ text-align: right; }}\n\
+         h2 {{ border-bottom: 2px solid #444; }}\n\
+         @media print {{ h2 {{ page-break-before: always; }} h2:first-of-type {{ page-break-before: avoid; }} }}\n\
+         </style>\n</head>\n<body>\n<h1>Moon report - {date}</h1>"
+    );
+
+    let _ = writeln!(out, "<h2>Moons</h2>");
+    if report.moons.is_empty() {
+        let _ = writeln!(out, "<p>No moons loaded.</p>");
+    } else {
+        let total: f64 = report.moons.iter().map(|m| m.monthly_isk).sum();
+        let _ = writeln!(
+            out,
+            "<p>Estimated monthly value: {} ISK</p>",
+            format_isk(total)
+        );
+        let _ = writeln!(
+            out,
+            "<table>\n<tr><th>Moon</th><th>ISK/month</th><th>Jumps to market</th></tr>"
+        );
+        for moon in report.moons {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+                escape_html(&moon.name),
+                format_isk(moon.monthly_isk),
+                moon.jumps_to_market
+                    .map_or_else(|| "-".to_string(), |jumps| jumps.to_string())
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    let _ = writeln!(out, "<h2>Top reactions</h2>");
+    if report.top_reactions.is_empty() {
+        let _ = writeln!(out, "<p>No analysis has been run yet.</p>");
+    } else {
+        let _ = writeln!(
+            out,
+            "<table>\n<tr><th>#</th><th>Output</th><th>Profit/run</th><th>Margin</th><th>ISK/day</th></tr>"
+        );
+        for (rank, result) in report.top_reactions.iter().enumerate() {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}%</td><td class=\"num\">{}</td></tr>",
+                rank + 1,
+                escape_html(&result.output_name),
+                format_isk(result.profit),
+                result.margin,
+                format_isk(result.isk_per_day)
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    if !report.shopping_lists.is_empty() {
+        let _ = writeln!(out, "<h2>Shopping lists</h2>");
+    }
+    for list in report.shopping_lists {
+        let _ = writeln!(
+            out,
+            "<h3>{} ({} runs) - {} ISK</h3>\n<table>\n<tr><th>Item</th><th>Quantity</th><th>Source</th><th>ISK</th></tr>",
+            escape_html(&list.output_name),
+            list.runs,
+            format_isk(list.total_cost)
+        );
+        for item in &list.items {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td class=\"num\">{}</td><td>{:?}</td><td class=\"num\">{}</td></tr>",
+                escape_html(&item.name),
+                item.quantity,
+                item.source,
+                format_isk(item.total_price)
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    if let Some(schedule) = report.schedule.filter(|s| !s.jobs.is_empty()) {
+        let _ = writeln!(
+            out,
+            "<h2>Job schedule</h2>\n<p>{} slots, done after {:.1} hours</p>\n<table>\n\
+             <tr><th>Character</th><th>Slot</th><th>Reaction</th><th>Runs</th><th>Start (h)</th><th>End (h)</th></tr>",
+            schedule.slots,
+            schedule.total_secs as f64 / 3600.0
+        );
+        for job in &schedule.jobs {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{:.1}</td><td class=\"num\">{:.1}</td></tr>",
+                job.character,
+                job.slot,
+                escape_html(&job.output_name),
+                job.runs,
+                job.start_secs as f64 / 3600.0,
+                job.end_secs as f64 / 3600.0
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    let _ = writeln!(out, "</body>\n</html>");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn format_isk(value: f64) -> String {
+    if value.abs() >= 1e9 {
+        format!("{:.2}b", value / 1e9)
+    } else if value.abs() >= 1e6 {
+        format!("{:.2}m", value / 1e6)
+    } else {
+        format!("{:.0}", value)
+    }
+}
+
+/// Write a report to disk, creating its folder if needed
+pub fn write_report(path: &Path, report: &str) -> Result<(), String> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+    }
+    std::fs::write(path, report).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+}
+
+/// Post a report to a Discord webhook, truncated to fit a single message
+pub async fn post_to_discord(webhook: &str, report: &str) -> Result<(), String> {
+    let content: String = report.chars().take(DISCORD_MESSAGE_LIMIT).collect();
+    let response = reqwest::Client::new()
+        .post(webhook)
+        .json(&serde_json::json!({ "content": content }))
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to post report to Discord: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Discord returned status: {}", response.status()));
+    }
+    Ok(())
+}
@@ -0,0 +1,210 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::prices::now_unix;
+use crate::reports::{civil_date, date_string};
+use crate::storage;
+
+const SCHEDULE_FILE: &str = "extraction_schedule.json";
+
+/// Longest chunk a moon drill can be set to grow
+const MAX_CHUNK_SECS: u64 = 56 * 86_400;
+/// How long after arrival a chunk is fractured automatically, used as the event length
+const AUTO_FRACTURE_SECS: u64 = 3 * 3600;
+
+/// A moon chunk being grown by a drill
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Extraction {
+    pub moon: String,
+    /// Unix timestamp (seconds) the extraction was started
+    pub started_at: u64,
+    pub duration_secs: u64,
+    /// Whether the arrival notification has been shown
+    #[serde(default)]
+    pub notified: bool,
+}
+
+impl Extraction {
+    /// Unix timestamp (seconds) the chunk arrives
+    pub fn arrives_at(&self) -> Result<u64, String> {
+        self.started_at
+            .checked_add(self.duration_secs)
+            .ok_or_else(|| format!("Extraction on {} arrives too far in the future", self.moon))
+    }
+}
+
+/// A tracked extraction with the time left until its chunk arrives
+#[derive(Debug, Clone, Serialize)]
+pub struct UpcomingExtraction {
+    pub moon: String,
+    pub started_at: u64,
+    pub arrives_at: u64,
+    /// Zero once the chunk has arrived
+    pub seconds_remaining: u64,
+    pub arrived: bool,
+}
+
+/// Persisted extraction timers, one per moon
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExtractionSchedule {
+    extractions: HashMap<String, Extraction>,
+}
+
+impl ExtractionSchedule {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(SCHEDULE_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(SCHEDULE_FILE), self)
+    }
+
+    /// Track an extraction, replacing the moon's previous one
+    pub fn set(&mut self, moon: &str, started_at: u64, duration_secs: u64) -> Result<(), String> {
+        let moon = moon.trim();
+        if moon.is_empty() {
+            return Err("Moon name is required".to_string());
+        }
+        if duration_secs == 0 || duration_secs > MAX_CHUNK_SECS {
+            return Err("Chunk duration must be between 1 second and 56 days".to_string());
+        }
+        let extraction = Extraction {
+            moon: moon.to_string(),
+            started_at,
+            duration_secs,
+            notified: false,
+        };
+        extraction.arrives_at()?;
+        self.extractions.insert(moon.to_string(), extraction);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, moon: &str) {
+        self.extractions.remove(moon.trim());
+    }
+
+    /// Tracked extractions, soonest arrival first. Entries whose arrival can't be worked
+    /// out (only possible in a hand-edited file) are left out
+    pub fn extractions(&self) -> Vec<Extraction> {
+        let mut extractions: Vec<Extraction> = self
+            .extractions
+            .values()
+            .filter(|e| e.arrives_at().is_ok())
+            .cloned()
+            .collect();
+        extractions.sort_by_key(|e| (e.arrives_at().ok(), e.moon.clone()));
+        extractions
+    }
+
+    /// Extractions arriving within `lead_secs` that haven't been notified yet, marked as
+    /// notified so each chunk is announced once
+    pub fn take_due_alerts(&mut self, now: u64, lead_secs: u64) -> Vec<Extraction> {
+        let mut due: Vec<Extraction> = self
+            .extractions
+            .values_mut()
+            .filter(|e| {
+                !e.notified
+                    && e.arrives_at()
+                        .is_ok_and(|at| at <= now.saturating_add(lead_secs))
+            })
+            .map(|e| {
+                e.notified = true;
+                e.clone()
+            })
+            .collect();
+        due.sort_by_key(|e| e.arrives_at().ok());
+        due
+    }
+
+    /// Tracked extractions with time remaining, soonest arrival first
+    pub fn upcoming(&self) -> Vec<UpcomingExtraction> {
+        let now = now_unix();
+        self.extractions()
+            .into_iter()
+            .filter_map(|e| {
+                let arrives_at = e.arrives_at().ok()?;
+                Some(UpcomingExtraction {
+                    arrives_at,
+                    seconds_remaining: arrives_at.saturating_sub(now),
+                    arrived: arrives_at <= now,
+                    moon: e.moon,
+                    started_at: e.started_at,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Render the tracked extractions as an iCalendar file with an event per chunk arrival
+pub fn to_ical(extractions: &[Extraction], now: u64) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//isomerc//MOON Reaction Calculator//EN".to_string(),
+        "CALSCALE:GREGORIAN".to_string(),
+    ];
+    for extraction in extractions {
+        let Ok(arrives_at) = extraction.arrives_at() else {
+            continue;
+        };
+        lines.extend([
+            "BEGIN:VEVENT".to_string(),
+            format!(
+                "UID:{}-{}@moon.isomerc",
+                extraction
+                    .moon
+                    .chars()
+                    .filter(|c| c.is_ascii_alphanumeric())
+                    .collect::<String>(),
+                arrives_at
+            ),
+            format!("DTSTAMP:{}", ical_time(now)),
+            format!("DTSTART:{}", ical_time(arrives_at)),
+            format!(
+                "DTEND:{}",
+                ical_time(arrives_at.saturating_add(AUTO_FRACTURE_SECS))
+            ),
+            format!(
+                "SUMMARY:{}",
+                ical_escape(&format!("Moon chunk: {}", extraction.moon))
+            ),
+            format!(
+                "DESCRIPTION:{}",
+                ical_escape(&format!(
+                    "Chunk ready to fracture. Extraction started {} {:02}:{:02} UTC.",
+                    date_string(extraction.started_at),
+                    extraction.started_at % 86_400 / 3600,
+                    extraction.started_at % 3600 / 60
+                ))
+            ),
+            "END:VEVENT".to_string(),
+        ]);
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut out = lines.join("\r\n");
+    out.push_str("\r\n");
+    out
+}
+
+// UTC date-time in the iCalendar basic format, e.g. 20250302T141500Z
+fn ical_time(unix_secs: u64) -> String {
+    let (year, month, day) = civil_date(unix_secs);
+    let secs = unix_secs % 86_400;
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+fn ical_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
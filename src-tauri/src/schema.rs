@@ -0,0 +1,128 @@
+use serde_json::{json, Value};
+
+/// Schema version of the JSON files this build writes. Every saved file is wrapped as
+/// `{"schema_version": n, "data": ...}`; files without the wrapper predate versioning and
+/// are version 0
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// One upgrade step, taking a file's contents from `version - 1` to `version`
+struct SchemaMigration {
+    version: u32,
+    /// File the step applies to, matched on the name without its folder (so it covers
+    /// every profile); `None` for every file
+    file: Option<&'static str>,
+    description: &'static str,
+    run: fn(Value) -> Result<Value, String>,
+}
+
+/// Every file migration, in order. A change to a persisted type that old data can't be read
+/// into, such as renaming a field of `MoonComposition` or `Settings`, bumps
+/// `SCHEMA_VERSION` and adds a step here that rewrites the old shape
+const SCHEMA_MIGRATIONS: &[SchemaMigration] = &[SchemaMigration {
+    version: 1,
+    file: None,
+    description: "Start recording schema versions; contents are unchanged",
+    run: Ok,
+}];
+
+/// Wrap a file's contents with the current schema version, ready to save
+pub fn wrap(data: Value) -> Value {
+    json!({ "schema_version": SCHEMA_VERSION, "data": data })
+}
+
+/// Schema version a stored file was written at
+pub fn stored_version(stored: &Value) -> u32 {
+    envelope(stored).map(|(version, _)| version).unwrap_or(0)
+}
+
+/// Unwrap a stored file and bring its contents up to the current schema. Files written by a
+/// newer build are refused rather than read with fields missing
+pub fn upgrade(file_name: &str, stored: Value) -> Result<Value, String> {
+    let (version, data) = match envelope(&stored) {
+        Some((version, data)) => (version, data.clone()),
+        None => (0, stored),
+    };
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "{} was saved by a newer version of the app (schema {}, this build reads up to {})",
+            file_name, version, SCHEMA_VERSION
+        ));
+    }
+    apply(SCHEMA_MIGRATIONS, file_name, version, data)
+}
+
+fn envelope(stored: &Value) -> Option<(u32, &Value)> {
+    let map = stored.as_object().filter(|map| map.len() == 2)?;
+    let version = map.get("schema_version")?.as_u64()?;
+    Some((u32::try_from(version).ok()?, map.get("data")?))
+}
+
+fn apply(
+    migrations: &[SchemaMigration],
+    file_name: &str,
+    version: u32,
+    mut data: Value,
+) -> Result<Value, String> {
+    let base_name = file_name.rsplit('/').next().unwrap_or(file_name);
+    for migration in migrations.iter().filter(|m| m.version > version) {
+        if migration.file.is_some_and(|file| file != base_name) {
+            continue;
+        }
+        data = (migration.run)(data).map_err(|e| {
+            format!(
+                "Failed to upgrade {} to schema {} ({}): {}",
+                file_name, migration.version, migration.description, e
+            )
+        })?;
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename_quantity(mut data: Value) -> Result<Value, String> {
+        if let Some(moon) = data.as_object_mut() {
+            let quantity = moon.remove("qty").ok_or("no qty")?;
+            moon.insert("quantity".to_string(), quantity);
+        }
+        Ok(data)
+    }
+
+    #[test]
+    fn test_upgrade_reads_unversioned_and_refuses_newer_files() {
+        let bare = json!([{ "name": "Moon 1" }]);
+        assert_eq!(
+            upgrade("profiles/Default/moons.json", bare.clone()).unwrap(),
+            bare
+        );
+
+        let wrapped = wrap(bare.clone());
+        assert_eq!(stored_version(&wrapped), SCHEMA_VERSION);
+        assert_eq!(upgrade("moons.json", wrapped).unwrap(), bare);
+
+        let newer = json!({ "schema_version": SCHEMA_VERSION + 1, "data": bare });
+        assert!(upgrade("moons.json", newer).is_err());
+    }
+
+    #[test]
+    fn test_apply_runs_only_pending_steps_for_the_file() {
+        let steps = [SchemaMigration {
+            version: 2,
+            file: Some("inventory.json"),
+            description: "Rename qty",
+            run: rename_quantity,
+        }];
+        let old = json!({ "qty": 5 });
+
+        let upgraded = apply(&steps, "profiles/Main/inventory.json", 1, old.clone()).unwrap();
+        assert_eq!(upgraded, json!({ "quantity": 5 }));
+        // Already at version 2, or a different file: left alone
+        assert_eq!(
+            apply(&steps, "inventory.json", 2, old.clone()).unwrap(),
+            old
+        );
+        assert_eq!(apply(&steps, "settings.json", 1, old.clone()).unwrap(), old);
+    }
+}
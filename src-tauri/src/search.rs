@@ -0,0 +1,148 @@
+use serde::Serialize;
+
+use crate::ore_mappings::OreMappings;
+use crate::parser::MoonComposition;
+use crate::reactions::ReactionDatabase;
+
+/// Most results returned for a single query
+const MAX_RESULTS: usize = 50;
+
+/// Actions the command palette can run, as (command, title)
+const PALETTE_COMMANDS: &[(&str, &str)] = &[
+    ("analyze_reactions", "Analyze reactions"),
+    ("rank_moons", "Rank moons by value"),
+    ("list_analyses", "Show analysis history"),
+    ("compare_analyses", "Compare analyses"),
+    ("get_shopping_list", "Shopping list"),
+    ("export_multibuy", "Export multibuy"),
+    ("plan_runs", "Plan reaction runs"),
+    ("plan_steady_state", "Plan steady-state production"),
+    ("simulate_prices", "Simulate price changes"),
+    ("appraise_paste", "Appraise item paste"),
+    ("get_inventory", "Show inventory"),
+    ("import_inventory", "Import inventory"),
+    ("get_stockpile_valuation", "Stockpile valuation"),
+];
+
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Moon,
+    Reaction,
+    Item,
+    Command,
+}
+
+/// One ranked match
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResult {
+    pub kind: SearchKind,
+    pub label: String,
+    /// Secondary text, e.g. the output of a reaction
+    pub detail: Option<String>,
+    /// Moon index, formula ID or item ID; commands use `command` instead
+    pub id: Option<u32>,
+    pub command: Option<String>,
+    pub score: u32,
+}
+
+/// Score how well `text` matches a lowercase query; higher is better, None is no match
+fn match_score(text: &str, query: &str) -> Option<u32> {
+    let text = text.to_lowercase();
+    if text == query {
+        return Some(100);
+    }
+    if text.starts_with(query) {
+        return Some(80);
+    }
+    if text.split_whitespace().any(|word| word.starts_with(query)) {
+        return Some(60);
+    }
+    if text.contains(query) {
+        return Some(40);
+    }
+
+    // Every query character in order, e.g. "ftc" for "Fermionic Condensates"
+    let mut chars = text.chars();
+    query
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .all(|q| chars.any(|c| c == q))
+        .then_some(10)
+}
+
+/// Search moons, reactions, items and commands in one ranked list
+pub fn search_everything(
+    query: &str,
+    moons: &[MoonComposition],
+    reactions_db: &ReactionDatabase,
+    ore_mappings: &OreMappings,
+) -> Vec<SearchResult> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut results = Vec::new();
+    let mut push = |kind, label: &str, detail: Option<String>, id, command: Option<&str>| {
+        if let Some(score) = match_score(label, &query) {
+            results.push(SearchResult {
+                kind,
+                label: label.to_string(),
+                detail,
+                id,
+                command: command.map(str::to_string),
+                score,
+            });
+        }
+    };
+
+    for (index, moon) in moons.iter().enumerate() {
+        push(
+            SearchKind::Moon,
+            &moon.name,
+            Some(format!("{} ores", moon.materials.len())),
+            Some(index as u32),
+            None,
+        );
+    }
+
+    for reaction in &reactions_db.reactions {
+        push(
+            SearchKind::Reaction,
+            &reaction.formula_name,
+            Some(format!(
+                "{} x {}",
+                reaction.output.quantity, reaction.output.name
+            )),
+            Some(reaction.formula_id),
+            None,
+        );
+    }
+
+    let mut items: Vec<(&String, Option<u32>)> = reactions_db
+        .name_to_id
+        .iter()
+        .map(|(name, id)| (name, Some(*id)))
+        .collect();
+    items.extend(ore_mappings.ore_names().map(|name| (name, None)));
+    for (name, id) in items {
+        let detail = ore_mappings
+            .tier_of(name)
+            .map(|tier| format!("{:?} moon ore", tier));
+        push(SearchKind::Item, name, detail, id, None);
+    }
+
+    for (command, title) in PALETTE_COMMANDS {
+        push(SearchKind::Command, title, None, None, Some(command));
+    }
+
+    results.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then_with(|| a.label.len().cmp(&b.label.len()))
+            .then_with(|| a.label.cmp(&b.label))
+    });
+    results.truncate(MAX_RESULTS);
+    results
+}
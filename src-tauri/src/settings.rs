@@ -0,0 +1,109 @@
+use serde::{Deserialize, Serialize};
+
+use crate::fees::SaleOptions;
+use crate::prices::{MarketHub, PriceSource, PricingMode, RetryPolicy};
+use crate::storage;
+
+const SETTINGS_FILE: &str = "settings.json";
+
+/// User preferences that apply across the app
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// Trade hub prices are fetched for
+    pub market_hub: MarketHub,
+    /// Order book statistic used as an item's price
+    pub pricing_mode: PricingMode,
+    /// Player structure whose market prices are used, needs an ESI login
+    pub market_structure_id: Option<u64>,
+    /// Value each moon's goo in the market of the region the moon is in, where it trades,
+    /// both in moon valuations and as the cost of the user's own goo in reactions
+    pub regional_moon_pricing: bool,
+    /// Broker fee, sales tax and selling options used when a request doesn't give its own
+    pub sale_options: SaleOptions,
+    /// Send the anonymous launch ping
+    pub telemetry_enabled: bool,
+    /// How long fetched prices are reused before being fetched again
+    pub price_cache_ttl_secs: u64,
+    /// Refetch cached prices in the background during long sessions
+    pub auto_refresh_prices: bool,
+    /// Minutes between background price refreshes
+    pub price_refresh_mins: u64,
+    /// How failed price requests are retried
+    pub price_retry: RetryPolicy,
+    /// How long the announcement feed is reused before asking the server again
+    pub announcement_ttl_secs: u64,
+    /// Show a desktop notification before a tracked moon chunk arrives
+    pub extraction_alerts: bool,
+    /// How many hours before arrival the notification is shown
+    pub extraction_alert_hours: f64,
+    /// Key for creating Janice appraisals
+    pub janice_api_key: Option<String>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            market_hub: MarketHub::default(),
+            pricing_mode: PricingMode::default(),
+            market_structure_id: None,
+            regional_moon_pricing: false,
+            sale_options: SaleOptions::default(),
+            telemetry_enabled: true,
+            price_cache_ttl_secs: 60 * 60,
+            auto_refresh_prices: false,
+            price_refresh_mins: 30,
+            price_retry: RetryPolicy::default(),
+            announcement_ttl_secs: 6 * 60 * 60,
+            extraction_alerts: true,
+            extraction_alert_hours: 3.0,
+            janice_api_key: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(SETTINGS_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(SETTINGS_FILE), self)
+    }
+
+    /// Where prices should be fetched from
+    pub fn price_source(&self) -> PriceSource {
+        PriceSource {
+            market: self.market_hub,
+            mode: self.pricing_mode,
+            structure_id: self.market_structure_id,
+        }
+    }
+
+    /// Check that rates are percentages and amounts aren't negative
+    pub fn validate(&self) -> Result<(), String> {
+        let options = &self.sale_options;
+        for (name, percent) in [
+            ("Broker fee", options.broker_fee_percent),
+            ("Sales tax", options.sales_tax_percent),
+        ] {
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(format!("{} must be between 0 and 100%", name));
+            }
+        }
+        if options.relists_per_day < 0.0 || options.sale_duration_days < 0.0 {
+            return Err("Relists and sale duration can't be negative".to_string());
+        }
+        options.hauling.validate()?;
+        if !self.extraction_alert_hours.is_finite() || self.extraction_alert_hours < 0.0 {
+            return Err("Extraction alert lead time can't be negative".to_string());
+        }
+        if self.auto_refresh_prices && self.price_refresh_mins < 5 {
+            return Err("Prices can be refreshed at most every 5 minutes".to_string());
+        }
+        if self.price_retry.max_attempts == 0 {
+            return Err("Price requests need at least one attempt".to_string());
+        }
+        Ok(())
+    }
+}
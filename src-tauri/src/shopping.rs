@@ -0,0 +1,142 @@
+use serde::Serialize;
+use std::io::Write;
+
+use crate::planner::RunPlan;
+use crate::reaction_tree::SourceType;
+
+/// One raw input of a reaction chain, aggregated across the whole tree
+#[derive(Debug, Clone, Serialize)]
+pub struct ShoppingItem {
+    pub name: String,
+    pub id: u32,
+    /// Quantity still to acquire after using stock
+    pub quantity: u32,
+    /// Quantity covered by the user's inventory
+    pub from_stock: u32,
+    pub source: SourceType,
+    pub unit_price: f64,
+    pub total_price: f64,
+    /// Volume of one unit, in m3
+    pub unit_volume_m3: f64,
+}
+
+/// Everything that must be bought to run a reaction chain
+#[derive(Debug, Clone, Serialize)]
+pub struct ShoppingList {
+    pub output_name: String,
+    pub output_id: u32,
+    pub runs: u32,
+    pub items: Vec<ShoppingItem>,
+    pub total_cost: f64,
+}
+
+impl ShoppingList {
+    /// Format as EVE multibuy text ("Item Name quantity" per line)
+    pub fn to_multibuy(&self) -> String {
+        let mut text = Vec::new();
+        // Writing to a Vec can't fail
+        let _ = self.write_multibuy(&mut text);
+        String::from_utf8_lossy(&text).into_owned()
+    }
+
+    /// Write the multibuy text line by line, e.g. straight to a file
+    pub fn write_multibuy(&self, out: &mut impl Write) -> std::io::Result<()> {
+        for (index, item) in self.items.iter().enumerate() {
+            if index > 0 {
+                writeln!(out)?;
+            }
+            write!(out, "{} {}", item.name, item.quantity)?;
+        }
+        Ok(())
+    }
+}
+
+/// Every raw material a reaction chain consumes, however it's sourced
+#[derive(Debug, Clone, Serialize)]
+pub struct BillOfMaterials {
+    pub output_name: String,
+    pub output_id: u32,
+    pub runs: u32,
+    /// One line per item across the whole tree, bought, mined and from stock
+    pub items: Vec<ShoppingItem>,
+    /// Cost of what has to be bought
+    pub buy_cost: f64,
+    /// Sell value of the moon materials used
+    pub moon_cost: f64,
+    /// Everything at sell value, stock included
+    pub total_cost: f64,
+}
+
+/// Take every leaf of a run plan, already netted across branches
+pub fn build_bill_of_materials(
+    plan: &RunPlan,
+    output_name: &str,
+    output_id: u32,
+) -> BillOfMaterials {
+    let cost_of = |source: SourceType| {
+        plan.inputs
+            .iter()
+            .filter(|item| item.source == source)
+            .map(|item| item.total_price)
+            .sum()
+    };
+
+    BillOfMaterials {
+        output_name: output_name.to_string(),
+        output_id,
+        runs: plan.runs,
+        items: plan.inputs.clone(),
+        buy_cost: cost_of(SourceType::Buy),
+        moon_cost: cost_of(SourceType::Moon),
+        total_cost: plan.total_cost,
+    }
+}
+
+/// Take the items that must be bought from a run plan
+pub fn build_shopping_list(plan: &RunPlan, output_name: &str, output_id: u32) -> ShoppingList {
+    let items: Vec<ShoppingItem> = plan
+        .inputs
+        .iter()
+        .filter(|item| item.source == SourceType::Buy)
+        .cloned()
+        .collect();
+
+    ShoppingList {
+        output_name: output_name.to_string(),
+        output_id,
+        runs: plan.runs,
+        total_cost: items.iter().map(|i| i.total_price).sum(),
+        items,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(name: &str, quantity: u32) -> ShoppingItem {
+        ShoppingItem {
+            name: name.to_string(),
+            id: 0,
+            quantity,
+            from_stock: 0,
+            source: SourceType::Buy,
+            unit_price: 10.0,
+            total_price: 10.0 * quantity as f64,
+            unit_volume_m3: 0.05,
+        }
+    }
+
+    #[test]
+    fn test_multibuy_format() {
+        let list = ShoppingList {
+            output_name: "Fullerides".to_string(),
+            output_id: 16679,
+            runs: 1,
+            items: vec![item("Helium Fuel Block", 10), item("Silicates", 100)],
+            total_cost: 1100.0,
+        };
+
+        assert_eq!(list.to_multibuy(), "Helium Fuel Block 10\nSilicates 100");
+    }
+}
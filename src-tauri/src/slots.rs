@@ -0,0 +1,266 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::planner::FormulaRuns;
+use crate::reactions::{Reaction, ReactionDatabase};
+
+/// Most reaction slots one character can have, with every reaction skill at 5
+pub const MAX_SLOTS_PER_CHARACTER: u32 = 11;
+
+/// Most characters a schedule can be spread over
+pub const MAX_CHARACTERS: u32 = 100;
+
+/// A product and how much of it to make
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProductTarget {
+    pub output_id: u32,
+    pub quantity: u32,
+}
+
+/// One reaction job placed on a slot
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledJob {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub output_name: String,
+    pub runs: u32,
+    /// Character the job runs on, from 1
+    pub character: u32,
+    /// Slot on that character, from 1
+    pub slot: u32,
+    /// Seconds from the start of the schedule
+    pub start_secs: u64,
+    pub end_secs: u64,
+    /// Intermediates that must be finished before the job can start
+    pub waits_for: Vec<String>,
+}
+
+/// Reaction jobs for a product mix, in the order they should be started
+#[derive(Debug, Clone, Serialize)]
+pub struct JobSchedule {
+    pub jobs: Vec<ScheduledJob>,
+    pub slots: u32,
+    /// Time until the last job finishes
+    pub total_secs: u64,
+    /// Slot time left unused before the last job finishes
+    pub idle_secs: u64,
+}
+
+/// Check the slot counts and give the total
+pub fn slot_count(characters: u32, slots_per_character: u32) -> Result<u32, String> {
+    if characters == 0 || slots_per_character == 0 {
+        return Err("At least one character with one reaction slot is needed".to_string());
+    }
+    if slots_per_character > MAX_SLOTS_PER_CHARACTER {
+        return Err(format!(
+            "A character can run at most {} reactions",
+            MAX_SLOTS_PER_CHARACTER
+        ));
+    }
+    if characters > MAX_CHARACTERS {
+        return Err(format!(
+            "Reactions can be spread over at most {} characters",
+            MAX_CHARACTERS
+        ));
+    }
+    characters
+        .checked_mul(slots_per_character)
+        .ok_or_else(|| "Too many reaction slots".to_string())
+}
+
+/// Add up the runs of each formula across several plans
+pub fn merge_formula_runs(plans: impl IntoIterator<Item = Vec<FormulaRuns>>) -> Vec<FormulaRuns> {
+    let mut merged: Vec<FormulaRuns> = Vec::new();
+    for formulas in plans {
+        for formula in formulas {
+            match merged
+                .iter_mut()
+                .find(|f| f.formula_id == formula.formula_id)
+            {
+                Some(existing) => {
+                    existing.runs += formula.runs;
+                    existing.duration_seconds += formula.duration_seconds;
+                    existing.surplus += formula.surplus;
+                    existing.jobs += formula.jobs;
+                }
+                None => merged.push(formula),
+            }
+        }
+    }
+    merged
+}
+
+/// Place the jobs for the given formula runs on the slots. Intermediates are scheduled
+/// before what consumes them, and a job only starts once every job making one of its
+/// inputs has finished; each job goes on the slot where it can start soonest
+pub fn schedule_jobs(
+    formulas: &[FormulaRuns],
+    reactions_db: &ReactionDatabase,
+    characters: u32,
+    slots_per_character: u32,
+) -> Result<JobSchedule, String> {
+    let slots = slot_count(characters, slots_per_character)?;
+
+    let reactions: HashMap<u32, &Reaction> = formulas
+        .iter()
+        .filter_map(|f| {
+            reactions_db
+                .reactions
+                .iter()
+                .find(|r| r.formula_id == f.formula_id)
+                .map(|r| (f.formula_id, r))
+        })
+        .collect();
+    let made_by: HashMap<u32, u32> = reactions
+        .values()
+        .map(|r| (r.output.id, r.formula_id))
+        .collect();
+    // Formulas in the plan whose output a formula consumes
+    let dependencies = |reaction: &Reaction| -> Vec<u32> {
+        reaction
+            .inputs
+            .iter()
+            .filter_map(|input| made_by.get(&input.id).copied())
+            .collect()
+    };
+
+    let mut levels: HashMap<u32, usize> = HashMap::new();
+    for formula_id in reactions.keys() {
+        level(*formula_id, &reactions, &dependencies, &mut levels, 0);
+    }
+    let mut order: Vec<&FormulaRuns> = formulas
+        .iter()
+        .filter(|f| reactions.contains_key(&f.formula_id) && f.runs > 0)
+        .collect();
+    order.sort_by(|a, b| {
+        levels[&a.formula_id]
+            .cmp(&levels[&b.formula_id])
+            .then_with(|| a.formula_name.cmp(&b.formula_name))
+    });
+
+    let mut slot_free_at = vec![0u64; slots as usize];
+    let mut finished_at: HashMap<u32, u64> = HashMap::new();
+    let mut jobs = Vec::new();
+    for formula in order {
+        let reaction = reactions[&formula.formula_id];
+        let deps = dependencies(reaction);
+        let ready_at = deps
+            .iter()
+            .filter_map(|id| finished_at.get(id))
+            .max()
+            .copied()
+            .unwrap_or(0);
+        let waits_for: Vec<String> = deps
+            .iter()
+            .map(|id| reactions[id].output.name.clone())
+            .collect();
+
+        let max_runs = reaction.max_runs_per_job.max(1);
+        let mut runs_left = formula.runs;
+        while runs_left > 0 {
+            let runs = runs_left.min(max_runs);
+            runs_left -= runs;
+
+            let (index, start_secs) = slot_free_at
+                .iter()
+                .enumerate()
+                .map(|(index, free_at)| (index, (*free_at).max(ready_at)))
+                .min_by_key(|(index, start)| (*start, *index))
+                .unwrap_or((0, ready_at));
+            let end_secs = start_secs + runs as u64 * reaction.run_seconds as u64;
+            slot_free_at[index] = end_secs;
+
+            let finished = finished_at.entry(formula.formula_id).or_insert(0);
+            *finished = (*finished).max(end_secs);
+
+            jobs.push(ScheduledJob {
+                formula_id: formula.formula_id,
+                formula_name: formula.formula_name.clone(),
+                output_name: formula.output_name.clone(),
+                runs,
+                character: index as u32 / slots_per_character + 1,
+                slot: index as u32 % slots_per_character + 1,
+                start_secs,
+                end_secs,
+                waits_for: waits_for.clone(),
+            });
+        }
+    }
+
+    jobs.sort_by_key(|j| (j.start_secs, j.character, j.slot));
+    let total_secs = jobs.iter().map(|j| j.end_secs).max().unwrap_or(0);
+    let busy_secs: u64 = jobs.iter().map(|j| j.end_secs - j.start_secs).sum();
+    Ok(JobSchedule {
+        jobs,
+        slots,
+        total_secs,
+        idle_secs: total_secs * slots as u64 - busy_secs,
+    })
+}
+
+// How many formulas deep a formula's inputs go within the plan; 0 when it only uses raw
+// materials
+fn level(
+    formula_id: u32,
+    reactions: &HashMap<u32, &Reaction>,
+    dependencies: &impl Fn(&Reaction) -> Vec<u32>,
+    levels: &mut HashMap<u32, usize>,
+    depth: usize,
+) -> usize {
+    if let Some(level) = levels.get(&formula_id) {
+        return *level;
+    }
+    // Reaction data with a cycle would recurse forever
+    if depth > reactions.len() {
+        return 0;
+    }
+    let level = dependencies(reactions[&formula_id])
+        .into_iter()
+        .filter(|id| *id != formula_id)
+        .map(|id| level(id, reactions, dependencies, levels, depth + 1) + 1)
+        .max()
+        .unwrap_or(0);
+    levels.insert(formula_id, level);
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reactions::test_support::{self, item};
+
+    fn runs(formula_id: u32, output_name: &str, runs: u32) -> FormulaRuns {
+        FormulaRuns {
+            formula_id,
+            formula_name: format!("{} Reaction Formula", output_name),
+            output_name: output_name.to_string(),
+            runs,
+            jobs: 1,
+            duration_seconds: 0,
+            surplus: 0,
+        }
+    }
+
+    #[test]
+    fn test_intermediates_finish_before_their_consumers_start() {
+        let reaction = |formula_id, output, inputs| Reaction {
+            max_runs_per_job: 10,
+            ..test_support::reaction(formula_id, output, inputs)
+        };
+        let db = ReactionDatabase::from_reactions(vec![
+            reaction(1, item("Top", 10, 1), vec![item("Mid", 11, 1)]),
+            reaction(2, item("Mid", 11, 1), vec![item("Goo", 12, 1)]),
+        ]);
+
+        // 15 runs of Mid split into two jobs that run side by side
+        let schedule = schedule_jobs(&[runs(1, "Top", 2), runs(2, "Mid", 15)], &db, 1, 3).unwrap();
+
+        let mid: Vec<&ScheduledJob> = schedule.jobs.iter().filter(|j| j.formula_id == 2).collect();
+        assert_eq!(mid.len(), 2);
+        assert!(mid.iter().all(|j| j.start_secs == 0));
+        let top = schedule.jobs.iter().find(|j| j.formula_id == 1).unwrap();
+        assert_eq!(top.start_secs, 10 * 3600);
+        assert_eq!(top.waits_for, vec!["Mid".to_string()]);
+        assert_eq!(schedule.total_secs, 12 * 3600);
+    }
+}
@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::inventory::StockEntry;
+use crate::prices::{now_unix, PriceInfo};
+use crate::storage;
+
+const STOCKPILE_FILE: &str = "stockpile_history.json";
+/// Points closer together than this replace each other instead of piling up
+const MIN_POINT_SPACING_SECS: u64 = 60 * 60;
+/// Keep roughly a year of hourly points at most
+const MAX_POINTS: usize = 24 * 365;
+
+/// Value of one stockpiled item at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockpileItem {
+    pub name: String,
+    pub quantity: u64,
+    pub unit_price: f64,
+    pub value: f64,
+}
+
+/// Value of the whole goo stockpile at a point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StockpilePoint {
+    pub timestamp: u64,
+    pub total_value: f64,
+    pub items: Vec<StockpileItem>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StockpileHistory {
+    points: Vec<StockpilePoint>,
+}
+
+/// Stockpile value over time, with gains on current holdings since they were first priced
+#[derive(Debug, Clone, Serialize)]
+pub struct StockpileValuation {
+    pub series: Vec<(u64, f64)>,
+    pub current: Option<StockpilePoint>,
+    /// Value change of the current holdings versus each item's earliest recorded price
+    pub unrealized_gain: f64,
+    pub unrealized_gain_percent: f64,
+}
+
+/// One inventory item at current buy and sell prices
+#[derive(Debug, Clone, Serialize)]
+pub struct ValuedStock {
+    pub name: String,
+    pub quantity: u64,
+    pub buy_value: f64,
+    pub sell_value: f64,
+}
+
+/// Inventory items of one kind, e.g. raw goo or intermediates
+#[derive(Debug, Clone, Serialize)]
+pub struct StockCategory {
+    pub name: String,
+    pub items: Vec<ValuedStock>,
+    pub buy_total: f64,
+    pub sell_total: f64,
+}
+
+/// Value of everything in the inventory, by category
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryValuation {
+    pub categories: Vec<StockCategory>,
+    pub buy_total: f64,
+    pub sell_total: f64,
+    /// Items no price was found for
+    pub unpriced: Vec<String>,
+}
+
+/// Price every stocked item and total them by the category `category_of` names, highest
+/// sell value first
+pub fn value_inventory(
+    stock: &[StockEntry],
+    prices: &HashMap<String, PriceInfo>,
+    category_of: impl Fn(&str) -> String,
+) -> InventoryValuation {
+    let mut categories: Vec<StockCategory> = Vec::new();
+    let mut unpriced = Vec::new();
+    for entry in stock {
+        let Some(price) = prices.get(&entry.name) else {
+            unpriced.push(entry.name.clone());
+            continue;
+        };
+        let item = ValuedStock {
+            name: entry.name.clone(),
+            quantity: entry.quantity,
+            buy_value: price.buy * entry.quantity as f64,
+            sell_value: price.sell * entry.quantity as f64,
+        };
+
+        let name = category_of(&entry.name);
+        let index = match categories.iter().position(|c| c.name == name) {
+            Some(index) => index,
+            None => {
+                categories.push(StockCategory {
+                    name,
+                    items: Vec::new(),
+                    buy_total: 0.0,
+                    sell_total: 0.0,
+                });
+                categories.len() - 1
+            }
+        };
+        let category = &mut categories[index];
+        category.buy_total += item.buy_value;
+        category.sell_total += item.sell_value;
+        category.items.push(item);
+    }
+
+    categories.sort_by(|a, b| b.sell_total.total_cmp(&a.sell_total));
+    InventoryValuation {
+        buy_total: categories.iter().map(|c| c.buy_total).sum(),
+        sell_total: categories.iter().map(|c| c.sell_total).sum(),
+        categories,
+        unpriced,
+    }
+}
+
+impl StockpileHistory {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(STOCKPILE_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(STOCKPILE_FILE), self)
+    }
+
+    /// Record the current value of the stocked items
+    pub fn record(&mut self, stock: &[StockEntry], prices: &HashMap<String, PriceInfo>) {
+        let items: Vec<StockpileItem> = stock
+            .iter()
+            .filter_map(|entry| {
+                let unit_price = prices.get(&entry.name)?.sell;
+                Some(StockpileItem {
+                    name: entry.name.clone(),
+                    quantity: entry.quantity,
+                    unit_price,
+                    value: unit_price * entry.quantity as f64,
+                })
+            })
+            .collect();
+
+        let point = StockpilePoint {
+            timestamp: now_unix(),
+            total_value: items.iter().map(|i| i.value).sum(),
+            items,
+        };
+
+        match self.points.last_mut() {
+            Some(last)
+                if point.timestamp.saturating_sub(last.timestamp) < MIN_POINT_SPACING_SECS =>
+            {
+                *last = point
+            }
+            _ => self.points.push(point),
+        }
+        if self.points.len() > MAX_POINTS {
+            let excess = self.points.len() - MAX_POINTS;
+            self.points.drain(..excess);
+        }
+    }
+
+    /// Summarize the series and the unrealized gain on current holdings
+    pub fn valuation(&self) -> StockpileValuation {
+        let current = self.points.last().cloned();
+
+        // Earliest recorded price of every item
+        let mut first_prices: HashMap<&str, f64> = HashMap::new();
+        for point in &self.points {
+            for item in &point.items {
+                first_prices.entry(&item.name).or_insert(item.unit_price);
+            }
+        }
+
+        let (unrealized_gain, cost_basis) = current
+            .as_ref()
+            .map(|point| {
+                point.items.iter().fold((0.0, 0.0), |(gain, basis), item| {
+                    let first = first_prices
+                        .get(item.name.as_str())
+                        .copied()
+                        .unwrap_or(item.unit_price);
+                    let item_basis = first * item.quantity as f64;
+                    (gain + item.value - item_basis, basis + item_basis)
+                })
+            })
+            .unwrap_or((0.0, 0.0));
+
+        StockpileValuation {
+            series: self
+                .points
+                .iter()
+                .map(|p| (p.timestamp, p.total_value))
+                .collect(),
+            current,
+            unrealized_gain,
+            unrealized_gain_percent: if cost_basis > 0.0 {
+                unrealized_gain / cost_basis * 100.0
+            } else {
+                0.0
+            },
+        }
+    }
+}
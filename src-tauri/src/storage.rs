@@ -0,0 +1,148 @@
+use flate2::read::GzDecoder;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::schema;
+
+/// Profile whose files `profile_file` points at; empty until a profile is chosen
+static ACTIVE_PROFILE: RwLock<String> = RwLock::new(String::new());
+
+/// Profile used when none has been created or chosen
+pub const DEFAULT_PROFILE: &str = "Default";
+
+/// Directory where all persisted app data lives
+pub fn data_dir() -> Option<PathBuf> {
+    dirs::data_local_dir().map(|p| p.join("moon-calculator"))
+}
+
+/// Point per-profile files at another profile
+pub fn set_active_profile(name: &str) {
+    if let Ok(mut active) = ACTIVE_PROFILE.write() {
+        *active = name.to_string();
+    }
+}
+
+/// Name, relative to the app data directory, of a file belonging to the active profile
+pub fn profile_file(file_name: &str) -> String {
+    let active = ACTIVE_PROFILE
+        .read()
+        .map(|name| name.clone())
+        .unwrap_or_default();
+    let profile = if active.is_empty() {
+        DEFAULT_PROFILE
+    } else {
+        active.as_str()
+    };
+    format!("profiles/{}/{}", profile, file_name)
+}
+
+/// Path of a file inside the app data directory
+pub fn data_file(file_name: &str) -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join(file_name))
+}
+
+/// Read a text file from the app data directory, if it exists
+pub fn load_text(file_name: &str) -> Option<String> {
+    fs::read_to_string(data_file(file_name)?).ok()
+}
+
+/// Load a JSON file from the app data directory, if it exists and parses. Files saved at
+/// an older schema version are upgraded first
+pub fn load_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let stored: Value = serde_json::from_str(&load_text(file_name)?).ok()?;
+    match schema::upgrade(file_name, stored) {
+        Ok(data) => serde_json::from_value(data).ok(),
+        Err(e) => {
+            tracing::warn!(error = %e, "Not loading file");
+            None
+        }
+    }
+}
+
+/// Write a value as JSON into the app data directory, stamped with the schema version.
+/// A file saved by a newer version of the app is never overwritten
+pub fn save_json<T: Serialize>(file_name: &str, value: &T) -> Result<(), String> {
+    let stored_version = load_text(file_name)
+        .and_then(|text| serde_json::from_str::<Value>(&text).ok())
+        .map(|stored| schema::stored_version(&stored))
+        .unwrap_or(0);
+    if stored_version > schema::SCHEMA_VERSION {
+        return Err(format!(
+            "{} was saved by a newer version of the app; update the app to change it",
+            file_name
+        ));
+    }
+
+    let data = serde_json::to_value(value)
+        .map_err(|e| format!("Failed to serialize {}: {}", file_name, e))?;
+    let json = serde_json::to_string_pretty(&schema::wrap(data))
+        .map_err(|e| format!("Failed to serialize {}: {}", file_name, e))?;
+    save_text(file_name, &json)
+}
+
+/// Write a text file into the app data directory
+pub fn save_text(file_name: &str, contents: &str) -> Result<(), String> {
+    let path = data_file(file_name).ok_or("Could not determine app data directory")?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create data directory: {}", e))?;
+    }
+
+    // Write to a temporary file first so a crash can't leave a half-written file behind
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, contents).map_err(|e| format!("Failed to write {}: {}", file_name, e))?;
+    fs::rename(&tmp_path, &path).map_err(|e| format!("Failed to save {}: {}", file_name, e))
+}
+
+/// Delete everything in the app data directory except the named entries. Returns how many
+/// files and folders were deleted
+pub fn remove_all_except(keep: &[&str]) -> Result<usize, String> {
+    let Some(dir) = data_dir() else {
+        return Ok(0);
+    };
+    let Ok(entries) = fs::read_dir(&dir) else {
+        return Ok(0);
+    };
+
+    let mut removed = 0;
+    for entry in entries.flatten() {
+        if keep.iter().any(|name| entry.file_name() == *name) {
+            continue;
+        }
+        let path = entry.path();
+        let result = if path.is_dir() {
+            fs::remove_dir_all(&path)
+        } else {
+            fs::remove_file(&path)
+        };
+        result.map_err(|e| format!("Failed to delete {}: {}", path.display(), e))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Decompress a gzip file, giving up once it inflates past `max_bytes` so a small crafted
+/// file (such as a shared moon pack) can't use up memory
+pub fn read_gzip(path: &Path, max_bytes: u64) -> Result<Vec<u8>, String> {
+    let file =
+        fs::File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut contents = Vec::new();
+    GzDecoder::new(std::io::BufReader::new(file))
+        .take(max_bytes + 1)
+        .read_to_end(&mut contents)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if contents.len() as u64 > max_bytes {
+        return Err(format!(
+            "{} is larger than {} MB uncompressed",
+            path.display(),
+            max_bytes / 1_000_000
+        ));
+    }
+    Ok(contents)
+}
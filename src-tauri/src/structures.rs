@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::bonuses::{BonusScenario, Refinery, RigTier, SecurityBand};
+use crate::schedule::UpcomingExtraction;
+use crate::storage;
+use crate::valuation::REPROCESSING_EFFICIENCY;
+
+const STRUCTURES_FILE: &str = "structures.json";
+
+/// Structure reprocessing yield before rigs, structure and skills
+const BASE_REPROCESSING_YIELD: f64 = 0.50;
+/// Reprocessing, Reprocessing Efficiency and moon ore processing skills at level 5
+const REPROCESSING_SKILLS_MULTIPLIER: f64 = 1.15 * 1.10 * 1.10;
+
+/// A refinery anchored on a moon, with the fit that decides its bonuses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoonStructure {
+    pub moon: String,
+    pub name: String,
+    pub refinery: Refinery,
+    pub security: SecurityBand,
+    /// Reaction rig (Standup M-Set Composite/Hybrid/Biochemical Reactor)
+    pub reaction_rig: RigTier,
+    /// Moon ore reprocessing rig (Standup M-Set Moon Ore Grading Processor)
+    pub reprocessing_rig: RigTier,
+}
+
+impl MoonStructure {
+    /// Reaction bonuses for jobs run in this structure, with reactions trained to 5
+    pub fn bonus_scenario(&self) -> BonusScenario {
+        BonusScenario {
+            name: self.name.clone(),
+            rig: self.reaction_rig,
+            security: self.security,
+            refinery: self.refinery,
+            ..BonusScenario::default()
+        }
+    }
+
+    /// Fraction of the listed goo yield recovered when reprocessing moon ore here, with
+    /// reprocessing skills trained to 5
+    pub fn reprocessing_efficiency(&self) -> f64 {
+        let rig = match self.reprocessing_rig {
+            RigTier::None => 0.0,
+            RigTier::T1 => 0.01,
+            RigTier::T2 => 0.03,
+        };
+        let security = match self.security {
+            SecurityBand::Lowsec => 1.06,
+            SecurityBand::Nullsec | SecurityBand::Wormhole => 1.12,
+        };
+        let structure = match self.refinery {
+            Refinery::Athanor => 1.02,
+            Refinery::Tatara => 1.055,
+        };
+        (BASE_REPROCESSING_YIELD + rig) * security * structure * REPROCESSING_SKILLS_MULTIPLIER
+    }
+}
+
+/// Extraction timers for the moons under one structure
+#[derive(Debug, Clone, Serialize)]
+pub struct StructureExtractions {
+    /// None for moons without a recorded structure
+    pub structure: Option<String>,
+    pub extractions: Vec<UpcomingExtraction>,
+}
+
+/// Structures recorded for the active profile's moons, by moon name
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MoonStructures {
+    structures: HashMap<String, MoonStructure>,
+}
+
+impl MoonStructures {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(STRUCTURES_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(STRUCTURES_FILE), self)
+    }
+
+    /// Record the structure on a moon, replacing any recorded before
+    pub fn set(&mut self, mut structure: MoonStructure) -> Result<(), String> {
+        structure.moon = structure.moon.trim().to_string();
+        structure.name = structure.name.trim().to_string();
+        if structure.moon.is_empty() || structure.name.is_empty() {
+            return Err("Moon and structure names are required".to_string());
+        }
+        self.structures.insert(structure.moon.clone(), structure);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, moon: &str) {
+        self.structures.remove(moon.trim());
+    }
+
+    pub fn get(&self, moon: &str) -> Option<&MoonStructure> {
+        self.structures.get(moon)
+    }
+
+    /// Structures sorted by name, then moon
+    pub fn list(&self) -> Vec<MoonStructure> {
+        let mut structures: Vec<MoonStructure> = self.structures.values().cloned().collect();
+        structures.sort_by(|a, b| a.name.cmp(&b.name).then_with(|| a.moon.cmp(&b.moon)));
+        structures
+    }
+
+    /// Reprocessing efficiency for a moon's ore, the default where no structure is recorded
+    pub fn reprocessing_efficiency(&self, moon: &str) -> f64 {
+        self.get(moon)
+            .map(MoonStructure::reprocessing_efficiency)
+            .unwrap_or(REPROCESSING_EFFICIENCY)
+    }
+
+    /// One bonus scenario per distinct structure
+    pub fn scenarios(&self) -> Vec<BonusScenario> {
+        let mut structures = self.list();
+        structures.dedup_by(|a, b| a.name == b.name);
+        structures
+            .iter()
+            .map(MoonStructure::bonus_scenario)
+            .collect()
+    }
+
+    /// Group extraction timers by the structure on each moon, soonest structure first
+    pub fn group_extractions(
+        &self,
+        extractions: Vec<UpcomingExtraction>,
+    ) -> Vec<StructureExtractions> {
+        let mut groups: Vec<StructureExtractions> = Vec::new();
+        for extraction in extractions {
+            let structure = self.get(&extraction.moon).map(|s| s.name.clone());
+            match groups.iter_mut().find(|g| g.structure == structure) {
+                Some(group) => group.extractions.push(extraction),
+                None => groups.push(StructureExtractions {
+                    structure,
+                    extractions: vec![extraction],
+                }),
+            }
+        }
+        groups
+    }
+}
@@ -0,0 +1,123 @@
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::fees::{self, SaleOptions};
+use crate::ore_mappings::is_moon_goo;
+use crate::reaction_tree::{self, ReactionTreeNode, SourceType, TreeContext};
+
+/// A chain held back by a single goo the user's moons don't produce
+#[derive(Debug, Clone, Serialize)]
+pub struct MaxBuyHint {
+    pub formula_id: u32,
+    pub formula_name: String,
+    pub goo_name: String,
+    pub goo_id: u32,
+    /// Goo needed for one run of the chain
+    pub quantity: u32,
+    pub market_price: f64,
+    /// Highest unit price that still leaves the target margin
+    pub max_price: f64,
+    /// Profit of one run when buying the goo at market price
+    pub profit_at_market: f64,
+}
+
+/// A raw material of a chain, totalled across the tree
+struct Leaf {
+    name: String,
+    source: SourceType,
+    quantity: u32,
+    unit_price: f64,
+}
+
+/// Sum the leaves of a tree, keyed by item ID
+fn collect_leaves(node: &ReactionTreeNode, leaves: &mut HashMap<u32, Leaf>) {
+    if node.children.is_empty() {
+        let leaf = leaves.entry(node.id).or_insert(Leaf {
+            name: node.name.clone(),
+            source: node.source.clone(),
+            quantity: 0,
+            unit_price: node.unit_price,
+        });
+        leaf.quantity += node.quantity;
+        return;
+    }
+    for child in &node.children {
+        collect_leaves(child, leaves);
+    }
+}
+
+/// For every chain that uses the user's moon goo but needs exactly one other goo from the
+/// market, work out the most that goo could cost while keeping `target_margin` percent
+pub fn max_buy_prices(
+    ctx: &TreeContext,
+    target_margin: f64,
+    sale_options: &SaleOptions,
+) -> Vec<MaxBuyHint> {
+    let mut hints = Vec::new();
+
+    for reaction in &ctx.reactions_db.reactions {
+        let Some(output_price) = ctx.prices.get(&reaction.output.name) else {
+            continue;
+        };
+        let Ok(tree) = reaction_tree::build_full_reaction_tree(
+            &reaction.output.name,
+            reaction.output.id,
+            reaction.output.quantity,
+            1,
+            ctx,
+        ) else {
+            continue;
+        };
+
+        let mut leaves = HashMap::new();
+        collect_leaves(&tree, &mut leaves);
+
+        let uses_moons = leaves.values().any(|leaf| leaf.source == SourceType::Moon);
+        let missing_goo: Vec<(&u32, &Leaf)> = leaves
+            .iter()
+            .filter(|(_, leaf)| leaf.source == SourceType::Buy && is_moon_goo(&leaf.name))
+            .collect();
+        let [(&goo_id, goo)] = missing_goo[..] else {
+            continue;
+        };
+        if !uses_moons || goo.quantity == 0 {
+            continue;
+        }
+
+        // Everything else is valued at market, the same way reaction profits are
+        let other_cost: f64 = leaves
+            .iter()
+            .filter(|(id, _)| **id != goo_id)
+            .map(|(_, leaf)| leaf.quantity as f64 * leaf.unit_price)
+            .sum();
+        let net_output =
+            fees::value_output(output_price, reaction.output.quantity, sale_options).net_value;
+
+        // profit >= margin * cost  =>  net - other - q*p >= m * (other + q*p)
+        let quantity = goo.quantity as f64;
+        let max_price = (net_output / (1.0 + target_margin / 100.0) - other_cost) / quantity;
+        if max_price <= 0.0 {
+            continue;
+        }
+
+        hints.push(MaxBuyHint {
+            formula_id: reaction.formula_id,
+            formula_name: reaction.formula_name.clone(),
+            goo_name: goo.name.clone(),
+            goo_id,
+            quantity: goo.quantity,
+            market_price: goo.unit_price,
+            max_price,
+            profit_at_market: net_output - other_cost - quantity * goo.unit_price,
+        });
+    }
+
+    // Most room between market and max price first
+    hints.sort_by(|a, b| {
+        let headroom = |h: &MaxBuyHint| h.max_price / h.market_price.max(f64::EPSILON);
+        headroom(b)
+            .partial_cmp(&headroom(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    hints
+}
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Serialize};
+
+use crate::parser::MoonComposition;
+use crate::prices::now_unix;
+use crate::storage;
+
+/// Holds the shared secret, so it is left out of backups unless asked for
+pub const CONFIG_FILE: &str = "sync.json";
+/// Moons as of the last successful sync, the base both sides' changes are measured from
+const BASE_FILE: &str = "sync_base.json";
+/// Tries before giving up when the remote copy keeps changing under us
+const MAX_ATTEMPTS: u32 = 3;
+
+/// Which copy wins when a moon was changed both here and remotely since the last sync
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    #[default]
+    Local,
+    Remote,
+}
+
+/// Where the moon database is synced to. Any server that serves GET and PUT on a URL, with
+/// ETags and conditional PUTs, works: a WebDAV share, an HTTP backend or an S3 object URL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SyncConfig {
+    /// Full URL of the synced file, e.g. https://dav.example.com/moon/moons.json
+    pub url: String,
+    /// Basic auth user, for WebDAV
+    pub username: Option<String>,
+    /// Basic auth password, or a bearer token when there's no user name
+    pub secret: Option<String>,
+    pub conflict_policy: ConflictPolicy,
+    /// Unix timestamp (seconds) of the last successful sync
+    pub last_synced_at: Option<u64>,
+}
+
+impl SyncConfig {
+    pub fn load() -> Self {
+        storage::load_json(&storage::profile_file(CONFIG_FILE)).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(&storage::profile_file(CONFIG_FILE), self)
+    }
+
+    fn request(
+        &self,
+        client: &reqwest::Client,
+        method: reqwest::Method,
+    ) -> reqwest::RequestBuilder {
+        let request = client
+            .request(method, &self.url)
+            .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+            .timeout(std::time::Duration::from_secs(30));
+        match (&self.username, &self.secret) {
+            (Some(user), secret) => request.basic_auth(user, secret.as_ref()),
+            (None, Some(token)) => request.bearer_auth(token),
+            (None, None) => request,
+        }
+    }
+}
+
+/// The file stored at the sync URL
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct SyncDocument {
+    /// Unix timestamp (seconds) of the last push
+    updated_at: u64,
+    moons: Vec<MoonComposition>,
+}
+
+/// Which copy of the remote file a merge was based on, so the push only replaces that copy
+enum RemoteVersion {
+    /// Nothing was synced yet; the push must not replace a file created in the meantime
+    Missing,
+    /// The ETag the file was served with
+    Tagged(String),
+}
+
+/// What a sync changed
+#[derive(Debug, Clone, Serialize)]
+pub struct SyncReport {
+    /// Moons added, changed or removed here from the remote copy
+    pub pulled: Vec<String>,
+    /// Moons changed both here and remotely, resolved by the conflict policy
+    pub conflicts: Vec<String>,
+    pub synced_at: u64,
+}
+
+/// Pull the remote copy, merge it with the local moons and push the result. Changes are
+/// found by comparing each side with the moons as of the last sync, so edits and removals
+/// on either side carry over; a moon changed on both sides is settled by the policy
+pub async fn sync_moons(
+    config: &SyncConfig,
+    local: &[MoonComposition],
+) -> Result<(Vec<MoonComposition>, SyncReport), String> {
+    if config.url.trim().is_empty() {
+        return Err("Set a sync URL first".to_string());
+    }
+    let base: Vec<MoonComposition> =
+        storage::load_json(&storage::profile_file(BASE_FILE)).unwrap_or_default();
+    let client = reqwest::Client::new();
+
+    for _ in 0..MAX_ATTEMPTS {
+        let (remote, version) = pull(config, &client).await?;
+        let (merged, report) = merge(&base, local, &remote.moons, config.conflict_policy);
+
+        let document = SyncDocument {
+            updated_at: report.synced_at,
+            moons: merged,
+        };
+        if push(config, &client, &document, &version).await? {
+            storage::save_json(&storage::profile_file(BASE_FILE), &document.moons)?;
+            return Ok((document.moons, report));
+        }
+        // The remote copy changed since we pulled it; merge again with the new copy
+    }
+    Err("The remote copy kept changing during sync, try again later".to_string())
+}
+
+/// Download the remote copy and its version. Servers that don't send an ETag are refused:
+/// without one a push can't tell whether another device pushed first, and would overwrite
+/// its changes
+async fn pull(
+    config: &SyncConfig,
+    client: &reqwest::Client,
+) -> Result<(SyncDocument, RemoteVersion), String> {
+    let response = config
+        .request(client, reqwest::Method::GET)
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach sync server: {}", e))?;
+
+    // Nothing synced yet
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok((SyncDocument::default(), RemoteVersion::Missing));
+    }
+    if !response.status().is_success() {
+        return Err(format!(
+            "Sync server returned status: {}",
+            response.status()
+        ));
+    }
+
+    let etag = response
+        .headers()
+        .get(reqwest::header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or(
+            "The sync server doesn't send ETags, so syncing could overwrite other devices' \
+             changes. Use a server that does, such as WebDAV or S3",
+        )?;
+    let document = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse synced moons: {}", e))?;
+    Ok((document, RemoteVersion::Tagged(etag)))
+}
+
+/// Upload the merged moons, only if the remote copy is still the one pulled. Returns false
+/// when it changed in the meantime
+async fn push(
+    config: &SyncConfig,
+    client: &reqwest::Client,
+    document: &SyncDocument,
+    version: &RemoteVersion,
+) -> Result<bool, String> {
+    let request = config.request(client, reqwest::Method::PUT).json(document);
+    let request = match version {
+        RemoteVersion::Missing => request.header(reqwest::header::IF_NONE_MATCH, "*"),
+        RemoteVersion::Tagged(etag) => request.header(reqwest::header::IF_MATCH, etag),
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach sync server: {}", e))?;
+
+    if response.status() == reqwest::StatusCode::PRECONDITION_FAILED {
+        return Ok(false);
+    }
+    if !response.status().is_success() {
+        return Err(format!(
+            "Sync server returned status: {}",
+            response.status()
+        ));
+    }
+    Ok(true)
+}
+
+/// Carry moons edited locally while a sync ran over to the synced list. `snapshot` is the
+/// local list the sync started from; local edits since then win
+pub fn keep_local_edits(
+    snapshot: &[MoonComposition],
+    current: &[MoonComposition],
+    synced: &[MoonComposition],
+) -> Vec<MoonComposition> {
+    merge(snapshot, current, synced, ConflictPolicy::Local).0
+}
+
+/// Three-way merge of moon lists, matching moons by celestial IDs (or by name when a scan
+/// has none), so a moon renamed on one device is still the same moon
+fn merge(
+    base: &[MoonComposition],
+    local: &[MoonComposition],
+    remote: &[MoonComposition],
+    policy: ConflictPolicy,
+) -> (Vec<MoonComposition>, SyncReport) {
+    let find = |moons: &[MoonComposition], key: &MoonComposition| -> Option<MoonComposition> {
+        moons.iter().find(|m| m.is_same_moon(key)).cloned()
+    };
+
+    // Local order first, then moons only the remote has
+    let mut keys: Vec<&MoonComposition> = Vec::new();
+    for moon in local.iter().chain(remote).chain(base) {
+        if !keys.iter().any(|key| key.is_same_moon(moon)) {
+            keys.push(moon);
+        }
+    }
+
+    let mut merged = Vec::new();
+    let mut pulled = Vec::new();
+    let mut conflicts = Vec::new();
+    for key in keys {
+        let name = key.name.as_str();
+        let (base, local, remote) = (find(base, key), find(local, key), find(remote, key));
+        let local_changed = local != base;
+        let remote_changed = remote != base;
+
+        let kept = match (local_changed, remote_changed) {
+            (_, false) => local,
+            (false, true) => {
+                pulled.push(name.to_string());
+                remote
+            }
+            (true, true) if local == remote => local,
+            (true, true) => {
+                conflicts.push(name.to_string());
+                match policy {
+                    ConflictPolicy::Local => local,
+                    ConflictPolicy::Remote => {
+                        pulled.push(name.to_string());
+                        remote
+                    }
+                }
+            }
+        };
+        merged.extend(kept);
+    }
+
+    (
+        merged,
+        SyncReport {
+            pulled,
+            conflicts,
+            synced_at: now_unix(),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moon(name: &str, notes: &str) -> MoonComposition {
+        MoonComposition {
+            name: name.to_string(),
+            materials: Vec::new(),
+            tags: Vec::new(),
+            notes: notes.to_string(),
+            location: None,
+            jumps_to_market: None,
+            highest_tier: None,
+        }
+    }
+
+    #[test]
+    fn test_merge_keeps_changes_from_both_sides() {
+        let base = vec![moon("A", ""), moon("B", ""), moon("C", "")];
+        // Local edited A and removed B; remote edited C and added D
+        let local = vec![moon("A", "local"), moon("C", "")];
+        let remote = vec![
+            moon("A", ""),
+            moon("B", ""),
+            moon("C", "remote"),
+            moon("D", ""),
+        ];
+
+        let (merged, report) = merge(&base, &local, &remote, ConflictPolicy::Local);
+
+        assert_eq!(
+            merged,
+            vec![moon("A", "local"), moon("C", "remote"), moon("D", "")]
+        );
+        assert_eq!(report.pulled, vec!["C".to_string(), "D".to_string()]);
+        assert!(report.conflicts.is_empty());
+    }
+
+    #[test]
+    fn test_merge_matches_renamed_moons_by_celestial_ids() {
+        let scanned = |name: &str| MoonComposition {
+            materials: vec![crate::parser::MaterialEntry {
+                name: "Cobaltite".to_string(),
+                quantity: 0.5,
+                item_id: 45494,
+                system_id: 30000142,
+                region_id: 40009077,
+                additional_id: 40009082,
+                tier: None,
+                extracted_units: None,
+            }],
+            ..moon(name, "")
+        };
+        let base = vec![scanned("Jita IV - Moon 4")];
+        let remote = vec![scanned("Home moon")];
+
+        let (merged, report) = merge(&base, &base, &remote, ConflictPolicy::Local);
+
+        assert_eq!(merged, remote);
+        assert!(report.conflicts.is_empty());
+    }
+}
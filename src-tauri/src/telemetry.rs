@@ -1,54 +1,155 @@
-use std::fs;
-use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::crash::{self, CrashReport};
+use crate::storage;
+
 const TELEMETRY_ENDPOINT: &str = "https://telemetry.illuminatedcorp.com/ping";
+const CRASH_ENDPOINT: &str = "https://telemetry.illuminatedcorp.com/crash";
 const TELEMETRY_TOKEN: Option<&str> = option_env!("MOON_TELEMETRY_TOKEN");
+const TELEMETRY_FILE: &str = "telemetry.json";
+/// Shown in place of the device ID before one exists; it is only created once the user
+/// agrees to telemetry
+const DEVICE_ID_PLACEHOLDER: &str = "(random ID, created if you allow telemetry)";
 
-fn get_device_id_path() -> Option<PathBuf> {
-    dirs::data_local_dir().map(|p| p.join("moon-calculator").join("device_id"))
+/// Persisted telemetry state
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+struct TelemetryState {
+    device_id: String,
+    /// Whether the user agreed to telemetry; None until they've been asked
+    consent: Option<bool>,
+    /// Unix timestamp (seconds) of the consent decision
+    decided_at: Option<u64>,
 }
 
-fn get_or_create_device_id() -> Option<String> {
-    let path = get_device_id_path()?;
+impl TelemetryState {
+    fn load() -> Self {
+        storage::load_json(TELEMETRY_FILE).unwrap_or_default()
+    }
 
-    // Try to read existing ID
-    if let Ok(id) = fs::read_to_string(&path) {
-        let id = id.trim().to_string();
-        if !id.is_empty() {
-            return Some(id);
+    fn save(&self) -> Result<(), String> {
+        storage::save_json(TELEMETRY_FILE, self)
+    }
+}
+
+/// The user's telemetry decision, for the first-run prompt and settings
+#[derive(Debug, Clone, Serialize)]
+pub struct TelemetryConsent {
+    /// Whether the user has been asked and answered
+    pub decided: bool,
+    pub granted: bool,
+    pub decided_at: Option<u64>,
+    /// Whether this build sends anything at all
+    pub available: bool,
+}
+
+/// Exactly what the launch ping sends
+#[derive(Debug, Clone, Serialize)]
+pub struct LaunchPayload {
+    pub device_id: String,
+    pub version: String,
+    pub os: String,
+}
+
+/// Forget the anonymous device ID, and with it the telemetry decision, so a new ID is
+/// created and the user is asked again
+pub fn reset_device_id() -> Result<(), String> {
+    let Some(path) = storage::data_file(TELEMETRY_FILE) else {
+        return Ok(());
+    };
+    match std::fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(format!("Failed to delete {}: {}", path.display(), e))
         }
+        _ => Ok(()),
     }
+}
 
-    // Create new ID
-    let id = Uuid::new_v4().to_string();
+/// Store the anonymous device ID
+pub fn save_device_id(device_id: &str) -> Result<(), String> {
+    let mut state = TelemetryState::load();
+    state.device_id = device_id.to_string();
+    state.save()
+}
 
-    // Ensure parent directory exists
-    if let Some(parent) = path.parent() {
-        let _ = fs::create_dir_all(parent);
+fn get_or_create_device_id() -> Option<String> {
+    // Try to read existing ID
+    let state = TelemetryState::load();
+    if !state.device_id.is_empty() {
+        return Some(state.device_id);
     }
 
-    // Save ID
-    let _ = fs::write(&path, &id);
+    // Create and save a new ID
+    let id = Uuid::new_v4().to_string();
+    save_device_id(&id).ok()?;
 
     Some(id)
 }
 
+pub fn consent() -> TelemetryConsent {
+    let state = TelemetryState::load();
+    TelemetryConsent {
+        decided: state.consent.is_some(),
+        granted: state.consent == Some(true),
+        decided_at: state.decided_at,
+        available: TELEMETRY_TOKEN.is_some(),
+    }
+}
+
+/// Record the user's answer to the telemetry prompt, creating the device ID if they agreed
+pub fn set_consent(granted: bool) -> Result<TelemetryConsent, String> {
+    let mut state = TelemetryState::load();
+    if granted && state.device_id.is_empty() {
+        state.device_id = Uuid::new_v4().to_string();
+    }
+    state.consent = Some(granted);
+    state.decided_at = Some(crate::prices::now_unix());
+    state.save()?;
+    Ok(consent())
+}
+
+/// The launch ping as it would be sent, for showing to the user. Nothing is stored: until
+/// there is a device ID a placeholder stands in for it
+pub fn preview_payload() -> LaunchPayload {
+    let state = TelemetryState::load();
+    let device_id = if state.device_id.is_empty() {
+        DEVICE_ID_PLACEHOLDER.to_string()
+    } else {
+        state.device_id
+    };
+    LaunchPayload {
+        device_id,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        os: std::env::consts::OS.to_string(),
+    }
+}
+
+/// The launch ping to send, creating the device ID if there is none. Only called once the
+/// user has agreed to telemetry
+fn launch_payload() -> Result<LaunchPayload, String> {
+    Ok(LaunchPayload {
+        device_id: get_or_create_device_id().ok_or("Failed to store the device ID")?,
+        ..preview_payload()
+    })
+}
+
+/// Send the launch ping in the background, only once the user has agreed to telemetry
 pub fn send_launch_ping() {
     // Skip if no token configured
     let token = match TELEMETRY_TOKEN {
         Some(t) => t.to_string(),
         None => return,
     };
+    if !consent().granted {
+        return;
+    }
 
-    let device_id = match get_or_create_device_id() {
-        Some(id) => id,
-        None => return,
+    let payload = match launch_payload() {
+        Ok(payload) => payload,
+        Err(_) => return,
     };
 
-    let version = env!("CARGO_PKG_VERSION").to_string();
-    let os = std::env::consts::OS.to_string();
-
     // Fire and forget in a separate thread
     std::thread::spawn(move || {
         let rt = tokio::runtime::Builder::new_current_thread()
@@ -61,11 +162,7 @@ pub fn send_launch_ping() {
                 client
                     .post(TELEMETRY_ENDPOINT)
                     .header("Authorization", format!("Bearer {}", token))
-                    .json(&serde_json::json!({
-                        "device_id": device_id,
-                        "version": version,
-                        "os": os
-                    }))
+                    .json(&payload)
                     .timeout(std::time::Duration::from_secs(5))
                     .send()
                     .await
@@ -73,3 +170,44 @@ pub fn send_launch_ping() {
         }
     });
 }
+
+/// Send crash reports in the background, marking each one as submitted once accepted. Only
+/// sent once the user has agreed to telemetry
+pub fn send_crash_reports(reports: Vec<CrashReport>) {
+    let Some(token) = TELEMETRY_TOKEN.map(str::to_string) else {
+        return;
+    };
+    if reports.is_empty() || !consent().granted {
+        return;
+    }
+    let device_id = get_or_create_device_id();
+
+    std::thread::spawn(move || {
+        let Ok(rt) = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+        else {
+            return;
+        };
+
+        rt.block_on(async {
+            let client = reqwest::Client::new();
+            for report in reports {
+                let sent = client
+                    .post(CRASH_ENDPOINT)
+                    .header("Authorization", format!("Bearer {}", token))
+                    .json(&serde_json::json!({
+                        "device_id": device_id,
+                        "report": report
+                    }))
+                    .timeout(std::time::Duration::from_secs(10))
+                    .send()
+                    .await
+                    .is_ok_and(|response| response.status().is_success());
+                if sent {
+                    let _ = crash::mark_submitted(&report);
+                }
+            }
+        });
+    });
+}
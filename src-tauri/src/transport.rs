@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+/// Jump freighter hold with Jump Freighters trained to 5
+const DEFAULT_JUMP_FREIGHTER_CARGO_M3: f64 = 360_000.0;
+
+/// What moving materials between the market and the refinery costs
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "model", rename_all = "snake_case")]
+pub enum HaulingRate {
+    /// Reactions run where the materials are bought and sold
+    #[default]
+    None,
+    /// A freight service charging by volume
+    PerM3 { isk_per_m3: f64 },
+    /// Hauling yourself in a jump freighter: isotopes burned over the route, spread over a
+    /// full hold. Count the jumps both ways if the freighter flies back empty
+    JumpFreighter {
+        jumps: u32,
+        isotopes_per_jump: f64,
+        isotope_price: f64,
+        #[serde(default = "default_jump_freighter_cargo_m3")]
+        cargo_m3: f64,
+    },
+}
+
+fn default_jump_freighter_cargo_m3() -> f64 {
+    DEFAULT_JUMP_FREIGHTER_CARGO_M3
+}
+
+impl HaulingRate {
+    /// Cost of moving one m3 one way
+    pub fn isk_per_m3(&self) -> f64 {
+        match *self {
+            HaulingRate::None => 0.0,
+            HaulingRate::PerM3 { isk_per_m3 } => isk_per_m3,
+            HaulingRate::JumpFreighter {
+                jumps,
+                isotopes_per_jump,
+                isotope_price,
+                cargo_m3,
+            } => {
+                if cargo_m3 <= 0.0 {
+                    return 0.0;
+                }
+                jumps as f64 * isotopes_per_jump * isotope_price / cargo_m3
+            }
+        }
+    }
+
+    /// Cost of moving the given volume one way
+    pub fn cost(&self, volume_m3: f64) -> f64 {
+        volume_m3 * self.isk_per_m3()
+    }
+
+    /// Check that rates and fuel figures aren't negative
+    pub fn validate(&self) -> Result<(), String> {
+        let valid = |value: f64| value.is_finite() && value >= 0.0;
+        match *self {
+            HaulingRate::None => Ok(()),
+            HaulingRate::PerM3 { isk_per_m3 } if !valid(isk_per_m3) => {
+                Err("Hauling rate can't be negative".to_string())
+            }
+            HaulingRate::JumpFreighter {
+                isotopes_per_jump,
+                isotope_price,
+                cargo_m3,
+                ..
+            } if !valid(isotopes_per_jump) || !valid(isotope_price) || !valid(cargo_m3) => {
+                Err("Jump freighter fuel figures can't be negative".to_string())
+            }
+            HaulingRate::JumpFreighter { cargo_m3: 0.0, .. } => {
+                Err("Jump freighter cargo capacity must be above zero".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,338 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+
+use crate::parser::MoonComposition;
+use crate::prices::now_unix;
+use crate::storage;
+
+const ESI_URL: &str = "https://esi.evetech.net/latest";
+/// ESI lookups so far; shared by all profiles since the map doesn't change
+const CACHE_FILE: &str = "universe.json";
+/// Failed lookups, and IDs ESI doesn't know, aren't retried for this long
+const FAILED_LOOKUP_TTL_SECS: u64 = 60 * 60;
+/// Most ESI lookups in flight at once
+const MAX_CONCURRENT_LOOKUPS: usize = 8;
+
+/// Set while a background lookup pass runs, so only one runs at a time
+static RESOLVING: AtomicBool = AtomicBool::new(false);
+
+/// Where a moon is, resolved from its solar system ID
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub system_id: u32,
+    pub system_name: String,
+    pub region_id: u32,
+    pub region_name: String,
+    /// Unrounded security status; the game shows it rounded to one decimal
+    pub security_status: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsiSystem {
+    name: String,
+    security_status: f64,
+    constellation_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsiConstellation {
+    region_id: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsiRegion {
+    name: String,
+}
+
+/// Solar systems, region names and routes fetched from ESI
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UniverseCache {
+    systems: HashMap<u32, SystemInfo>,
+    regions: HashMap<u32, String>,
+    /// Jumps on the shortest route, keyed by `route_key`; None when there's no stargate
+    /// route, e.g. from wormhole space
+    routes: HashMap<String, Option<u32>>,
+    /// Unix timestamp (seconds) of the last failed lookup, keyed by `system_key` or
+    /// `route_key`
+    failed: HashMap<String, u64>,
+}
+
+fn route_key(from: u32, to: u32) -> String {
+    format!("{}:{}", from, to)
+}
+
+impl UniverseCache {
+    pub fn load() -> Self {
+        storage::load_json(CACHE_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        storage::save_json(CACHE_FILE, self)
+    }
+
+    pub fn system(&self, system_id: u32) -> Option<&SystemInfo> {
+        self.systems.get(&system_id)
+    }
+
+    /// Jumps between two systems, if the route has been looked up and exists
+    pub fn jumps(&self, from: u32, to: u32) -> Option<u32> {
+        self.routes.get(&route_key(from, to)).copied().flatten()
+    }
+
+    /// Systems the moons are in that still need looking up: not cached, and not failed
+    /// recently
+    fn unresolved_systems(&self, moons: &[MoonComposition], now: u64) -> Vec<u32> {
+        system_ids(moons)
+            .into_iter()
+            .filter(|id| {
+                !self.systems.contains_key(id) && !self.failed_recently(&system_key(*id), now)
+            })
+            .collect()
+    }
+
+    fn failed_recently(&self, key: &str, now: u64) -> bool {
+        self.failed
+            .get(key)
+            .is_some_and(|at| now.saturating_sub(*at) < FAILED_LOOKUP_TTL_SECS)
+    }
+
+    /// Routes from the moons' systems to the market system that still need looking up
+    fn unrouted_systems(
+        &self,
+        moons: &[MoonComposition],
+        market_system_id: u32,
+        now: u64,
+    ) -> Vec<u32> {
+        system_ids(moons)
+            .into_iter()
+            .filter(|id| {
+                let key = route_key(*id, market_system_id);
+                !self.routes.contains_key(&key) && !self.failed_recently(&key, now)
+            })
+            .collect()
+    }
+
+    /// Fill in each moon's location and jumps to the market system from the cache
+    pub fn enrich(&self, moons: &mut [MoonComposition], market_system_id: u32) {
+        for moon in moons {
+            let system_id = moon.celestial_ids().map(|(system_id, _, _)| system_id);
+            moon.location = system_id.and_then(|id| self.system(id)).cloned();
+            moon.jumps_to_market = system_id.and_then(|id| self.jumps(id, market_system_id));
+        }
+    }
+}
+
+/// Solar systems the moons' scans name, leaving out scans without one
+fn system_ids(moons: &[MoonComposition]) -> HashSet<u32> {
+    moons
+        .iter()
+        .filter_map(|moon| moon.celestial_ids())
+        .map(|(system_id, _, _)| system_id)
+        .filter(|id| *id != 0)
+        .collect()
+}
+
+fn system_key(system_id: u32) -> String {
+    format!("system:{}", system_id)
+}
+
+/// Look up what isn't cached about the moons' locations and routes in the background, one pass at a
+/// time, saving the results to the cache. Lookups run concurrently; failed ones and IDs
+/// ESI doesn't know are remembered and not retried for a while
+pub fn resolve_in_background(
+    cache: &UniverseCache,
+    moons: &[MoonComposition],
+    market_system_id: u32,
+) {
+    let now = now_unix();
+    let systems = cache.unresolved_systems(moons, now);
+    let unrouted = cache.unrouted_systems(moons, market_system_id, now);
+    if systems.is_empty() && unrouted.is_empty() {
+        return;
+    }
+    if RESOLVING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+
+    let known_regions = cache.regions.clone();
+    tauri::async_runtime::spawn(async move {
+        let client = reqwest::Client::new();
+        let (found, routes) = tokio::join!(
+            lookup_systems(&client, systems, known_regions),
+            lookup_routes(&client, unrouted, market_system_id),
+        );
+
+        // Read the cache again so nothing saved meanwhile is lost
+        let mut cache = UniverseCache::load();
+        let now = now_unix();
+        for (system_id, result) in found {
+            match result {
+                Ok(Some((info, region_name))) => {
+                    cache.regions.insert(info.region_id, region_name);
+                    cache.failed.remove(&system_key(system_id));
+                    cache.systems.insert(system_id, info);
+                }
+                Ok(None) => {
+                    tracing::warn!(system_id, "ESI doesn't know this solar system");
+                    cache.failed.insert(system_key(system_id), now);
+                }
+                Err(e) => {
+                    tracing::warn!(system_id, error = %e, "Failed to resolve solar system");
+                    cache.failed.insert(system_key(system_id), now);
+                }
+            }
+        }
+        for (system_id, result) in routes {
+            let key = route_key(system_id, market_system_id);
+            match result {
+                // The route lists both ends
+                Ok(route) => {
+                    let jumps = route.map(|systems| systems.len().saturating_sub(1) as u32);
+                    cache.failed.remove(&key);
+                    cache.routes.insert(key, jumps);
+                }
+                Err(e) => {
+                    tracing::warn!(system_id, error = %e, "Failed to look up route to the market");
+                    cache.failed.insert(key, now);
+                }
+            }
+        }
+        if let Err(e) = cache.save() {
+            tracing::warn!(error = %e, "Failed to cache solar systems");
+        }
+        RESOLVING.store(false, Ordering::Release);
+    });
+}
+
+/// Look up solar systems concurrently. Each result is the system and its region's name,
+/// or None when ESI doesn't know the ID
+async fn lookup_systems(
+    client: &reqwest::Client,
+    system_ids: Vec<u32>,
+    known_regions: HashMap<u32, String>,
+) -> Vec<(u32, Result<Option<(SystemInfo, String)>, String>)> {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_LOOKUPS));
+    let known_regions = Arc::new(known_regions);
+    let mut lookups = JoinSet::new();
+    for system_id in system_ids {
+        let client = client.clone();
+        let permits = Arc::clone(&permits);
+        let known_regions = Arc::clone(&known_regions);
+        lookups.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            (
+                system_id,
+                lookup_system(&client, system_id, &known_regions).await,
+            )
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = lookups.join_next().await {
+        if let Ok(result) = result {
+            results.push(result);
+        }
+    }
+    results
+}
+
+/// Look up the routes from systems to the market system concurrently. Each result lists
+/// the systems on the route, or is None when there's no stargate route
+async fn lookup_routes(
+    client: &reqwest::Client,
+    system_ids: Vec<u32>,
+    market_system_id: u32,
+) -> Vec<(u32, Result<Option<Vec<u32>>, String>)> {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT_LOOKUPS));
+    let mut lookups = JoinSet::new();
+    for system_id in system_ids {
+        let client = client.clone();
+        let permits = Arc::clone(&permits);
+        lookups.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            let path = format!("route/{}/{}/", system_id, market_system_id);
+            (system_id, fetch(&client, &path).await)
+        });
+    }
+
+    let mut results = Vec::new();
+    while let Some(result) = lookups.join_next().await {
+        if let Ok(result) = result {
+            results.push(result);
+        }
+    }
+    results
+}
+
+async fn lookup_system(
+    client: &reqwest::Client,
+    system_id: u32,
+    known_regions: &HashMap<u32, String>,
+) -> Result<Option<(SystemInfo, String)>, String> {
+    let Some(system) =
+        fetch::<EsiSystem>(client, &format!("universe/systems/{}/", system_id)).await?
+    else {
+        return Ok(None);
+    };
+    let constellation: EsiConstellation = fetch(
+        client,
+        &format!("universe/constellations/{}/", system.constellation_id),
+    )
+    .await?
+    .ok_or_else(|| format!("Unknown constellation {}", system.constellation_id))?;
+    let region_name = match known_regions.get(&constellation.region_id) {
+        Some(name) => name.clone(),
+        None => {
+            fetch::<EsiRegion>(
+                client,
+                &format!("universe/regions/{}/", constellation.region_id),
+            )
+            .await?
+            .ok_or_else(|| format!("Unknown region {}", constellation.region_id))?
+            .name
+        }
+    };
+
+    Ok(Some((
+        SystemInfo {
+            system_id,
+            system_name: system.name,
+            region_id: constellation.region_id,
+            region_name: region_name.clone(),
+            security_status: system.security_status,
+        },
+        region_name,
+    )))
+}
+
+/// GET an ESI endpoint; None when ESI doesn't know the ID or has no route
+async fn fetch<T: serde::de::DeserializeOwned>(
+    client: &reqwest::Client,
+    path: &str,
+) -> Result<Option<T>, String> {
+    let response = client
+        .get(format!("{}/{}", ESI_URL, path))
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to reach ESI: {}", e))?;
+
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !response.status().is_success() {
+        return Err(format!("ESI returned status: {}", response.status()));
+    }
+
+    response
+        .json()
+        .await
+        .map(Some)
+        .map_err(|e| format!("Failed to parse ESI response: {}", e))
+}
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/isomerc/moon/releases/latest";
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    html_url: String,
+}
+
+/// Result of comparing this build against the latest release
+#[derive(Debug, Clone, Serialize)]
+pub struct UpdateInfo {
+    pub current_version: String,
+    pub latest_version: String,
+    pub update_available: bool,
+    /// Release page with the notes for the latest version
+    pub release_url: String,
+}
+
+/// Ask GitHub for the latest release and compare it with the running version
+pub async fn check() -> Result<UpdateInfo, String> {
+    let response = reqwest::Client::new()
+        .get(LATEST_RELEASE_URL)
+        .header("User-Agent", "MOON-Reaction-Calculator/1.0")
+        .header("Accept", "application/vnd.github+json")
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await
+        .map_err(|e| format!("Failed to check for updates: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("GitHub returned status: {}", response.status()));
+    }
+
+    let release: Release = response
+        .json()
+        .await
+        .map_err(|e| format!("Failed to parse release info: {}", e))?;
+
+    let current_version = env!("CARGO_PKG_VERSION").to_string();
+    let latest_version = release.tag_name.trim_start_matches('v').to_string();
+    Ok(UpdateInfo {
+        update_available: is_newer(&latest_version, &current_version),
+        current_version,
+        latest_version,
+        release_url: release.html_url,
+    })
+}
+
+/// Whether dotted version `a` is newer than `b`. Pre-release suffixes are ignored
+fn is_newer(a: &str, b: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or_default()
+            .split('.')
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    let (a, b) = (parts(a), parts(b));
+    let len = a.len().max(b.len());
+    let padded =
+        |v: &[u64]| -> Vec<u64> { (0..len).map(|i| v.get(i).copied().unwrap_or(0)).collect() };
+    padded(&a) > padded(&b)
+}
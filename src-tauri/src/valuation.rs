@@ -0,0 +1,503 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+use crate::fees::{self, SaleOptions};
+use crate::ore_mappings::{OreMappings, OreTier, MOON_ORE_VOLUME_M3};
+use crate::parser::MoonComposition;
+use crate::prices::{self, PriceInfo};
+use crate::reactions::ReactionDatabase;
+
+/// Approximate ore volume a moon drill extracts per hour
+pub const EXTRACTION_M3_PER_HOUR: f64 = 30_000.0;
+/// Units of ore consumed per reprocessing batch
+pub const REPROCESSING_BATCH_SIZE: f64 = 100.0;
+/// Fraction of the listed goo yield recovered when reprocessing, for moons without a
+/// recorded structure
+pub const REPROCESSING_EFFICIENCY: f64 = 0.80;
+/// Volume of one unit of raw moon material
+const GOO_VOLUME_M3: f64 = 0.05;
+
+const HOURS_PER_MONTH: f64 = 24.0 * 30.0;
+const HOURS_PER_DAY: f64 = 24.0;
+
+/// Goo produced by a moon over a month of continuous extraction
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GooYield {
+    pub name: String,
+    pub units: f64,
+    pub unit_price: f64,
+    pub value: f64,
+}
+
+/// Share of a moon's composition and value belonging to one ore tier
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TierShare {
+    pub tier: OreTier,
+    pub fraction: f64,
+    pub monthly_isk: f64,
+}
+
+/// Estimated monthly value of a single moon
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MoonValuation {
+    pub name: String,
+    pub monthly_isk: f64,
+    pub goo: Vec<GooYield>,
+    pub tiers: Vec<TierShare>,
+    /// Jumps from the moon to the market hub, when the route is known
+    #[serde(default)]
+    pub jumps_to_market: Option<u32>,
+    /// Region whose market priced the goo, when it wasn't the trade hub
+    #[serde(default)]
+    pub price_region: Option<String>,
+}
+
+/// Fraction of a moon's composition in each ore tier it has any of
+pub fn tier_fractions(moon: &MoonComposition, ore_mappings: &OreMappings) -> HashMap<OreTier, f64> {
+    let mut fractions = HashMap::new();
+    for material in &moon.materials {
+        if let Some(tier) = ore_mappings.tier_of(&ore_mappings.base_ore(material)) {
+            *fractions.entry(tier).or_insert(0.0) += material.quantity;
+        }
+    }
+    fractions
+}
+
+/// Value a moon by the goo its ores reprocess into over a month of extraction, recovering
+/// `efficiency` of the listed yield
+pub fn value_moon(
+    moon: &MoonComposition,
+    ore_mappings: &OreMappings,
+    prices: &HashMap<String, PriceInfo>,
+    efficiency: f64,
+) -> MoonValuation {
+    let monthly_batches =
+        EXTRACTION_M3_PER_HOUR * HOURS_PER_MONTH / MOON_ORE_VOLUME_M3 / REPROCESSING_BATCH_SIZE;
+
+    let mut goo_units: HashMap<String, f64> = HashMap::new();
+    let tier_fraction = tier_fractions(moon, ore_mappings);
+    let mut tier_value: HashMap<OreTier, f64> = HashMap::new();
+
+    for material in &moon.materials {
+        let base_ore = ore_mappings.base_ore(material);
+        let Some(tier) = ore_mappings.tier_of(&base_ore) else {
+            continue;
+        };
+        let Some(yields) = ore_mappings.goo_yields(&base_ore) else {
+            continue;
+        };
+        for (goo, per_batch) in yields {
+            let units = material.quantity * monthly_batches * *per_batch as f64 * efficiency;
+            let unit_price = prices.get(goo).map(|p| p.sell).unwrap_or(0.0);
+            *goo_units.entry(goo.clone()).or_insert(0.0) += units;
+            *tier_value.entry(tier).or_insert(0.0) += units * unit_price;
+        }
+    }
+
+    let mut goo: Vec<GooYield> = goo_units
+        .into_iter()
+        .map(|(name, units)| {
+            let unit_price = prices.get(&name).map(|p| p.sell).unwrap_or(0.0);
+            GooYield {
+                value: units * unit_price,
+                name,
+                units,
+                unit_price,
+            }
+        })
+        .collect();
+    goo.sort_by(|a, b| {
+        b.value
+            .partial_cmp(&a.value)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+
+    let tiers: Vec<TierShare> = OreTier::ALL
+        .iter()
+        .map(|tier| TierShare {
+            tier: *tier,
+            fraction: tier_fraction.get(tier).copied().unwrap_or(0.0),
+            monthly_isk: tier_value.get(tier).copied().unwrap_or(0.0),
+        })
+        .collect();
+
+    MoonValuation {
+        name: moon.name.clone(),
+        monthly_isk: goo.iter().map(|g| g.value).sum(),
+        goo,
+        tiers,
+        jumps_to_market: moon.jumps_to_market,
+        price_region: None,
+    }
+}
+
+/// Monthly value at which the value half of a moon's score is full
+const FULL_SCORE_MONTHLY_ISK: f64 = 10_000_000_000.0;
+
+/// Weight of a tier in the rarity half of a moon's score; each tier counts double the one
+/// below it
+fn tier_weight(tier: OreTier) -> f64 {
+    match tier {
+        OreTier::R4 => 1.0,
+        OreTier::R8 => 2.0,
+        OreTier::R16 => 4.0,
+        OreTier::R32 => 8.0,
+        OreTier::R64 => 16.0,
+    }
+}
+
+/// One number, 0 to 100, for triaging moons: half from how much of the moon is rare ore,
+/// half from what its goo is worth at current prices
+#[derive(Debug, Clone, Serialize)]
+pub struct MoonScore {
+    pub name: String,
+    pub score: f64,
+    /// 100 for a moon that's all R64 ore
+    pub rarity: f64,
+    /// 100 for a moon worth `FULL_SCORE_MONTHLY_ISK` a month or more
+    pub value: f64,
+    pub monthly_isk: f64,
+    pub highest_tier: Option<OreTier>,
+}
+
+pub fn score_moon(valuation: &MoonValuation) -> MoonScore {
+    let rarity: f64 = valuation
+        .tiers
+        .iter()
+        .map(|share| share.fraction * tier_weight(share.tier))
+        .sum::<f64>()
+        / tier_weight(OreTier::R64)
+        * 100.0;
+    let value = (valuation.monthly_isk / FULL_SCORE_MONTHLY_ISK).clamp(0.0, 1.0) * 100.0;
+
+    MoonScore {
+        name: valuation.name.clone(),
+        score: (rarity + value) / 2.0,
+        rarity,
+        value,
+        monthly_isk: valuation.monthly_isk,
+        highest_tier: valuation
+            .tiers
+            .iter()
+            .filter(|share| share.fraction > 0.0)
+            .map(|share| share.tier)
+            .max(),
+    }
+}
+
+/// How far a moon's output is processed before it's sold
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SaleStage {
+    RawOre,
+    CompressedOre,
+    ReprocessedGoo,
+    ReactedProducts,
+}
+
+/// Monthly value of a moon's output sold at one stage, after fees
+#[derive(Debug, Clone, Serialize)]
+pub struct StageValue {
+    pub stage: SaleStage,
+    pub gross_isk: f64,
+    /// Broker fees, relisting, sales tax and hauling
+    pub fees_isk: f64,
+    pub net_isk: f64,
+    /// Items that had no price and count as zero
+    pub unpriced: Vec<String>,
+}
+
+/// The most profitable reaction a goo feeds straight into
+#[derive(Debug, Clone, Serialize)]
+pub struct GooReaction {
+    pub goo: String,
+    pub formula_name: String,
+    pub output_name: String,
+    pub margin: f64,
+}
+
+/// What a moon's monthly output is worth at each stage of processing
+#[derive(Debug, Clone, Serialize)]
+pub struct MoonSaleComparison {
+    pub moon: String,
+    pub stages: Vec<StageValue>,
+    /// Stage with the highest net value
+    pub best_stage: SaleStage,
+    /// Reactions used for the reacted stage
+    pub reactions: Vec<GooReaction>,
+}
+
+/// Find the most profitable reaction each goo is a direct input of. Goo that only feeds
+/// unprofitable or unpriced reactions is left out
+pub fn best_goo_reactions(
+    goo_names: &[String],
+    reactions_db: &ReactionDatabase,
+    prices: &HashMap<String, PriceInfo>,
+    sale_options: &SaleOptions,
+) -> HashMap<String, (GooReaction, prices::ReactionProfit)> {
+    let mut best: HashMap<String, (GooReaction, prices::ReactionProfit)> = HashMap::new();
+    let goo_ids = reactions_db.get_user_material_ids(goo_names);
+
+    for reaction in &reactions_db.reactions {
+        let Some(profit) =
+            prices::calculate_reaction_profit(reaction, prices, &goo_ids, sale_options)
+        else {
+            continue;
+        };
+        if profit.profit <= 0.0 {
+            continue;
+        }
+        for input in reaction.inputs.iter().filter(|i| goo_ids.contains(&i.id)) {
+            if best
+                .get(&input.name)
+                .is_some_and(|(current, _)| current.margin >= profit.margin)
+            {
+                continue;
+            }
+            best.insert(
+                input.name.clone(),
+                (
+                    GooReaction {
+                        goo: input.name.clone(),
+                        formula_name: reaction.formula_name.clone(),
+                        output_name: reaction.output.name.clone(),
+                        margin: profit.margin,
+                    },
+                    profit.clone(),
+                ),
+            );
+        }
+    }
+    best
+}
+
+/// Compare selling a moon's monthly output as raw ore, compressed ore, reprocessed goo or
+/// reacted products. Reacted goo is credited with its share, by input cost, of the best
+/// reaction it feeds; goo with no profitable reaction is sold as is
+pub fn compare_sale_stages(
+    moon: &MoonComposition,
+    ore_mappings: &OreMappings,
+    prices: &HashMap<String, PriceInfo>,
+    efficiency: f64,
+    sale_options: &SaleOptions,
+    goo_reactions: &HashMap<String, (GooReaction, prices::ReactionProfit)>,
+) -> MoonSaleComparison {
+    let monthly_m3 = EXTRACTION_M3_PER_HOUR * HOURS_PER_MONTH;
+    let hauling = &sale_options.hauling;
+
+    let mut raw = StageTotal::new(SaleStage::RawOre);
+    let mut compressed = StageTotal::new(SaleStage::CompressedOre);
+    for material in &moon.materials {
+        let ore = ore_mappings.english_name(&material.name);
+        let volume_m3 = monthly_m3 * material.quantity;
+        let units = volume_m3 / ore_mappings.ore_volume_m3(&ore_mappings.base_ore(material));
+        raw.sell(&ore, units, prices.get(&ore), sale_options);
+        raw.fees += hauling.cost(volume_m3);
+
+        // Compressed ore is one unit per unit of ore at a hundredth of the volume
+        let compressed_ore = format!("Compressed {}", ore);
+        compressed.sell(
+            &compressed_ore,
+            units,
+            prices.get(&compressed_ore),
+            sale_options,
+        );
+        compressed.fees += hauling.cost(volume_m3 / 100.0);
+    }
+
+    let goo = value_moon(moon, ore_mappings, prices, efficiency).goo;
+    let mut reprocessed = StageTotal::new(SaleStage::ReprocessedGoo);
+    let mut reacted = StageTotal::new(SaleStage::ReactedProducts);
+    let mut reactions = Vec::new();
+    for yielded in &goo {
+        let price = prices.get(&yielded.name);
+        reprocessed.sell(&yielded.name, yielded.units, price, sale_options);
+
+        let share = goo_reactions
+            .get(&yielded.name)
+            .and_then(|(reaction, profit)| {
+                let input = profit.inputs.iter().find(|i| i.name == yielded.name)?;
+                (input.quantity > 0 && profit.input_cost > 0.0).then(|| {
+                    let runs = yielded.units / input.quantity as f64;
+                    let cost_share = input.total_price / profit.input_cost;
+                    (reaction, profit, runs * cost_share)
+                })
+            });
+        match share {
+            Some((reaction, profit, scale)) => {
+                reacted.gross += profit.output_value * scale;
+                reacted.fees +=
+                    (profit.output_fees + profit.import_hauling_cost + profit.export_hauling_cost)
+                        * scale;
+                reactions.push(reaction.clone());
+            }
+            None => reacted.sell(&yielded.name, yielded.units, price, sale_options),
+        }
+    }
+    // Goo reacted on site isn't hauled, but the reprocessed goo is
+    reprocessed.fees += hauling.cost(goo.iter().map(|g| g.units).sum::<f64>() * GOO_VOLUME_M3);
+
+    let stages: Vec<StageValue> = [raw, compressed, reprocessed, reacted]
+        .into_iter()
+        .map(StageTotal::finish)
+        .collect();
+    let best_stage = stages
+        .iter()
+        .max_by(|a, b| {
+            a.net_isk
+                .partial_cmp(&b.net_isk)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .map(|s| s.stage)
+        .unwrap_or(SaleStage::ReprocessedGoo);
+
+    MoonSaleComparison {
+        moon: moon.name.clone(),
+        stages,
+        best_stage,
+        reactions,
+    }
+}
+
+// Running totals for one sale stage
+struct StageTotal {
+    stage: SaleStage,
+    gross: f64,
+    fees: f64,
+    unpriced: HashSet<String>,
+}
+
+impl StageTotal {
+    fn new(stage: SaleStage) -> Self {
+        Self {
+            stage,
+            gross: 0.0,
+            fees: 0.0,
+            unpriced: HashSet::new(),
+        }
+    }
+
+    // Add the sale of a (possibly fractional) number of units
+    fn sell(&mut self, name: &str, units: f64, price: Option<&PriceInfo>, options: &SaleOptions) {
+        let Some(price) = price.filter(|p| p.sell > 0.0 || p.buy > 0.0) else {
+            self.unpriced.insert(name.to_string());
+            return;
+        };
+        let sale = fees::value_output(price, 1, options);
+        self.gross += sale.gross_value * units;
+        self.fees += (sale.gross_value - sale.net_value) * units;
+    }
+
+    fn finish(self) -> StageValue {
+        let mut unpriced: Vec<String> = self.unpriced.into_iter().collect();
+        unpriced.sort();
+        StageValue {
+            stage: self.stage,
+            gross_isk: self.gross,
+            fees_isk: self.fees,
+            net_isk: self.gross - self.fees,
+            unpriced,
+        }
+    }
+}
+
+/// Units of one ore in an extraction
+#[derive(Debug, Clone, Serialize)]
+pub struct OreUnits {
+    pub name: String,
+    /// Share of the moon's composition
+    pub fraction: f64,
+    pub volume_m3: f64,
+    pub units: f64,
+}
+
+/// Goo units produced by reprocessing an extraction
+#[derive(Debug, Clone, Serialize)]
+pub struct GooUnits {
+    pub name: String,
+    pub units: f64,
+}
+
+/// What one extraction of a moon yields
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractionYield {
+    pub moon: String,
+    pub days: f64,
+    pub total_m3: f64,
+    pub ores: Vec<OreUnits>,
+    pub goo: Vec<GooUnits>,
+}
+
+/// Goo units available from the ore actually extracted, by goo name, recovering the
+/// efficiency of each moon's refinery. Materials without extracted units add nothing
+pub fn extracted_goo(
+    moons: &[MoonComposition],
+    ore_mappings: &OreMappings,
+    efficiency_of: impl Fn(&str) -> f64,
+) -> HashMap<String, f64> {
+    let mut goo_units: HashMap<String, f64> = HashMap::new();
+    for moon in moons {
+        let efficiency = efficiency_of(&moon.name);
+        for material in &moon.materials {
+            let Some(units) = material.extracted_units else {
+                continue;
+            };
+            if let Some(yields) = ore_mappings.goo_yields(&ore_mappings.base_ore(material)) {
+                let batches = (units / REPROCESSING_BATCH_SIZE).floor();
+                for (goo, per_batch) in yields {
+                    *goo_units.entry(goo.clone()).or_insert(0.0) +=
+                        batches * *per_batch as f64 * efficiency;
+                }
+            }
+        }
+    }
+    goo_units
+}
+
+/// Work out the ore and reprocessed goo from extracting a moon for a number of days,
+/// recovering `efficiency` of the listed goo yield
+pub fn extraction_yield(
+    moon: &MoonComposition,
+    days: f64,
+    ore_mappings: &OreMappings,
+    efficiency: f64,
+) -> ExtractionYield {
+    let total_m3 = EXTRACTION_M3_PER_HOUR * HOURS_PER_DAY * days;
+
+    let mut ores: Vec<OreUnits> = Vec::new();
+    let mut goo_units: HashMap<String, f64> = HashMap::new();
+    for material in &moon.materials {
+        let base_ore = ore_mappings.base_ore(material);
+        let volume_m3 = total_m3 * material.quantity;
+        let units = volume_m3 / ore_mappings.ore_volume_m3(&base_ore);
+
+        if let Some(yields) = ore_mappings.goo_yields(&base_ore) {
+            let batches = units / REPROCESSING_BATCH_SIZE;
+            for (goo, per_batch) in yields {
+                *goo_units.entry(goo.clone()).or_insert(0.0) +=
+                    batches * *per_batch as f64 * efficiency;
+            }
+        }
+
+        ores.push(OreUnits {
+            name: material.name.clone(),
+            fraction: material.quantity,
+            volume_m3,
+            units,
+        });
+    }
+
+    let mut goo: Vec<GooUnits> = goo_units
+        .into_iter()
+        .map(|(name, units)| GooUnits { name, units })
+        .collect();
+    goo.sort_by(|a, b| a.name.cmp(&b.name));
+
+    ExtractionYield {
+        moon: moon.name.clone(),
+        days,
+        total_m3,
+        ores,
+        goo,
+    }
+}
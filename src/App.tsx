@@ -1,5 +1,6 @@
 import { useState, useEffect } from "react";
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 import ReactionTreeGraph from "./ReactionTreeGraph";
 import "./App.css";
 
@@ -11,11 +12,48 @@ interface MaterialEntry {
   system_id: number;
   region_id: number;
   additional_id: number;
+  tier?: OreTier;
 }
 
+type OreTier = "R4" | "R8" | "R16" | "R32" | "R64";
+
 interface MoonComposition {
   name: string;
   materials: MaterialEntry[];
+  tags?: string[];
+  notes?: string;
+  location?: SystemInfo;
+  jumps_to_market?: number;
+  highest_tier?: OreTier;
+}
+
+interface SystemInfo {
+  system_id: number;
+  system_name: string;
+  region_id: number;
+  region_name: string;
+  security_status: number;
+}
+
+interface ParseError {
+  kind: string;
+  message: string;
+  line: number | null;
+  column: number | null;
+  text: string | null;
+}
+
+// Describe a parse error with the line it happened on, so it can be found in a long paste
+function describeParseError(err: unknown): string {
+  if (typeof err !== "object" || err === null || !("kind" in err)) {
+    return String(err);
+  }
+  const { message, line, column, text } = err as ParseError;
+  if (line === null) {
+    return message;
+  }
+  const location = column === null ? `Line ${line}` : `Line ${line}, column ${column}`;
+  return text === null ? `${location}: ${message}` : `${location}: ${message}\n${text.trim()}`;
 }
 
 interface InputBreakdown {
@@ -26,17 +64,19 @@ interface InputBreakdown {
   from_moon: boolean;
 }
 
-type SourceType = "moon" | "buy" | "react" | "output";
+type SourceType = "moon" | "buy" | "react" | "output" | "stock";
 
 interface ReactionTreeNode {
   name: string;
   id: number;
   quantity: number;
+  from_stock: number;
   source: SourceType;
   unit_price: number;
   total_price: number;
   reaction_name: string | null;
   children: ReactionTreeNode[];
+  alternative: { source: SourceType; cost: number } | null;
 }
 
 interface ReactionProfit {
@@ -50,11 +90,48 @@ interface ReactionProfit {
   input_cost: number;
   profit: number;
   margin: number;
+  cash_input_cost: number;
+  cash_profit: number;
+  cash_margin: number;
+  input_volume_m3: number;
+  output_volume_m3: number;
+  isk_per_m3: number;
+  isk_per_day: number;
+  breakeven_price: number;
+  price_headroom: number;
   inputs: InputBreakdown[];
   uses_user_materials: boolean;
   reaction_tree: ReactionTreeNode | null;
 }
 
+interface TelemetryConsent {
+  decided: boolean;
+  granted: boolean;
+  decided_at: number | null;
+  available: boolean;
+}
+
+interface DataFreshness {
+  price_source: string;
+  prices_fetched_at: number | null;
+  price_age_secs: number | null;
+  data_version: string;
+  data_synced_at: number | null;
+  severity: "fresh" | "warning" | "critical";
+  warnings: string[];
+}
+
+interface WithFreshness<T> {
+  data: T;
+  freshness: DataFreshness;
+}
+
+interface Page<T> {
+  items: T[];
+  offset: number;
+  total: number;
+}
+
 interface Tab {
   id: string;
   name: string;
@@ -69,13 +146,16 @@ function App() {
   const [moons, setMoons] = useState<MoonComposition[]>([]);
   const [uniqueMaterials, setUniqueMaterials] = useState<string[]>([]);
   const [errorMessage, setErrorMessage] = useState<string | null>(null);
+  const [notice, setNotice] = useState<string | null>(null);
   const [tabs, setTabs] = useState<Tab[]>([{ id: "home", name: "Home" }]);
   const [activeTab, setActiveTab] = useState("home");
   const [isAnalyzing, setIsAnalyzing] = useState(false);
   const [expandedReactions, setExpandedReactions] = useState<Set<number>>(new Set());
+  const [reactionTrees, setReactionTrees] = useState<Record<number, ReactionTreeNode>>({});
   const [sortField, setSortField] = useState<SortField>("margin");
   const [sortDirection, setSortDirection] = useState<SortDirection>("desc");
   const [reactionDetailTab, setReactionDetailTab] = useState<Record<number, "graph" | "text">>({});
+  const [telemetryPayload, setTelemetryPayload] = useState<string | null>(null);
 
   // Load moons and materials on mount and after changes
   const refreshData = async () => {
@@ -93,6 +173,41 @@ function App() {
     refreshData();
   }, []);
 
+  // Say when pinned prices lapse, so analyses using market prices again aren't a surprise
+  useEffect(() => {
+    const unlisten = listen<{ item: string }[]>("prices://pins-expired", (event) => {
+      const items = event.payload.map((pin) => pin.item).join(", ");
+      setNotice(`Price pins expired, using market prices again for: ${items}`);
+    });
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, []);
+
+  // Ask about telemetry on first run, showing exactly what would be sent
+  useEffect(() => {
+    (async () => {
+      try {
+        const consent = await invoke<TelemetryConsent>("get_telemetry_consent");
+        if (consent.available && !consent.decided) {
+          const payload = await invoke<object>("get_telemetry_payload");
+          setTelemetryPayload(JSON.stringify(payload, null, 2));
+        }
+      } catch (err) {
+        console.error("Failed to load telemetry consent:", err);
+      }
+    })();
+  }, []);
+
+  const handleTelemetryConsent = async (granted: boolean) => {
+    try {
+      await invoke("set_telemetry_consent", { granted });
+      setTelemetryPayload(null);
+    } catch (err) {
+      console.error("Failed to record telemetry consent:", err);
+    }
+  };
+
   const handleAddMoon = async () => {
     setErrorMessage(null);
 
@@ -114,7 +229,7 @@ function App() {
       setInputText("");
       await refreshData();
     } catch (err) {
-      setErrorMessage(String(err));
+      setErrorMessage(describeParseError(err));
     }
   };
 
@@ -137,7 +252,10 @@ function App() {
     setErrorMessage(null);
 
     try {
-      const results = await invoke<ReactionProfit[]>("analyze_reactions");
+      const { data } = await invoke<WithFreshness<Page<ReactionProfit>>>("analyze_reactions");
+      const results = data.items;
+      // Trees built for earlier results used older prices
+      setReactionTrees({});
 
       // Create a new tab with results
       const newTabId = `analysis-${Date.now()}`;
@@ -181,7 +299,36 @@ function App() {
 
   const activeTabData = tabs.find((t) => t.id === activeTab);
 
-  const toggleReactionExpanded = (formulaId: number) => {
+  // Trees aren't part of the analysis results; build them when a result is first expanded
+  const loadReactionTree = async (result: ReactionProfit) => {
+    if (reactionTrees[result.formula_id]) return;
+    try {
+      const tree = await invoke<ReactionTreeNode>("build_tree", {
+        outputId: result.output_id,
+        quantity: result.output_quantity,
+      });
+      setReactionTrees((prev) => ({ ...prev, [result.formula_id]: tree }));
+    } catch (err) {
+      setErrorMessage(String(err));
+    }
+  };
+
+  const openDetailWindow = async (result: ReactionProfit) => {
+    try {
+      await invoke("open_detail_window", {
+        outputId: result.output_id,
+        quantity: result.output_quantity,
+      });
+    } catch (err) {
+      setErrorMessage(String(err));
+    }
+  };
+
+  const toggleReactionExpanded = (result: ReactionProfit) => {
+    const formulaId = result.formula_id;
+    if (!expandedReactions.has(formulaId)) {
+      loadReactionTree(result);
+    }
     setExpandedReactions((prev) => {
       const next = new Set(prev);
       if (next.has(formulaId)) {
@@ -288,6 +435,20 @@ function App() {
     <div className="app">
       <img src="/logo.png" alt="MOON" className="app-logo" />
 
+      {telemetryPayload && (
+        <div className="telemetry-consent">
+          <p>
+            MOON can send an anonymous ping on launch, and crash reports, to help fix problems.
+            This is everything the ping contains:
+          </p>
+          <pre>{telemetryPayload}</pre>
+          <div className="telemetry-actions">
+            <button onClick={() => handleTelemetryConsent(true)}>Allow</button>
+            <button onClick={() => handleTelemetryConsent(false)}>Don't send anything</button>
+          </div>
+        </div>
+      )}
+
       {/* Tab Bar */}
       <div className="tab-bar">
         {tabs.map((tab) => (
@@ -346,6 +507,11 @@ function App() {
             {errorMessage && (
               <div className="error-message">{errorMessage}</div>
             )}
+            {notice && (
+              <div className="notice-message" onClick={() => setNotice(null)}>
+                {notice}
+              </div>
+            )}
           </div>
 
           {/* Dual Panel Layout */}
@@ -355,7 +521,12 @@ function App() {
               <h2 className="panel-heading">Loaded Moons ({moons.length})</h2>
               <div className="moons-list">
                 {moons.map((moon, index) => (
-                  <div key={moon.name} className="moon-card">
+                  <div
+                    key={moon.name}
+                    className={`moon-card tier-${(
+                      moon.highest_tier ?? "none"
+                    ).toLowerCase()}`}
+                  >
                     <div className="moon-header">
                       <span className="moon-name">
                         {index + 1}. {moon.name}
@@ -367,10 +538,20 @@ function App() {
                         ✕
                       </button>
                     </div>
+                    {moon.location && (
+                      <div className="moon-location">
+                        {moon.location.system_name} (
+                        {moon.location.security_status.toFixed(1)}),{" "}
+                        {moon.location.region_name}
+                        {moon.jumps_to_market !== undefined &&
+                          ` · ${moon.jumps_to_market} jumps to market`}
+                      </div>
+                    )}
                     <div className="materials-list">
                       {moon.materials.map((material) => (
                         <div key={material.name} className="material-item">
-                          • {material.name}:{" "}
+                          • {material.name}
+                          {material.tier && ` (${material.tier})`}:{" "}
                           {(material.quantity * 100).toFixed(2)}%
                         </div>
                       ))}
@@ -427,6 +608,7 @@ function App() {
                 className="expand-btn"
                 onClick={() => {
                   const allIds = new Set(activeTabData.results!.map(r => r.formula_id));
+                  activeTabData.results!.forEach(loadReactionTree);
                   setExpandedReactions(allIds);
                 }}
               >
@@ -467,6 +649,7 @@ function App() {
           <div className="results-list">
             {getSortedResults(activeTabData.results).map((result) => {
               const isExpanded = expandedReactions.has(result.formula_id);
+              const tree = reactionTrees[result.formula_id];
               return (
                 <div
                   key={result.formula_id}
@@ -474,7 +657,7 @@ function App() {
                 >
                   <div
                     className="result-header"
-                    onClick={() => toggleReactionExpanded(result.formula_id)}
+                    onClick={() => toggleReactionExpanded(result)}
                   >
                     <div className="result-cell name">
                       <div className="result-name">{result.output_name}</div>
@@ -508,18 +691,24 @@ function App() {
                           >
                             Instructions
                           </button>
+                          <button
+                            className="detail-tab open-window"
+                            onClick={(e) => { e.stopPropagation(); openDetailWindow(result); }}
+                          >
+                            Open in Window
+                          </button>
                         </div>
 
-                        {getDetailTab(result.formula_id) === "graph" && result.reaction_tree && (
+                        {getDetailTab(result.formula_id) === "graph" && tree && (
                           <>
                             <div className="zoom-hint">Ctrl + Scroll to zoom</div>
-                            <ReactionTreeGraph tree={result.reaction_tree} formatIsk={formatIsk} />
+                            <ReactionTreeGraph tree={tree} formatIsk={formatIsk} />
                           </>
                         )}
 
-                        {getDetailTab(result.formula_id) === "text" && result.reaction_tree && (
+                        {getDetailTab(result.formula_id) === "text" && tree && (
                           <div className="text-instructions">
-                            {generateTextInstructions(result.reaction_tree).map((line, i) => (
+                            {generateTextInstructions(tree).map((line, i) => (
                               <div key={i} className={line === "" ? "instruction-spacer" : line.startsWith("  ") ? "instruction-item" : "instruction-header"}>
                                 {line}
                               </div>
@@ -533,6 +722,11 @@ function App() {
                         <span className={result.profit > 0 ? "positive" : "negative"}>
                           {formatIsk(result.profit)} ({result.margin.toFixed(1)}%)
                         </span>
+                        <span title="Moon materials counted as free">Cash flow per run:</span>
+                        <span className={result.cash_profit > 0 ? "positive" : "negative"}>
+                          {formatIsk(result.cash_profit)}
+                          {result.cash_input_cost > 0 && ` (${result.cash_margin.toFixed(1)}%)`}
+                        </span>
                       </div>
                     </div>
                   )}
@@ -0,0 +1,223 @@
+import { useState, useEffect } from "react";
+import { invoke } from "@tauri-apps/api/core";
+import { getCurrentWebviewWindow } from "@tauri-apps/api/webviewWindow";
+import ReactionTreeGraph from "./ReactionTreeGraph";
+import "./App.css";
+
+// Types matching the Rust backend
+type SourceType = "moon" | "buy" | "react" | "output" | "stock";
+
+interface ReactionTreeNode {
+  name: string;
+  id: number;
+  quantity: number;
+  from_stock: number;
+  source: SourceType;
+  unit_price: number;
+  total_price: number;
+  reaction_name: string | null;
+  children: ReactionTreeNode[];
+}
+
+interface ScheduledJob {
+  formula_id: number;
+  formula_name: string;
+  output_name: string;
+  runs: number;
+  character: number;
+  slot: number;
+  start_secs: number;
+  end_secs: number;
+  waits_for: string[];
+}
+
+interface ShoppingItem {
+  name: string;
+  id: number;
+  quantity: number;
+  from_stock: number;
+  source: SourceType;
+  unit_price: number;
+  total_price: number;
+}
+
+interface DetailTarget {
+  output_id: number;
+  quantity: number;
+  characters: number;
+  slots_per_character: number;
+}
+
+interface DetailView {
+  label: string;
+  output_name: string;
+  target: DetailTarget;
+  tree: ReactionTreeNode;
+  schedule: { jobs: ScheduledJob[]; slots: number; total_secs: number; idle_secs: number };
+  shopping_list: { runs: number; items: ShoppingItem[]; total_cost: number };
+}
+
+// Event the backend sends to this window only, when its view has been rebuilt
+const DETAIL_UPDATED_EVENT = "detail://updated";
+
+const formatIsk = (value: number) => {
+  if (Math.abs(value) >= 1_000_000_000) {
+    return (value / 1_000_000_000).toFixed(2) + "B ISK";
+  } else if (Math.abs(value) >= 1_000_000) {
+    return (value / 1_000_000).toFixed(2) + "M ISK";
+  } else if (Math.abs(value) >= 1_000) {
+    return (value / 1_000).toFixed(2) + "K ISK";
+  }
+  return value.toFixed(2) + " ISK";
+};
+
+const formatDuration = (secs: number) => {
+  const hours = Math.floor(secs / 3600);
+  const minutes = Math.floor((secs % 3600) / 60);
+  return hours > 0 ? `${hours}h ${minutes}m` : `${minutes}m`;
+};
+
+// A reaction kept open in its own window: tree, job schedule and shopping list
+function DetailWindow({ label }: { label: string }) {
+  const [view, setView] = useState<DetailView | null>(null);
+  const [errorMessage, setErrorMessage] = useState<string | null>(null);
+
+  useEffect(() => {
+    invoke<DetailView>("get_detail_view", { label })
+      .then(setView)
+      .catch((err) => setErrorMessage(String(err)));
+
+    const unlisten = getCurrentWebviewWindow().listen<DetailView>(DETAIL_UPDATED_EVENT, (event) =>
+      setView(event.payload),
+    );
+    return () => {
+      unlisten.then((stop) => stop());
+    };
+  }, [label]);
+
+  const updateTarget = async (changes: Partial<DetailTarget>) => {
+    if (!view) return;
+    const target = { ...view.target, ...changes };
+    try {
+      setView(
+        await invoke<DetailView>("set_detail_target", {
+          label,
+          quantity: target.quantity,
+          characters: target.characters,
+          slotsPerCharacter: target.slots_per_character,
+        }),
+      );
+      setErrorMessage(null);
+    } catch (err) {
+      setErrorMessage(String(err));
+    }
+  };
+
+  if (!view) {
+    return <div className="detail-window">{errorMessage ?? "Loading..."}</div>;
+  }
+
+  return (
+    <div className="detail-window">
+      <h1>{view.output_name}</h1>
+      {errorMessage && <div className="error-message">{errorMessage}</div>}
+
+      <div className="detail-controls">
+        <label>
+          Quantity
+          <input
+            type="number"
+            min={1}
+            value={view.target.quantity}
+            onChange={(e) => updateTarget({ quantity: Number(e.target.value) })}
+          />
+        </label>
+        <label>
+          Characters
+          <input
+            type="number"
+            min={1}
+            value={view.target.characters}
+            onChange={(e) => updateTarget({ characters: Number(e.target.value) })}
+          />
+        </label>
+        <label>
+          Slots per character
+          <input
+            type="number"
+            min={1}
+            value={view.target.slots_per_character}
+            onChange={(e) => updateTarget({ slots_per_character: Number(e.target.value) })}
+          />
+        </label>
+      </div>
+
+      <div className="details-section tree-section">
+        <div className="zoom-hint">Ctrl + Scroll to zoom</div>
+        <ReactionTreeGraph tree={view.tree} formatIsk={formatIsk} />
+      </div>
+
+      <div className="result-details">
+        <div className="details-section">
+          <div className="details-heading">
+            Schedule ({view.schedule.slots} slots, {formatDuration(view.schedule.total_secs)})
+          </div>
+          <table className="breakdown-table">
+            <thead>
+              <tr>
+                <th>Job</th>
+                <th>Runs</th>
+                <th>Slot</th>
+                <th>Starts</th>
+              </tr>
+            </thead>
+            <tbody>
+              {view.schedule.jobs.map((job, i) => (
+                <tr key={i} title={job.waits_for.length > 0 ? `Waits for ${job.waits_for.join(", ")}` : undefined}>
+                  <td>{job.output_name}</td>
+                  <td>{job.runs.toLocaleString()}</td>
+                  <td>
+                    {job.character}/{job.slot}
+                  </td>
+                  <td>{formatDuration(job.start_secs)}</td>
+                </tr>
+              ))}
+            </tbody>
+          </table>
+        </div>
+
+        <div className="details-section">
+          <div className="details-heading">Shopping list ({view.shopping_list.runs} runs)</div>
+          <table className="breakdown-table">
+            <thead>
+              <tr>
+                <th>Item</th>
+                <th>Quantity</th>
+                <th>In stock</th>
+                <th>Cost</th>
+              </tr>
+            </thead>
+            <tbody>
+              {view.shopping_list.items.map((item) => (
+                <tr key={item.id}>
+                  <td>{item.name}</td>
+                  <td>{item.quantity.toLocaleString()}</td>
+                  <td>{item.from_stock.toLocaleString()}</td>
+                  <td>{formatIsk(item.total_price)}</td>
+                </tr>
+              ))}
+            </tbody>
+            <tfoot>
+              <tr>
+                <td colSpan={3}>Total</td>
+                <td>{formatIsk(view.shopping_list.total_cost)}</td>
+              </tr>
+            </tfoot>
+          </table>
+        </div>
+      </div>
+    </div>
+  );
+}
+
+export default DetailWindow;
@@ -12,12 +12,13 @@ import {
 import dagre from "dagre";
 import "@xyflow/react/dist/style.css";
 
-type SourceType = "moon" | "buy" | "react" | "output";
+type SourceType = "moon" | "buy" | "react" | "output" | "stock";
 
 interface ReactionTreeNode {
   name: string;
   id: number;
   quantity: number;
+  from_stock: number;
   source: SourceType;
   unit_price: number;
   total_price: number;
@@ -37,6 +38,7 @@ function ReactionNode({ data }: { data: { label: string; source: SourceType; qua
     react: { bg: "#d97706", border: "#f59e0b", text: "#fff" },
     moon: { bg: "#059669", border: "#10b981", text: "#fff" },
     buy: { bg: "#dc2626", border: "#ef4444", text: "#fff" },
+    stock: { bg: "#0284c7", border: "#0ea5e9", text: "#fff" },
   };
 
   const sourceLabels: Record<SourceType, string> = {
@@ -44,6 +46,7 @@ function ReactionNode({ data }: { data: { label: string; source: SourceType; qua
     react: "REACT",
     moon: "MOON",
     buy: "BUY",
+    stock: "STOCK",
   };
 
   const colors = sourceColors[data.source];
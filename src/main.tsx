@@ -1,9 +1,13 @@
 import React from "react";
 import ReactDOM from "react-dom/client";
 import App from "./App";
+import DetailWindow from "./DetailWindow";
+
+// Reaction detail windows are opened on the same page with their label in the URL
+const detailLabel = new URLSearchParams(window.location.search).get("detail");
 
 ReactDOM.createRoot(document.getElementById("root") as HTMLElement).render(
   <React.StrictMode>
-    <App />
+    {detailLabel ? <DetailWindow label={detailLabel} /> : <App />}
   </React.StrictMode>,
 );